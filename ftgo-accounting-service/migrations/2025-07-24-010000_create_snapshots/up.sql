@@ -0,0 +1,7 @@
+CREATE TABLE snapshots (
+    stream_name     text        not null primary key references event_stream(name),
+    sequence        bigint      not null,
+    version         int         not null,
+    payload         jsonb       not null,
+    created_at      timestamptz not null default now()
+);
@@ -0,0 +1 @@
+ALTER TABLE account_details DROP COLUMN held_amount;
@@ -0,0 +1 @@
+ALTER TABLE account_details ADD COLUMN held_amount numeric not null default 0;
@@ -0,0 +1,11 @@
+CREATE TABLE account_transactions (
+    account_id          uuid        not null,
+    sequence            bigint      not null,
+    direction           text        not null,
+    amount              numeric     not null,
+    description         text,
+    order_id            uuid,
+    resulting_balance   numeric     not null,
+    created_at          timestamptz not null,
+    primary key (account_id, sequence)
+);
@@ -0,0 +1,7 @@
+INSERT INTO checkpoints (subscription_id, stream_name, sequence, checkpointed_at)
+SELECT 'projector', stream_name, min(sequence), min(checkpointed_at)
+FROM checkpoints
+WHERE subscription_id IN ('projector:account_details', 'projector:account_infos')
+GROUP BY stream_name;
+
+DELETE FROM checkpoints WHERE subscription_id LIKE 'projector:%';
@@ -0,0 +1,8 @@
+-- Each projection now keeps its own checkpoints. Projections fed so far continue from
+-- the shared ones; account_transactions is idempotent and backfills from the start.
+INSERT INTO checkpoints (subscription_id, stream_name, sequence, checkpointed_at)
+SELECT 'projector:' || projection, stream_name, sequence, checkpointed_at
+FROM checkpoints, unnest(ARRAY['account_details', 'account_infos']) AS projection
+WHERE subscription_id = 'projector';
+
+DELETE FROM checkpoints WHERE subscription_id = 'projector';
@@ -0,0 +1,17 @@
+CREATE TABLE checkpoints (
+    subscription_id text        not null,
+    stream_name     text        not null references event_stream(name),
+    sequence        bigint      not null,
+    checkpointed_at timestamptz not null,
+    primary key (subscription_id, stream_name)
+);
+
+INSERT INTO checkpoints (subscription_id, stream_name, sequence, checkpointed_at)
+SELECT s.subscription_id, e.stream_name, max(e.sequence), s.checkpointed_at
+FROM subscriptions s
+JOIN events e ON e.position <= s.position
+GROUP BY s.subscription_id, e.stream_name, s.checkpointed_at;
+
+DROP TABLE subscriptions;
+DROP INDEX ix_events_position;
+ALTER TABLE events DROP COLUMN position;
@@ -0,0 +1,43 @@
+CREATE SEQUENCE events_position_seq MINVALUE 0 START 0;
+
+ALTER TABLE events ADD COLUMN position bigint;
+UPDATE events
+SET position = ordered.position
+FROM (
+    SELECT stream_name, id, row_number() OVER (ORDER BY created_at, stream_name, sequence) - 1 AS position
+    FROM events
+) AS ordered
+WHERE events.stream_name = ordered.stream_name AND events.id = ordered.id;
+SELECT setval('events_position_seq', coalesce(max(position) + 1, 0), false) FROM events;
+
+ALTER TABLE events
+    ALTER COLUMN position SET NOT NULL,
+    ALTER COLUMN position SET DEFAULT nextval('events_position_seq');
+ALTER SEQUENCE events_position_seq OWNED BY events.position;
+CREATE UNIQUE INDEX ix_events_position ON events (position);
+
+CREATE TABLE subscriptions (
+    subscription_id text        not null primary key,
+    position        bigint      not null,
+    checkpointed_at timestamptz not null
+);
+
+-- Continue each subscription from the last position up to which every event was checkpointed
+INSERT INTO subscriptions (subscription_id, position, checkpointed_at)
+SELECT
+    s.subscription_id,
+    coalesce(
+        (
+            SELECT min(e.position) - 1
+            FROM events e
+            LEFT JOIN checkpoints c
+                ON c.subscription_id = s.subscription_id AND c.stream_name = e.stream_name
+            WHERE c.sequence IS NULL OR e.sequence > c.sequence
+        ),
+        (SELECT max(position) FROM events),
+        -1
+    ),
+    now()
+FROM (SELECT DISTINCT subscription_id FROM checkpoints) AS s;
+
+DROP TABLE checkpoints;
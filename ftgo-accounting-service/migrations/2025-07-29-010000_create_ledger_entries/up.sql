@@ -0,0 +1,13 @@
+CREATE TABLE ledger_entries (
+    source_account_id   uuid        not null,
+    source_sequence     bigint      not null,
+    line                integer     not null,
+    account_kind        text        not null,
+    account_id          uuid,
+    direction           text        not null,
+    amount              numeric     not null,
+    order_id            uuid,
+    created_at          timestamptz not null,
+    primary key (source_account_id, source_sequence, line)
+);
+CREATE INDEX ledger_entries_account_idx ON ledger_entries (account_kind, account_id);
@@ -1,28 +1,73 @@
-use std::fmt::Display;
+use std::{collections::HashMap, fmt::Display, sync::LazyLock};
 
-use bigdecimal::{BigDecimal, Zero};
+use bigdecimal::{BigDecimal, RoundingMode, Zero};
 use diesel_async::AsyncPgConnection;
 use ftgo_proto::{
     accounting_service::{
         accounting_event, AccountDeposited, AccountOpened, AccountWithdrawn, AccountingEvent,
+        AuthorizationCaptured, AuthorizationReleased, FundsAuthorized, Payee,
     },
     common::Money,
 };
 use futures::TryStreamExt;
 use kafka::producer::AsBytes;
 use prost::Message;
-use serde_json::json;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use thiserror::Error;
 use uuid::Uuid;
 
-use crate::store::event::{AppendCondition, EventData, EventStore, EventStoreError};
+use crate::{
+    models::Event,
+    store::event::{
+        AppendCondition, EventData, EventMetadata, EventStore, EventStoreError, SnapshotPolicy,
+        UpcasterChain,
+    },
+};
+
+/// Version of `AccountSnapshot`. Bump it whenever the snapshot shape changes;
+/// snapshots of other versions are ignored and the stream is replayed from the start.
+const ACCOUNT_SNAPSHOT_VERSION: i32 = 2;
+
+const DEFAULT_SNAPSHOT_POLICY: SnapshotPolicy = SnapshotPolicy::EveryNEvents(100);
 
 #[derive(Clone)]
 pub struct Account {
     pub id: Uuid,
     pub balance: BigDecimal,
+    /// Amounts held by open authorizations, keyed by authorization id. Held amounts are
+    /// still part of `balance` until they are captured.
+    pub holds: HashMap<String, BigDecimal>,
+}
+
+/// Restaurant an order payment goes to. The platform keeps `commission_rate` of the amount.
+#[derive(Clone, Debug)]
+pub struct OrderPayee {
+    pub restaurant_id: Uuid,
+    pub commission_rate: BigDecimal,
+}
+
+impl OrderPayee {
+    fn for_amount(&self, amount: &BigDecimal) -> Payee {
+        let commission = (amount * &self.commission_rate).with_scale_round(2, RoundingMode::HalfUp);
+        Payee {
+            restaurant_id: self.restaurant_id.to_string(),
+            commission: Some(Money {
+                amount: commission.normalized().to_string(),
+            }),
+        }
+    }
 }
 
 impl Account {
+    pub fn held_balance(&self) -> BigDecimal {
+        self.holds.values().sum()
+    }
+
+    pub fn available_balance(&self) -> BigDecimal {
+        &self.balance - self.held_balance()
+    }
+
     pub fn open(&self) -> Result<AccountingEvent, AccountError> {
         Ok(AccountingEvent {
             event: Some(accounting_event::Event::AccountOpened(AccountOpened {
@@ -35,6 +80,7 @@ impl Account {
         &self,
         amount: BigDecimal,
         description: Option<String>,
+        payee: Option<OrderPayee>,
     ) -> Result<AccountingEvent, AccountError> {
         Ok(AccountingEvent {
             event: Some(accounting_event::Event::AccountDeposited(
@@ -44,6 +90,7 @@ impl Account {
                         amount: amount.to_string(),
                     }),
                     description: description,
+                    payee: payee.map(|payee| payee.for_amount(&amount)),
                 },
             )),
         })
@@ -53,11 +100,13 @@ impl Account {
         &self,
         amount: BigDecimal,
         description: Option<String>,
+        payee: Option<OrderPayee>,
     ) -> Result<AccountingEvent, AccountError> {
-        if amount > self.balance {
+        let available = self.available_balance();
+        if amount > available {
             return Err(AccountError::AccountLimitExceeded {
                 requested: amount,
-                balance: self.balance.clone(),
+                balance: available,
             });
         }
         Ok(AccountingEvent {
@@ -68,6 +117,85 @@ impl Account {
                         amount: amount.to_string(),
                     }),
                     description: description,
+                    payee: payee.map(|payee| payee.for_amount(&amount)),
+                },
+            )),
+        })
+    }
+
+    /// Holds `amount` of the available balance until the authorization is captured or
+    /// released
+    pub fn authorize_funds(
+        &self,
+        authorization_id: String,
+        amount: BigDecimal,
+        description: Option<String>,
+    ) -> Result<AccountingEvent, AccountError> {
+        if self.holds.contains_key(&authorization_id) {
+            return Err(AccountError::AuthorizationAlreadyExists { authorization_id });
+        }
+        let available = self.available_balance();
+        if amount > available {
+            return Err(AccountError::AccountLimitExceeded {
+                requested: amount,
+                balance: available,
+            });
+        }
+        Ok(AccountingEvent {
+            event: Some(accounting_event::Event::FundsAuthorized(FundsAuthorized {
+                id: self.id.to_string(),
+                authorization_id,
+                amount: Some(Money {
+                    amount: amount.to_string(),
+                }),
+                description,
+            })),
+        })
+    }
+
+    /// Withdraws the held amount of the authorization from the balance
+    pub fn capture_authorization(
+        &self,
+        authorization_id: String,
+        payee: Option<OrderPayee>,
+    ) -> Result<AccountingEvent, AccountError> {
+        let amount = self.holds.get(&authorization_id).ok_or_else(|| {
+            AccountError::AuthorizationNotFound {
+                authorization_id: authorization_id.clone(),
+            }
+        })?;
+        Ok(AccountingEvent {
+            event: Some(accounting_event::Event::AuthorizationCaptured(
+                AuthorizationCaptured {
+                    id: self.id.to_string(),
+                    authorization_id,
+                    amount: Some(Money {
+                        amount: amount.to_string(),
+                    }),
+                    payee: payee.map(|payee| payee.for_amount(amount)),
+                },
+            )),
+        })
+    }
+
+    /// Returns the held amount of the authorization to the available balance
+    pub fn release_authorization(
+        &self,
+        authorization_id: String,
+    ) -> Result<AccountingEvent, AccountError> {
+        let amount = self.holds.get(&authorization_id).ok_or_else(|| {
+            AccountError::AuthorizationNotFound {
+                authorization_id: authorization_id.clone(),
+            }
+        })?;
+        Ok(AccountingEvent {
+            event: Some(accounting_event::Event::AuthorizationReleased(
+                AuthorizationReleased {
+                    id: self.id.to_string(),
+                    authorization_id,
+                    amount: Some(Money {
+                        amount: amount.to_string(),
+                    }),
                 },
             )),
         })
@@ -79,37 +207,136 @@ impl Account {
         Self {
             id: id,
             balance: BigDecimal::zero(),
+            holds: HashMap::new(),
         }
     }
 
-    pub fn apply(&self, accounting_event: AccountingEvent) -> Self {
-        match accounting_event.event.unwrap() {
+    pub fn apply(&self, accounting_event: AccountingEvent) -> Result<Self, InvalidAccountEvent> {
+        let event = accounting_event.event.ok_or(InvalidAccountEvent::Empty)?;
+        let event_type = event_type(&event);
+        let amount_of = |amount: Option<Money>| {
+            amount
+                .and_then(|amount| amount.amount.parse::<BigDecimal>().ok())
+                .ok_or(InvalidAccountEvent::InvalidField {
+                    event_type,
+                    field: "amount",
+                })
+        };
+        Ok(match event {
             accounting_event::Event::AccountOpened(event) => Self {
-                id: event.id.parse().expect("Invalid account id"),
+                id: event
+                    .id
+                    .parse()
+                    .map_err(|_| InvalidAccountEvent::InvalidField {
+                        event_type,
+                        field: "id",
+                    })?,
                 balance: BigDecimal::zero(),
+                holds: HashMap::new(),
             },
             accounting_event::Event::AccountDeposited(event) => Self {
                 id: self.id,
-                balance: self.balance.clone()
-                    + event
-                        .amount
-                        .unwrap()
-                        .amount
-                        .parse::<BigDecimal>()
-                        .expect("Invalid amount"),
+                balance: self.balance.clone() + amount_of(event.amount)?,
+                holds: self.holds.clone(),
             },
             accounting_event::Event::AccountWithdrawn(event) => Self {
                 id: self.id,
-                balance: self.balance.clone()
-                    - event
-                        .amount
-                        .unwrap()
-                        .amount
-                        .parse::<BigDecimal>()
-                        .expect("Invalid amount"),
+                balance: self.balance.clone() - amount_of(event.amount)?,
+                holds: self.holds.clone(),
             },
+            accounting_event::Event::FundsAuthorized(event) => {
+                let mut holds = self.holds.clone();
+                holds.insert(event.authorization_id, amount_of(event.amount)?);
+                Self {
+                    id: self.id,
+                    balance: self.balance.clone(),
+                    holds,
+                }
+            }
+            accounting_event::Event::AuthorizationCaptured(event) => {
+                let mut holds = self.holds.clone();
+                holds.remove(&event.authorization_id);
+                Self {
+                    id: self.id,
+                    balance: self.balance.clone() - amount_of(event.amount)?,
+                    holds,
+                }
+            }
+            accounting_event::Event::AuthorizationReleased(event) => {
+                let mut holds = self.holds.clone();
+                holds.remove(&event.authorization_id);
+                Self {
+                    id: self.id,
+                    balance: self.balance.clone(),
+                    holds,
+                }
+            }
             accounting_event::Event::CommandReplyRequested(_) => self.to_owned(),
+        })
+    }
+}
+
+fn event_type(event: &accounting_event::Event) -> &'static str {
+    match event {
+        accounting_event::Event::AccountOpened(_) => "AccountOpened",
+        accounting_event::Event::AccountDeposited(_) => "AccountDeposited",
+        accounting_event::Event::AccountWithdrawn(_) => "AccountWithdrawn",
+        accounting_event::Event::FundsAuthorized(_) => "FundsAuthorized",
+        accounting_event::Event::AuthorizationCaptured(_) => "AuthorizationCaptured",
+        accounting_event::Event::AuthorizationReleased(_) => "AuthorizationReleased",
+        accounting_event::Event::CommandReplyRequested(_) => "CommandReplyRequested",
+    }
+}
+
+/// Upcasters of the events in `Account-` streams. Register one with its payload migration
+/// whenever the encoding of an event changes, and keep `apply` on the latest shape.
+static UPCASTERS: LazyLock<UpcasterChain> = LazyLock::new(UpcasterChain::default);
+
+/// Decodes a stored account event, upcasting its payload to the current schema version
+pub fn decode_event(event: &Event) -> Result<AccountingEvent, EventStoreError> {
+    let payload = UPCASTERS.upcast(event)?;
+    AccountingEvent::decode(payload.as_bytes()).map_err(|err| EventStoreError::InvalidEvent {
+        stream_name: event.stream_name.to_string(),
+        sequence: event.sequence,
+        reason: err.to_string(),
+    })
+}
+
+#[derive(Serialize, Deserialize)]
+struct AccountSnapshot {
+    id: Uuid,
+    balance: String,
+    holds: HashMap<String, String>,
+}
+
+impl Account {
+    fn to_snapshot(&self) -> Value {
+        serde_json::to_value(AccountSnapshot {
+            id: self.id,
+            balance: self.balance.to_string(),
+            holds: self
+                .holds
+                .iter()
+                .map(|(authorization_id, amount)| (authorization_id.clone(), amount.to_string()))
+                .collect(),
+        })
+        .unwrap()
+    }
+
+    fn from_snapshot(version: i32, payload: &Value) -> Option<Self> {
+        if version != ACCOUNT_SNAPSHOT_VERSION {
+            return None;
         }
+        let snapshot = serde_json::from_value::<AccountSnapshot>(payload.clone()).ok()?;
+        Some(Self {
+            id: snapshot.id,
+            balance: snapshot.balance.parse().ok()?,
+            holds: snapshot
+                .holds
+                .into_iter()
+                .map(|(authorization_id, amount)| Some((authorization_id, amount.parse().ok()?)))
+                .collect::<Option<_>>()?,
+        })
     }
 }
 
@@ -118,6 +345,12 @@ pub enum AccountError {
         requested: BigDecimal,
         balance: BigDecimal,
     },
+    AuthorizationAlreadyExists {
+        authorization_id: String,
+    },
+    AuthorizationNotFound {
+        authorization_id: String,
+    },
 }
 
 impl Display for AccountError {
@@ -127,12 +360,31 @@ impl Display for AccountError {
                 "Requested amount {} is greater than balance {}",
                 requested, balance
             )),
+            AccountError::AuthorizationAlreadyExists { authorization_id } => f.write_fmt(
+                format_args!("Authorization {} already exists", authorization_id),
+            ),
+            AccountError::AuthorizationNotFound { authorization_id } => {
+                f.write_fmt(format_args!("Authorization {} not found", authorization_id))
+            }
         }
     }
 }
 
+/// Account event that cannot be applied to the aggregate
+#[derive(Error, Debug)]
+pub enum InvalidAccountEvent {
+    #[error("event is empty")]
+    Empty,
+    #[error("{event_type} has invalid {field}")]
+    InvalidField {
+        event_type: &'static str,
+        field: &'static str,
+    },
+}
+
 pub struct AccountStore<'a> {
     conn: &'a mut AsyncPgConnection,
+    snapshot_policy: SnapshotPolicy,
 }
 
 impl<'a> AccountStore<'a> {
@@ -149,14 +401,11 @@ impl<'a> AccountStore<'a> {
                 events
                     .iter()
                     .map(|(event_id, accounting_event)| {
-                        let event_type = match accounting_event.event.as_ref().unwrap() {
-                            accounting_event::Event::AccountOpened(_) => "AccountOpened",
-                            accounting_event::Event::AccountDeposited(_) => "AccountDeposited",
-                            accounting_event::Event::AccountWithdrawn(_) => "AccountWithdrawn",
-                            accounting_event::Event::CommandReplyRequested(_) => {
-                                "CommandReplyRequested"
-                            }
-                        };
+                        let event_type = accounting_event
+                            .event
+                            .as_ref()
+                            .map(event_type)
+                            .expect("Appended event is empty");
                         let event = EventData {
                             payload: {
                                 let mut buf = Vec::new();
@@ -164,7 +413,11 @@ impl<'a> AccountStore<'a> {
                                 buf
                             }
                             .into(),
-                            metadata: json!({"event_type": event_type}),
+                            metadata: serde_json::to_value(EventMetadata {
+                                event_type: event_type.to_string(),
+                                schema_version: UPCASTERS.current_version(event_type),
+                            })
+                            .unwrap(),
                         };
                         (event_id.as_ref(), event)
                     })
@@ -175,25 +428,205 @@ impl<'a> AccountStore<'a> {
         Ok(())
     }
 
+    pub async fn find_event(
+        &mut self,
+        id: &Uuid,
+        event_id: &Uuid,
+    ) -> Result<Option<AccountingEvent>, EventStoreError> {
+        let stream_id = format!("Account-{}", id);
+        let mut client = EventStore::new(&stream_id, self.conn);
+        client
+            .find_event(event_id)
+            .await?
+            .map(|event| decode_event(&event))
+            .transpose()
+    }
+
+    /// Loads the account from its latest snapshot and the events appended after it
     pub async fn get(&mut self, id: &Uuid) -> Result<(Account, i64), EventStoreError> {
         let stream_id = format!("Account-{}", id);
         let mut client = EventStore::new(&stream_id, self.conn);
-        let stream = client.read_stream().await?;
-        let account = stream
-            .try_fold(
-                (Account::new(id.clone()), -1),
-                async |(account, _), event| {
-                    let sequence = event.sequence;
-                    let event = AccountingEvent::decode(event.payload.as_bytes())
-                        .expect("Invalid accounting event");
-                    Ok((account.apply(event), sequence))
-                },
-            )
+        let snapshot = client.load_snapshot().await?.and_then(|snapshot| {
+            Account::from_snapshot(snapshot.version, &snapshot.payload)
+                .map(|account| (account, snapshot.sequence))
+        });
+        let snapshot_sequence = snapshot.as_ref().map(|(_, sequence)| *sequence);
+        let initial = snapshot.unwrap_or_else(|| (Account::new(*id), -1));
+
+        let stream = client.read_stream_after(initial.1).await?;
+        let (account, last_sequence) = stream
+            .map_err(EventStoreError::UnexpectedInternal)
+            .try_fold(initial, async |(account, _), event| {
+                let account = account.apply(decode_event(&event)?).map_err(|err| {
+                    EventStoreError::InvalidEvent {
+                        stream_name: event.stream_name.to_string(),
+                        sequence: event.sequence,
+                        reason: err.to_string(),
+                    }
+                })?;
+                Ok((account, event.sequence))
+            })
             .await?;
-        Ok(account)
+
+        if self
+            .snapshot_policy
+            .should_snapshot(snapshot_sequence, last_sequence)
+        {
+            // Losing a snapshot only costs a longer replay next time
+            if let Err(err) = client
+                .save_snapshot(
+                    last_sequence,
+                    ACCOUNT_SNAPSHOT_VERSION,
+                    account.to_snapshot(),
+                )
+                .await
+            {
+                eprintln!("Failed to save snapshot of {}: {:?}", stream_id, err);
+            }
+        }
+        Ok((account, last_sequence))
     }
 
     pub fn new(conn: &'a mut AsyncPgConnection) -> Self {
-        Self { conn }
+        Self {
+            conn,
+            snapshot_policy: DEFAULT_SNAPSHOT_POLICY,
+        }
+    }
+
+    pub fn with_snapshot_policy(self, snapshot_policy: SnapshotPolicy) -> Self {
+        Self {
+            snapshot_policy,
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::establish_connection;
+    use diesel_async::AsyncConnection;
+
+    // Every test runs in a transaction that is never committed
+    async fn setup_connection() -> AsyncPgConnection {
+        let mut conn = establish_connection().await;
+        conn.begin_test_transaction().await.unwrap();
+        conn
+    }
+
+    fn decided(event: Result<AccountingEvent, AccountError>) -> AccountingEvent {
+        event.unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    fn amount(amount: &str) -> BigDecimal {
+        amount.parse().unwrap()
+    }
+
+    /// Opens an account with a deposit of 10, stored at sequences 0 and 1
+    async fn open_account(store: &mut AccountStore<'_>) -> Uuid {
+        let id = Uuid::new_v4();
+        let account = Account::new(id);
+        let events = vec![
+            (None, decided(account.open())),
+            (None, decided(account.deposit(amount("10"), None, None))),
+        ];
+        store
+            .append(&id, &events, Some(AppendCondition::NoStream))
+            .await
+            .unwrap();
+        id
+    }
+
+    async fn deposit(store: &mut AccountStore<'_>, id: &Uuid, value: &str) {
+        let event = decided(Account::new(*id).deposit(amount(value), None, None));
+        store.append(id, &vec![(None, event)], None).await.unwrap();
+    }
+
+    #[test]
+    fn test_snapshot_round_trip() {
+        let mut account = Account::new(Uuid::new_v4());
+        account.balance = amount("12.50");
+        account.holds.insert("auth-1".to_string(), amount("3.25"));
+
+        let restored =
+            Account::from_snapshot(ACCOUNT_SNAPSHOT_VERSION, &account.to_snapshot()).unwrap();
+        assert_eq!(restored.id, account.id);
+        assert_eq!(restored.balance, account.balance);
+        assert_eq!(restored.holds, account.holds);
+    }
+
+    #[test]
+    fn test_snapshot_of_other_version_is_ignored() {
+        let snapshot = Account::new(Uuid::new_v4()).to_snapshot();
+        assert!(Account::from_snapshot(ACCOUNT_SNAPSHOT_VERSION - 1, &snapshot).is_none());
+        assert!(Account::from_snapshot(ACCOUNT_SNAPSHOT_VERSION + 1, &snapshot).is_none());
+    }
+
+    #[tokio::test]
+    async fn test_get_replays_only_events_after_snapshot() {
+        let conn = &mut setup_connection().await;
+        let mut store = AccountStore::new(conn).with_snapshot_policy(SnapshotPolicy::Never);
+        let id = open_account(&mut store).await;
+
+        // A snapshot that disagrees with the stream shows which events were replayed
+        let mut snapshotted = Account::new(id);
+        snapshotted.balance = amount("100");
+        EventStore::new(&format!("Account-{}", id), store.conn)
+            .save_snapshot(1, ACCOUNT_SNAPSHOT_VERSION, snapshotted.to_snapshot())
+            .await
+            .unwrap();
+        deposit(&mut store, &id, "5").await;
+
+        let (account, last_sequence) = store.get(&id).await.unwrap();
+        assert_eq!(last_sequence, 2);
+        assert_eq!(account.balance, amount("105"));
+    }
+
+    #[tokio::test]
+    async fn test_get_replays_whole_stream_for_outdated_snapshot() {
+        let conn = &mut setup_connection().await;
+        let mut store = AccountStore::new(conn).with_snapshot_policy(SnapshotPolicy::Never);
+        let id = open_account(&mut store).await;
+
+        let mut snapshotted = Account::new(id);
+        snapshotted.balance = amount("100");
+        EventStore::new(&format!("Account-{}", id), store.conn)
+            .save_snapshot(1, ACCOUNT_SNAPSHOT_VERSION - 1, snapshotted.to_snapshot())
+            .await
+            .unwrap();
+        deposit(&mut store, &id, "5").await;
+
+        let (account, last_sequence) = store.get(&id).await.unwrap();
+        assert_eq!(last_sequence, 2);
+        assert_eq!(account.balance, amount("15"));
+    }
+
+    #[tokio::test]
+    async fn test_get_saves_snapshot_by_policy() {
+        let conn = &mut setup_connection().await;
+        let mut store =
+            AccountStore::new(conn).with_snapshot_policy(SnapshotPolicy::EveryNEvents(3));
+        let id = open_account(&mut store).await;
+        let stream_name = format!("Account-{}", id);
+
+        store.get(&id).await.unwrap();
+        let snapshot = EventStore::new(&stream_name, store.conn)
+            .load_snapshot()
+            .await
+            .unwrap();
+        assert_eq!(snapshot, None);
+
+        deposit(&mut store, &id, "5").await;
+        store.get(&id).await.unwrap();
+        let snapshot = EventStore::new(&stream_name, store.conn)
+            .load_snapshot()
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!(snapshot.sequence, 2);
+        assert_eq!(snapshot.version, ACCOUNT_SNAPSHOT_VERSION);
+        let restored = Account::from_snapshot(snapshot.version, &snapshot.payload).unwrap();
+        assert_eq!(restored.balance, amount("15"));
     }
 }
@@ -1,8 +1,10 @@
-use std::{env, thread::sleep, time::Duration};
+use std::{collections::HashMap, env, thread::sleep, time::Duration};
 
 use dotenvy::dotenv;
 use ftgo_accounting_service::{
-    aggregate::account::AccountStore, establish_connection, service::AccountingService,
+    aggregate::account::{Account, AccountStore},
+    establish_connection,
+    service::{AccountingError, AccountingService, CommandEventId},
     COMMAND_CHANNEL,
 };
 use ftgo_proto::{
@@ -19,6 +21,73 @@ use uuid::Uuid;
 const CONSUMER_EVENT_CHANNEL: &'static str = "consumer.event";
 const GROUP: &'static str = "accounting-service";
 
+/// Namespace of the event ids derived from command headers
+const COMMAND_EVENT_ID_NAMESPACE: Uuid = Uuid::from_u128(0x6f0c2a4e_8d1b_4c57_9e3a_2b7d5f41c9a8);
+
+/// Derives the event id of a command from its headers, so that a redelivered command maps
+/// to the event it already produced. `kind` tells apart commands sent within the same saga
+/// when no `REQUEST-ID` is available.
+///
+/// A saga compensation carries the `COMPENSATED-REQUEST-ID` of the command it undoes, whose
+/// kind is `compensated_kind`. Its event id is derived from that request id instead, so the
+/// command is undone at most once, and only if its event exists.
+fn command_event_id(
+    kind: &str,
+    compensated_kind: Option<&str>,
+    state: &HashMap<String, String>,
+) -> Option<CommandEventId> {
+    if let (Some(compensated_kind), Some(compensated_request_id)) =
+        (compensated_kind, state.get("COMPENSATED-REQUEST-ID"))
+    {
+        return Some(CommandEventId {
+            event_id: derive_event_id(kind, &format!("undo:{}", compensated_request_id)),
+            compensated_event_id: Some(derive_event_id(compensated_kind, compensated_request_id)),
+        });
+    }
+    let key = match (
+        state.get("REQUEST-ID"),
+        state.get("SAGA-TYPE"),
+        state.get("SAGA-ID"),
+    ) {
+        (Some(request_id), _, _) => request_id.to_string(),
+        (None, Some(saga_type), Some(saga_id)) => format!("{}:{}", saga_type, saga_id),
+        _ => return None,
+    };
+    Some(CommandEventId {
+        event_id: derive_event_id(kind, &key),
+        compensated_event_id: None,
+    })
+}
+
+fn derive_event_id(kind: &str, key: &str) -> Uuid {
+    Uuid::new_v5(
+        &COMMAND_EVENT_ID_NAMESPACE,
+        format!("{}:{}", kind, key).as_bytes(),
+    )
+}
+
+fn parse_restaurant_id(restaurant_id: Option<String>) -> Result<Option<Uuid>, ()> {
+    restaurant_id
+        .map(|restaurant_id| restaurant_id.parse::<Uuid>().map_err(|_| ()))
+        .transpose()
+}
+
+/// A command that kept losing append races was replied to with a failure already, so it is
+/// logged instead of stopping the consumer
+fn command_result(result: Result<Account, AccountingError>) -> Result<(), ()> {
+    match result {
+        Ok(_) => Ok(()),
+        Err(AccountingError::ConcurrentModification) => {
+            println!(
+                "Failed to apply command: {}",
+                AccountingError::ConcurrentModification
+            );
+            Ok(())
+        }
+        Err(_) => Err(()),
+    }
+}
+
 enum AcceptedMessage {
     AccountingCommand(AccountingCommand),
     ConsumerEvent(ConsumerEvent),
@@ -49,33 +118,83 @@ impl AcceptedMessage {
                         command,
                     ) => {
                         let account_id = command.id.parse::<Uuid>().map_err(|_| ())?;
-                        let _ = service
-                            .deposit(
-                                account_id,
-                                command.amount.ok_or(())?.amount.parse().map_err(|_| ())?,
-                                command.description,
-                                None,
-                                command_metadata,
-                            )
-                            .await
-                            .map_err(|_| ())?;
-                        Ok(())
+                        command_result(
+                            service
+                                .deposit(
+                                    account_id,
+                                    command.amount.ok_or(())?.amount.parse().map_err(|_| ())?,
+                                    command.description,
+                                    parse_restaurant_id(command.restaurant_id)?,
+                                    command_event_id("deposit", Some("withdraw"), &command_event.state),
+                                    command_metadata,
+                                )
+                                .await,
+                        )
                     }
                     ftgo_proto::accounting_service::accounting_command::Command::Withdraw(
                         command,
                     ) => {
                         let account_id = command.id.parse::<Uuid>().map_err(|_| ())?;
-                        let _ = service
-                            .withdraw(
-                                account_id,
-                                command.amount.ok_or(())?.amount.parse().map_err(|_| ())?,
-                                command.description,
-                                None,
-                                command_metadata,
-                            )
-                            .await
-                            .map_err(|_| ())?;
-                        Ok(())
+                        command_result(
+                            service
+                                .withdraw(
+                                    account_id,
+                                    command.amount.ok_or(())?.amount.parse().map_err(|_| ())?,
+                                    command.description,
+                                    parse_restaurant_id(command.restaurant_id)?,
+                                    command_event_id("withdraw", Some("deposit"), &command_event.state),
+                                    command_metadata,
+                                )
+                                .await,
+                        )
+                    }
+                    ftgo_proto::accounting_service::accounting_command::Command::AuthorizeFunds(
+                        command,
+                    ) => {
+                        let account_id = command.id.parse::<Uuid>().map_err(|_| ())?;
+                        command_result(
+                            service
+                                .authorize_funds(
+                                    account_id,
+                                    command.authorization_id,
+                                    command.amount.ok_or(())?.amount.parse().map_err(|_| ())?,
+                                    command.description,
+                                    command_event_id("authorize", None, &command_event.state),
+                                    command_metadata,
+                                )
+                                .await,
+                        )
+                    }
+                    ftgo_proto::accounting_service::accounting_command::Command::CaptureAuthorization(
+                        command,
+                    ) => {
+                        let account_id = command.id.parse::<Uuid>().map_err(|_| ())?;
+                        command_result(
+                            service
+                                .capture_authorization(
+                                    account_id,
+                                    command.authorization_id,
+                                    parse_restaurant_id(command.restaurant_id)?,
+                                    command_event_id("capture", None, &command_event.state),
+                                    command_metadata,
+                                )
+                                .await,
+                        )
+                    }
+                    ftgo_proto::accounting_service::accounting_command::Command::ReleaseAuthorization(
+                        command,
+                    ) => {
+                        let account_id = command.id.parse::<Uuid>().map_err(|_| ())?;
+                        command_result(
+                            service
+                                .release_authorization(
+                                    account_id,
+                                    command.authorization_id,
+                                    command_event_id("release", Some("authorize"), &command_event.state),
+                                    command_metadata,
+                                )
+                                .await,
+                        )
                     }
                 }
             }
@@ -3,20 +3,24 @@ use std::time::Duration;
 
 use diesel_async::AsyncPgConnection;
 use dotenvy::dotenv;
-use ftgo_accounting_service::establish_connection;
-use ftgo_accounting_service::store::checkpoint::CheckpointStore;
+use ftgo_accounting_service::store::subscription::{
+    EventListener, SubscriptionStore, DEFAULT_POLL_INTERVAL,
+};
+use ftgo_accounting_service::{
+    aggregate::account::decode_event, database_url, establish_connection,
+};
 use ftgo_proto::accounting_service::{accounting_event, AccountingEvent};
 use ftgo_proto::common::CommandReply;
 use futures::TryStreamExt;
 use kafka::client::RequiredAcks;
-use kafka::producer::{AsBytes, Producer, Record};
+use kafka::producer::{Producer, Record};
 use prost::Message;
 
 const ACCOUNTING_EVENT_TOPIC: &str = "accounting.event";
 const CHECKPOINT_NAME: &str = "accounting-producer";
 
 struct EventStoreProducer<'a> {
-    store: CheckpointStore<'a>,
+    store: SubscriptionStore<'a>,
     kafka: Producer,
 }
 
@@ -28,7 +32,7 @@ impl<'a> EventStoreProducer<'a> {
             .with_required_acks(RequiredAcks::One)
             .create()?;
 
-        let store = CheckpointStore::new(CHECKPOINT_NAME, conn);
+        let store = SubscriptionStore::new(CHECKPOINT_NAME, conn);
 
         Ok(Self { store, kafka })
     }
@@ -38,7 +42,7 @@ impl<'a> EventStoreProducer<'a> {
         let mut stream = self.store.retrieve_events_after_checkpoint().await?;
         while let Some(event) = stream.try_next().await? {
             if event.stream_name.starts_with("Account-") {
-                let accounting_event = AccountingEvent::decode(event.payload.as_bytes()).unwrap();
+                let accounting_event = decode_event(&event)?;
 
                 // Handle command reply events separately
                 if let Some(accounting_event::Event::CommandReplyRequested(reply_request)) =
@@ -53,7 +57,7 @@ impl<'a> EventStoreProducer<'a> {
                 }
             }
 
-            self.store.store(&event.stream_name, event.sequence).await?;
+            self.store.checkpoint(event.position).await?;
 
             processed_any = true;
         }
@@ -70,6 +74,9 @@ impl<'a> EventStoreProducer<'a> {
             Some(accounting_event::Event::AccountOpened(_)) => "AccountOpened",
             Some(accounting_event::Event::AccountDeposited(_)) => "AccountDeposited",
             Some(accounting_event::Event::AccountWithdrawn(_)) => "AccountWithdrawn",
+            Some(accounting_event::Event::FundsAuthorized(_)) => "FundsAuthorized",
+            Some(accounting_event::Event::AuthorizationCaptured(_)) => "AuthorizationCaptured",
+            Some(accounting_event::Event::AuthorizationReleased(_)) => "AuthorizationReleased",
             // Skip internal event
             Some(accounting_event::Event::CommandReplyRequested(_)) => {
                 return Ok(());
@@ -125,6 +132,7 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let mut producer = EventStoreProducer::new(conn)
         .await
         .expect("Failed to initiate");
+    let listener = EventListener::spawn(database_url(), DEFAULT_POLL_INTERVAL);
 
     loop {
         match producer.process_events().await.unwrap() {
@@ -132,8 +140,8 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 // Processed some events, continue immediately
             }
             false => {
-                // No events to process, sleep for a bit
-                tokio::time::sleep(Duration::from_secs(1)).await;
+                // No events to process, wait for new ones
+                listener.wait().await;
             }
         }
     }
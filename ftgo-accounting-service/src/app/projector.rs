@@ -1,23 +1,17 @@
 use std::time::Duration;
 
-use diesel_async::AsyncPgConnection;
+use diesel_async::{scoped_futures::ScopedFutureExt, AsyncConnection, AsyncPgConnection};
 use ftgo_accounting_service::{
-    establish_connection,
-    projection::{
-        account_details::AccountDetailsProjection, account_infos::AccountInfosProjection,
-        AccountingProjection,
-    },
-    store::checkpoint::CheckpointStore,
+    aggregate::account::decode_event,
+    database_url, establish_connection,
+    projection::ProjectionName,
+    store::subscription::{EventListener, SubscriptionStore, DEFAULT_POLL_INTERVAL},
 };
-use ftgo_proto::accounting_service::AccountingEvent;
-use futures::TryStreamExt;
-use kafka::producer::AsBytes;
-use prost::Message;
-
-const SUBSCRIPTION_ID: &'static str = "projector";
+use futures::{future::join_all, TryStreamExt};
 
 struct Projector<'a> {
-    store: CheckpointStore<'a>,
+    projection: ProjectionName,
+    store: SubscriptionStore<'a>,
     conn: &'a mut AsyncPgConnection,
 }
 
@@ -27,44 +21,80 @@ impl<'a> Projector<'a> {
         let mut stream = self.store.retrieve_events_after_checkpoint().await?;
         while let Some(event_row) = stream.try_next().await? {
             if event_row.stream_name.starts_with("Account-") {
-                let event = AccountingEvent::decode(event_row.payload.as_bytes())
-                    .expect("Failed to decode accounting event");
-
-                AccountDetailsProjection::new(self.conn)
-                    .process(&event, event_row.sequence)
-                    .await
-                    .expect("Failed to process while AccountDetails projection");
-                AccountInfosProjection::new(self.conn)
-                    .process(&event, event_row.sequence)
-                    .await
-                    .expect("Failed to process while AccountInfosProjection projection");
+                let event = decode_event(&event_row)?;
 
-                self.store
-                    .store(&event_row.stream_name, event_row.sequence)
+                self.projection
+                    .process(self.conn, &event, event_row.sequence, event_row.created_at)
                     .await?;
             }
+            self.store.checkpoint(event_row.position).await?;
             processed += 1;
         }
         Ok(processed)
     }
 
-    pub async fn main(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+    /// Processes events until the projection has caught up with the event store
+    async fn catch_up(&mut self) -> Result<(), Box<dyn std::error::Error>> {
+        while self.process_once().await? > 0 {}
+        Ok(())
+    }
+
+    pub async fn main(&mut self, listener: &EventListener) {
         loop {
-            match self.process_once().await? {
-                0 => tokio::time::sleep(Duration::from_secs(1)).await,
-                _ => {}
+            match self.process_once().await {
+                Ok(0) => listener.wait().await,
+                Ok(_) => {}
+                // Retried from the last checkpoint, other projections keep going meanwhile
+                Err(err) => {
+                    eprintln!("Failed to process {} projection: {}", self.projection, err);
+                    tokio::time::sleep(Duration::from_secs(5)).await;
+                }
             };
         }
     }
 }
 
+async fn run(projection: ProjectionName) {
+    let conn = &mut establish_connection().await;
+    let mut projection_conn = establish_connection().await;
+    let store = SubscriptionStore::new(&projection.subscription_id(), conn);
+    let listener = EventListener::spawn(database_url(), DEFAULT_POLL_INTERVAL);
+    let mut projector = Projector {
+        projection,
+        store,
+        conn: &mut projection_conn,
+    };
+    projector.main(&listener).await
+}
+
 pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    join_all(ProjectionName::ALL.map(run)).await;
+    Ok(())
+}
+
+/// Clears the projection tables along with its subscription and replays every event
+pub async fn rebuild(projection: ProjectionName) -> Result<(), Box<dyn std::error::Error>> {
     let conn = &mut establish_connection().await;
+    conn.transaction::<_, diesel::result::Error, _>(|conn| {
+        async move {
+            projection.clear(conn).await?;
+            SubscriptionStore::new(&projection.subscription_id(), conn)
+                .clear()
+                .await
+        }
+        .scope_boxed()
+    })
+    .await?;
+    println!("Cleared {} projection, replaying events", projection);
+
     let mut projection_conn = establish_connection().await;
-    let store = CheckpointStore::new(SUBSCRIPTION_ID, conn);
+    let store = SubscriptionStore::new(&projection.subscription_id(), conn);
     let mut projector = Projector {
+        projection,
         store,
         conn: &mut projection_conn,
     };
-    projector.main().await
+    projector.catch_up().await?;
+    println!("Rebuilt {} projection", projection);
+    Ok(())
 }
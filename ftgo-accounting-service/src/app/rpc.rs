@@ -2,18 +2,25 @@ use bigdecimal::BigDecimal;
 use diesel_async::{async_connection_wrapper::AsyncConnectionWrapper, AsyncPgConnection};
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use ftgo_accounting_service::{
-    aggregate::account::AccountStore, establish_connection, service::AccountingService,
+    aggregate::account::AccountStore,
+    establish_connection,
+    projection::account_transactions::{self, DIRECTION_CREDIT},
+    service::{AccountingError, AccountingService},
 };
 use ftgo_proto::{
     accounting_service::{
         accounting_service_server::{
             AccountingService as AccountingServiceBase, AccountingServiceServer,
         },
-        AccountDetails, AccountInfo, DepositAccountPayload, GetAccountPayload, ListAccountsPayload,
-        ListAccountsResponse, WithdrawAccountPayload,
+        AccountDetails, AccountInfo, AccountTransaction, AccountTransactionDirection,
+        AccountTransactionEdge, DepositAccountPayload, GetAccountPayload,
+        GetRestaurantBalancePayload, LedgerReconciliation, ListAccountTransactionsPayload,
+        ListAccountTransactionsResponse, ListAccountsPayload, ListAccountsResponse,
+        ReconcileLedgerPayload, RestaurantBalance, WithdrawAccountPayload,
     },
     common::Money,
 };
+use prost_types::Timestamp;
 use std::str::FromStr;
 use tonic::transport::Server;
 use tonic::{Request, Response, Status};
@@ -49,6 +56,9 @@ impl AccountingServiceBase for AccountingServiceImpl {
                 balance: Some(Money {
                     amount: account.amount.to_string(),
                 }),
+                held_balance: Some(Money {
+                    amount: account.held_amount.to_string(),
+                }),
             })),
             None => Err(Status::not_found("Account not found")),
         }
@@ -74,15 +84,21 @@ impl AccountingServiceBase for AccountingServiceImpl {
         let mut service = AccountingService::new(store, projection_conn);
 
         let account = service
-            .deposit(account_id, amount, None, None, None)
+            .deposit(account_id, amount, None, None, None, None)
             .await
-            .map_err(|_| Status::internal("Internal error"))?;
+            .map_err(|err| match err {
+                AccountingError::ConcurrentModification => Status::aborted(err.to_string()),
+                _ => Status::internal("Internal error"),
+            })?;
 
         Ok(Response::new(AccountDetails {
             id: account.id.to_string(),
             balance: Some(Money {
                 amount: account.balance.to_string(),
             }),
+            held_balance: Some(Money {
+                amount: account.held_balance().to_string(),
+            }),
         }))
     }
 
@@ -106,15 +122,21 @@ impl AccountingServiceBase for AccountingServiceImpl {
         let mut service = AccountingService::new(store, projection_conn);
 
         let account = service
-            .withdraw(account_id, amount, None, None, None)
+            .withdraw(account_id, amount, None, None, None, None)
             .await
-            .map_err(|_| Status::internal("Internal error"))?;
+            .map_err(|err| match err {
+                AccountingError::ConcurrentModification => Status::aborted(err.to_string()),
+                _ => Status::internal("Internal error"),
+            })?;
 
         Ok(Response::new(AccountDetails {
             id: account.id.to_string(),
             balance: Some(Money {
                 amount: account.balance.to_string(),
             }),
+            held_balance: Some(Money {
+                amount: account.held_balance().to_string(),
+            }),
         }))
     }
 
@@ -151,6 +173,128 @@ impl AccountingServiceBase for AccountingServiceImpl {
                 .collect(),
         }))
     }
+
+    async fn list_account_transactions(
+        &self,
+        request: Request<ListAccountTransactionsPayload>,
+    ) -> Result<Response<ListAccountTransactionsResponse>, Status> {
+        let payload = request.into_inner();
+        let account_id = Uuid::from_str(&payload.account_id)
+            .map_err(|_| Status::invalid_argument("Invalid account_id"))?;
+        let after = payload
+            .after
+            .map(|s| s.parse::<i64>())
+            .transpose()
+            .map_err(|_| Status::invalid_argument("Invalid after"))?;
+        let before = payload
+            .before
+            .map(|s| s.parse::<i64>())
+            .transpose()
+            .map_err(|_| Status::invalid_argument("Invalid before"))?;
+
+        let conn = &mut establish_connection().await;
+        let projection_conn = &mut establish_connection().await;
+        let store = AccountStore::new(conn);
+        let mut service = AccountingService::new(store, projection_conn);
+
+        let transactions = service
+            .list_account_transactions(&account_id, payload.first, after, payload.last, before)
+            .await
+            .map_err(|err| match err {
+                AccountingError::InvalidPagination(message) => Status::invalid_argument(message),
+                _ => Status::internal("Internal error"),
+            })?;
+
+        Ok(Response::new(ListAccountTransactionsResponse {
+            edges: transactions
+                .iter()
+                .map(|t| AccountTransactionEdge {
+                    node: Some(serialize_transaction(t)),
+                    cursor: t.sequence.to_string(),
+                })
+                .collect(),
+        }))
+    }
+
+    async fn get_restaurant_balance(
+        &self,
+        request: Request<GetRestaurantBalancePayload>,
+    ) -> Result<Response<RestaurantBalance>, Status> {
+        let payload = request.into_inner();
+        let restaurant_id = Uuid::from_str(&payload.restaurant_id)
+            .map_err(|_| Status::invalid_argument("Invalid restaurant_id"))?;
+
+        let conn = &mut establish_connection().await;
+        let projection_conn = &mut establish_connection().await;
+        let store = AccountStore::new(conn);
+        let mut service = AccountingService::new(store, projection_conn);
+
+        let balance = service
+            .get_restaurant_balance(&restaurant_id)
+            .await
+            .map_err(|_| Status::internal("Internal error"))?;
+        Ok(Response::new(RestaurantBalance {
+            restaurant_id: restaurant_id.to_string(),
+            balance: Some(Money {
+                amount: balance.to_string(),
+            }),
+        }))
+    }
+
+    async fn reconcile_ledger(
+        &self,
+        _request: Request<ReconcileLedgerPayload>,
+    ) -> Result<Response<LedgerReconciliation>, Status> {
+        let conn = &mut establish_connection().await;
+        let projection_conn = &mut establish_connection().await;
+        let store = AccountStore::new(conn);
+        let mut service = AccountingService::new(store, projection_conn);
+
+        let reconciliation = service
+            .reconcile_ledger()
+            .await
+            .map_err(|_| Status::internal("Internal error"))?;
+        Ok(Response::new(LedgerReconciliation {
+            total_debits: Some(Money {
+                amount: reconciliation.total_debits.to_string(),
+            }),
+            total_credits: Some(Money {
+                amount: reconciliation.total_credits.to_string(),
+            }),
+            balanced: reconciliation.is_balanced(),
+            unbalanced_transactions: reconciliation
+                .unbalanced_transactions
+                .iter()
+                .map(|t| format!("{}:{}", t.source_account_id, t.source_sequence))
+                .collect(),
+        }))
+    }
+}
+
+fn serialize_transaction(
+    transaction: &account_transactions::AccountTransaction,
+) -> AccountTransaction {
+    AccountTransaction {
+        account_id: transaction.account_id.to_string(),
+        direction: if transaction.direction == DIRECTION_CREDIT {
+            AccountTransactionDirection::Credit
+        } else {
+            AccountTransactionDirection::Debit
+        }
+        .into(),
+        amount: Some(Money {
+            amount: transaction.amount.to_string(),
+        }),
+        description: transaction.description.clone(),
+        order_id: transaction.order_id.map(|id| id.to_string()),
+        resulting_balance: Some(Money {
+            amount: transaction.resulting_balance.to_string(),
+        }),
+        created_at: Some(Timestamp {
+            seconds: transaction.created_at.timestamp(),
+            nanos: transaction.created_at.timestamp_subsec_nanos() as i32,
+        }),
+    }
 }
 
 pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
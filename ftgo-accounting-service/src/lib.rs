@@ -1,5 +1,6 @@
 use std::env;
 
+use bigdecimal::BigDecimal;
 use diesel_async::{AsyncConnection, AsyncPgConnection};
 use dotenvy::dotenv;
 
@@ -13,9 +14,24 @@ pub mod store;
 pub const EVENT_CHANNEL: &str = "accounting.event";
 pub const COMMAND_CHANNEL: &str = "accounting.command";
 
-pub async fn establish_connection() -> AsyncPgConnection {
+pub fn database_url() -> String {
+    dotenv().ok();
+
+    env::var("DATABASE_URL").expect("DATABASE_URL must be set")
+}
+
+/// Share of order payments kept by the platform, `PLATFORM_COMMISSION_RATE` or 10%
+pub fn platform_commission_rate() -> BigDecimal {
     dotenv().ok();
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    AsyncPgConnection::establish(&database_url).await.unwrap()
+    env::var("PLATFORM_COMMISSION_RATE")
+        .map(|rate| {
+            rate.parse()
+                .expect("PLATFORM_COMMISSION_RATE must be a decimal")
+        })
+        .unwrap_or_else(|_| "0.1".parse().unwrap())
+}
+
+pub async fn establish_connection() -> AsyncPgConnection {
+    AsyncPgConnection::establish(&database_url()).await.unwrap()
 }
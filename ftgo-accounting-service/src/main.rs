@@ -1,4 +1,5 @@
 use clap::{Parser, Subcommand};
+use ftgo_accounting_service::projection::ProjectionName;
 
 pub mod app;
 
@@ -14,7 +15,19 @@ enum Commands {
     RPC,
     Consumer,
     Producer,
-    Projector,
+    Projector {
+        #[command(subcommand)]
+        command: Option<ProjectorCommands>,
+    },
+}
+
+#[derive(Subcommand)]
+enum ProjectorCommands {
+    /// Clears the projection and replays it from the first event
+    Rebuild {
+        #[arg(long)]
+        projection: ProjectionName,
+    },
 }
 
 #[tokio::main]
@@ -24,6 +37,9 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
         Commands::RPC => app::rpc::main().await,
         Commands::Consumer => app::consumer::main().await,
         Commands::Producer => app::producer::main().await,
-        Commands::Projector => app::projector::main().await,
+        Commands::Projector { command: None } => app::projector::main().await,
+        Commands::Projector {
+            command: Some(ProjectorCommands::Rebuild { projection }),
+        } => app::projector::rebuild(*projection).await,
     }
 }
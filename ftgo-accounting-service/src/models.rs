@@ -4,7 +4,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use uuid::Uuid;
 
-use crate::schema::{checkpoints, event_stream, events};
+use crate::schema::{event_stream, events, snapshots, subscriptions};
 
 #[derive(Queryable, Selectable, Identifiable, Insertable, AsChangeset, Debug, PartialEq)]
 #[diesel(table_name = event_stream, primary_key(name))]
@@ -22,6 +22,8 @@ pub struct Event {
     pub payload: Vec<u8>,
     pub metadata: Value,
     pub created_at: DateTime<Utc>,
+    /// Position of the event across all streams, in the order events were committed
+    pub position: i64,
 }
 
 #[derive(Insertable, Debug, PartialEq)]
@@ -45,10 +47,28 @@ pub struct NewEvent {
     PartialEq,
     Debug,
 )]
-#[diesel(table_name = checkpoints, primary_key(stream_name, subscription_id))]
-pub struct Checkpoint {
-    pub stream_name: String,
+#[diesel(table_name = subscriptions, primary_key(subscription_id))]
+pub struct Subscription {
     pub subscription_id: String,
-    pub sequence: i64,
+    pub position: i64,
     pub checkpointed_at: DateTime<Utc>,
 }
+
+#[derive(Queryable, Selectable, Identifiable, Debug, PartialEq)]
+#[diesel(table_name = snapshots, primary_key(stream_name))]
+pub struct Snapshot {
+    pub stream_name: String,
+    pub sequence: i64,
+    pub version: i32,
+    pub payload: Value,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Insertable, AsChangeset, Debug, PartialEq)]
+#[diesel(table_name = snapshots)]
+pub struct NewSnapshot {
+    pub stream_name: String,
+    pub sequence: i64,
+    pub version: i32,
+    pub payload: Value,
+}
@@ -6,7 +6,7 @@ use diesel_async::{
 use ftgo_proto::accounting_service::{accounting_event, AccountingEvent};
 use uuid::Uuid;
 
-use super::{AccountingProjection, AccountingProjectionError};
+use super::{parse_account_id, parse_amount, AccountingProjection, AccountingProjectionError};
 
 use crate::schema::account_details;
 
@@ -17,6 +17,7 @@ pub struct AccountDetail {
     pub amount: BigDecimal,
     pub version: i64,
     pub last_processed_sequence: i64,
+    pub held_amount: BigDecimal,
 }
 
 pub struct AccountDetailsProjection<'a> {
@@ -42,9 +43,11 @@ impl AccountingProjection for AccountDetailsProjection<'_> {
                     amount: BigDecimal::zero(),
                     version: sequence,
                     last_processed_sequence: sequence,
+                    held_amount: BigDecimal::zero(),
                 };
                 insert_into(account_details::table)
                     .values(&entity)
+                    .on_conflict_do_nothing()
                     .execute(self.conn)
                     .await?;
                 Ok(())
@@ -135,6 +138,83 @@ impl AccountingProjection for AccountDetailsProjection<'_> {
                     })
                     .await
             }
+            accounting_event::Event::FundsAuthorized(event) => {
+                let aid = parse_account_id("FundsAuthorized", &event.id)?;
+                let amount = parse_amount("FundsAuthorized", &event.amount)?;
+                self.conn
+                    .transaction(|conn| {
+                        async move {
+                            if Self::was_already_applied(conn, &aid, sequence).await? {
+                                return Ok(());
+                            };
+                            update(account_details::table)
+                                .set((
+                                    account_details::held_amount.eq(account_details::held_amount
+                                        + AsExpression::<Numeric>::as_expression(amount)),
+                                    account_details::version.eq(sequence),
+                                    account_details::last_processed_sequence.eq(sequence),
+                                ))
+                                .filter(account_details::id.eq(aid))
+                                .execute(conn)
+                                .await?;
+                            Ok(())
+                        }
+                        .scope_boxed()
+                    })
+                    .await
+            }
+            accounting_event::Event::AuthorizationCaptured(event) => {
+                let aid = parse_account_id("AuthorizationCaptured", &event.id)?;
+                let amount = parse_amount("AuthorizationCaptured", &event.amount)?;
+                self.conn
+                    .transaction(|conn| {
+                        async move {
+                            if Self::was_already_applied(conn, &aid, sequence).await? {
+                                return Ok(());
+                            };
+                            update(account_details::table)
+                                .set((
+                                    account_details::amount.eq(account_details::amount
+                                        - AsExpression::<Numeric>::as_expression(amount.clone())),
+                                    account_details::held_amount.eq(account_details::held_amount
+                                        - AsExpression::<Numeric>::as_expression(amount)),
+                                    account_details::version.eq(sequence),
+                                    account_details::last_processed_sequence.eq(sequence),
+                                ))
+                                .filter(account_details::id.eq(aid))
+                                .execute(conn)
+                                .await?;
+                            Ok(())
+                        }
+                        .scope_boxed()
+                    })
+                    .await
+            }
+            accounting_event::Event::AuthorizationReleased(event) => {
+                let aid = parse_account_id("AuthorizationReleased", &event.id)?;
+                let amount = parse_amount("AuthorizationReleased", &event.amount)?;
+                self.conn
+                    .transaction(|conn| {
+                        async move {
+                            if Self::was_already_applied(conn, &aid, sequence).await? {
+                                return Ok(());
+                            };
+                            update(account_details::table)
+                                .set((
+                                    account_details::held_amount.eq(account_details::held_amount
+                                        - AsExpression::<Numeric>::as_expression(amount)),
+                                    account_details::version.eq(sequence),
+                                    account_details::last_processed_sequence.eq(sequence),
+                                ))
+                                .filter(account_details::id.eq(aid))
+                                .execute(conn)
+                                .await?;
+                            Ok(())
+                        }
+                        .scope_boxed()
+                    })
+                    .await
+            }
             accounting_event::Event::CommandReplyRequested(_) => Ok(()),
         }
     }
@@ -8,7 +8,7 @@ use uuid::Uuid;
 
 use crate::schema::account_infos;
 
-use super::{AccountingProjection, AccountingProjectionError};
+use super::{parse_account_id, parse_amount, AccountingProjection, AccountingProjectionError};
 
 #[derive(Queryable, Selectable, Identifiable, Insertable, Debug, PartialEq)]
 #[diesel(table_name = account_infos)]
@@ -49,6 +49,7 @@ impl AccountingProjection for AccountInfosProjection<'_> {
                 };
                 insert_into(account_infos::table)
                     .values(&entity)
+                    .on_conflict_do_nothing()
                     .execute(self.conn)
                     .await?;
                 Ok(())
@@ -141,7 +142,37 @@ impl AccountingProjection for AccountInfosProjection<'_> {
                     })
                     .await
             }
-            accounting_event::Event::CommandReplyRequested(_) => Ok(()),
+            // Only captured authorizations leave the account
+            accounting_event::Event::AuthorizationCaptured(event) => {
+                let aid = parse_account_id("AuthorizationCaptured", &event.id)?;
+                let amount = parse_amount("AuthorizationCaptured", &event.amount)?;
+                let amount_expr = AsExpression::<Numeric>::as_expression(amount);
+                self.conn
+                    .transaction(|conn| {
+                        async move {
+                            if Self::was_already_applied(conn, &aid, sequence).await? {
+                                return Ok(());
+                            };
+                            update(account_infos::table)
+                                .set((
+                                    account_infos::withdraw_accumulate
+                                        .eq(account_infos::withdraw_accumulate + amount_expr),
+                                    account_infos::withdraw_count
+                                        .eq(account_infos::withdraw_count + 1),
+                                    account_infos::last_processed_sequence.eq(sequence),
+                                ))
+                                .filter(account_infos::id.eq(aid))
+                                .execute(conn)
+                                .await?;
+                            Ok(())
+                        }
+                        .scope_boxed()
+                    })
+                    .await
+            }
+            accounting_event::Event::FundsAuthorized(_)
+            | accounting_event::Event::AuthorizationReleased(_)
+            | accounting_event::Event::CommandReplyRequested(_) => Ok(()),
         }
     }
 }
@@ -0,0 +1,121 @@
+use bigdecimal::{BigDecimal, Zero};
+use chrono::{DateTime, Utc};
+use diesel::{insert_into, prelude::*};
+use diesel_async::{
+    scoped_futures::ScopedFutureExt, AsyncConnection, AsyncPgConnection, RunQueryDsl,
+};
+use ftgo_proto::accounting_service::{accounting_event, AccountingEvent};
+use uuid::Uuid;
+
+use super::{parse_account_id, parse_amount, AccountingProjection, AccountingProjectionError};
+
+use crate::schema::account_transactions;
+
+pub const DIRECTION_CREDIT: &str = "CREDIT";
+pub const DIRECTION_DEBIT: &str = "DEBIT";
+
+#[derive(Queryable, Selectable, Insertable, Debug, PartialEq)]
+#[diesel(table_name = account_transactions)]
+pub struct AccountTransaction {
+    pub account_id: Uuid,
+    pub sequence: i64,
+    pub direction: String,
+    pub amount: BigDecimal,
+    pub description: Option<String>,
+    pub order_id: Option<Uuid>,
+    pub resulting_balance: BigDecimal,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Every movement of an account balance. Authorizations only hold funds, so they show up
+/// once captured.
+pub struct AccountTransactionsProjection<'a> {
+    conn: &'a mut AsyncPgConnection,
+    recorded_at: DateTime<Utc>,
+}
+
+impl AccountingProjection for AccountTransactionsProjection<'_> {
+    async fn process(
+        &mut self,
+        event: &AccountingEvent,
+        sequence: i64,
+    ) -> Result<(), AccountingProjectionError> {
+        let (aid, direction, amount, description, order_id) = match event.event.as_ref().unwrap() {
+            accounting_event::Event::AccountDeposited(event) => (
+                parse_account_id("AccountDeposited", &event.id)?,
+                DIRECTION_CREDIT,
+                parse_amount("AccountDeposited", &event.amount)?,
+                event.description.clone(),
+                event.description.as_deref().and_then(order_id_of),
+            ),
+            accounting_event::Event::AccountWithdrawn(event) => (
+                parse_account_id("AccountWithdrawn", &event.id)?,
+                DIRECTION_DEBIT,
+                parse_amount("AccountWithdrawn", &event.amount)?,
+                event.description.clone(),
+                event.description.as_deref().and_then(order_id_of),
+            ),
+            accounting_event::Event::AuthorizationCaptured(event) => (
+                parse_account_id("AuthorizationCaptured", &event.id)?,
+                DIRECTION_DEBIT,
+                parse_amount("AuthorizationCaptured", &event.amount)?,
+                None,
+                event.authorization_id.parse().ok(),
+            ),
+            accounting_event::Event::AccountOpened(_)
+            | accounting_event::Event::FundsAuthorized(_)
+            | accounting_event::Event::AuthorizationReleased(_)
+            | accounting_event::Event::CommandReplyRequested(_) => return Ok(()),
+        };
+        let recorded_at = self.recorded_at;
+
+        self.conn
+            .transaction(|conn| {
+                async move {
+                    let previous_balance = account_transactions::table
+                        .select(account_transactions::resulting_balance)
+                        .filter(account_transactions::account_id.eq(aid))
+                        .filter(account_transactions::sequence.lt(sequence))
+                        .order_by(account_transactions::sequence.desc())
+                        .first::<BigDecimal>(conn)
+                        .await
+                        .optional()?
+                        .unwrap_or_else(BigDecimal::zero);
+                    let resulting_balance = if direction == DIRECTION_CREDIT {
+                        previous_balance + &amount
+                    } else {
+                        previous_balance - &amount
+                    };
+                    insert_into(account_transactions::table)
+                        .values(AccountTransaction {
+                            account_id: aid,
+                            sequence,
+                            direction: direction.to_string(),
+                            amount,
+                            description,
+                            order_id,
+                            resulting_balance,
+                            created_at: recorded_at,
+                        })
+                        .on_conflict_do_nothing()
+                        .execute(conn)
+                        .await?;
+                    Ok(())
+                }
+                .scope_boxed()
+            })
+            .await
+    }
+}
+
+/// Order services describe their account commands as `Order {order_id}`
+pub(super) fn order_id_of(description: &str) -> Option<Uuid> {
+    description.strip_prefix("Order ")?.parse().ok()
+}
+
+impl<'a> AccountTransactionsProjection<'a> {
+    /// `recorded_at` is the time the processed event was appended
+    pub fn new(conn: &'a mut AsyncPgConnection, recorded_at: DateTime<Utc>) -> Self {
+        Self { conn, recorded_at }
+    }
+}
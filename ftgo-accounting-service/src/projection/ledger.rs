@@ -0,0 +1,238 @@
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use diesel::{insert_into, prelude::*};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use ftgo_proto::accounting_service::{accounting_event, AccountingEvent, Payee};
+use uuid::Uuid;
+
+use super::{
+    account_transactions::{order_id_of, DIRECTION_CREDIT, DIRECTION_DEBIT},
+    parse_account_id, parse_amount, AccountingProjection, AccountingProjectionError,
+};
+
+use crate::schema::ledger_entries;
+
+pub const ACCOUNT_KIND_CONSUMER: &str = "CONSUMER";
+pub const ACCOUNT_KIND_RESTAURANT: &str = "RESTAURANT";
+pub const ACCOUNT_KIND_PLATFORM: &str = "PLATFORM";
+/// Money entering or leaving the platform, e.g. top-ups and withdrawals of consumers
+pub const ACCOUNT_KIND_EXTERNAL: &str = "EXTERNAL";
+
+#[derive(Queryable, Selectable, Insertable, Debug, PartialEq)]
+#[diesel(table_name = ledger_entries)]
+pub struct LedgerEntry {
+    pub source_account_id: Uuid,
+    pub source_sequence: i64,
+    pub line: i32,
+    pub account_kind: String,
+    pub account_id: Option<Uuid>,
+    pub direction: String,
+    pub amount: BigDecimal,
+    pub order_id: Option<Uuid>,
+    pub created_at: DateTime<Utc>,
+}
+
+/// Ledger transaction, the entries recorded for one event, whose debits and credits differ
+#[derive(QueryableByName, Debug, PartialEq)]
+pub struct UnbalancedTransaction {
+    #[diesel(sql_type = diesel::sql_types::Uuid)]
+    pub source_account_id: Uuid,
+    #[diesel(sql_type = diesel::sql_types::BigInt)]
+    pub source_sequence: i64,
+}
+
+/// Double-entry ledger of the balance movements. Each event becomes a transaction of entries
+/// whose debits and credits sum to the same amount. Order payments are credited to the
+/// restaurant minus the platform commission, refunds reverse them.
+pub struct LedgerProjection<'a> {
+    conn: &'a mut AsyncPgConnection,
+    recorded_at: DateTime<Utc>,
+}
+
+impl AccountingProjection for LedgerProjection<'_> {
+    async fn process(
+        &mut self,
+        event: &AccountingEvent,
+        sequence: i64,
+    ) -> Result<(), AccountingProjectionError> {
+        let (aid, consumer_direction, amount, payee, order_id) = match event.event.as_ref().unwrap()
+        {
+            accounting_event::Event::AccountDeposited(event) => (
+                parse_account_id("AccountDeposited", &event.id)?,
+                DIRECTION_CREDIT,
+                parse_amount("AccountDeposited", &event.amount)?,
+                parse_payee("AccountDeposited", &event.payee)?,
+                event.description.as_deref().and_then(order_id_of),
+            ),
+            accounting_event::Event::AccountWithdrawn(event) => (
+                parse_account_id("AccountWithdrawn", &event.id)?,
+                DIRECTION_DEBIT,
+                parse_amount("AccountWithdrawn", &event.amount)?,
+                parse_payee("AccountWithdrawn", &event.payee)?,
+                event.description.as_deref().and_then(order_id_of),
+            ),
+            accounting_event::Event::AuthorizationCaptured(event) => (
+                parse_account_id("AuthorizationCaptured", &event.id)?,
+                DIRECTION_DEBIT,
+                parse_amount("AuthorizationCaptured", &event.amount)?,
+                parse_payee("AuthorizationCaptured", &event.payee)?,
+                event.authorization_id.parse().ok(),
+            ),
+            accounting_event::Event::AccountOpened(_)
+            | accounting_event::Event::FundsAuthorized(_)
+            | accounting_event::Event::AuthorizationReleased(_)
+            | accounting_event::Event::CommandReplyRequested(_) => return Ok(()),
+        };
+        let counter_direction = if consumer_direction == DIRECTION_CREDIT {
+            DIRECTION_DEBIT
+        } else {
+            DIRECTION_CREDIT
+        };
+
+        let mut lines = vec![(
+            ACCOUNT_KIND_CONSUMER,
+            Some(aid),
+            consumer_direction,
+            amount.clone(),
+        )];
+        match payee {
+            Some((restaurant_id, commission)) => {
+                lines.push((
+                    ACCOUNT_KIND_RESTAURANT,
+                    Some(restaurant_id),
+                    counter_direction,
+                    &amount - &commission,
+                ));
+                lines.push((ACCOUNT_KIND_PLATFORM, None, counter_direction, commission));
+            }
+            None => lines.push((ACCOUNT_KIND_EXTERNAL, None, counter_direction, amount)),
+        }
+
+        let entries = lines
+            .into_iter()
+            .enumerate()
+            .map(
+                |(line, (account_kind, account_id, direction, amount))| LedgerEntry {
+                    source_account_id: aid,
+                    source_sequence: sequence,
+                    line: line as i32,
+                    account_kind: account_kind.to_string(),
+                    account_id,
+                    direction: direction.to_string(),
+                    amount,
+                    order_id,
+                    created_at: self.recorded_at,
+                },
+            )
+            .collect::<Vec<_>>();
+        insert_into(ledger_entries::table)
+            .values(entries)
+            .on_conflict_do_nothing()
+            .execute(self.conn)
+            .await?;
+        Ok(())
+    }
+}
+
+fn parse_payee(
+    type_: &str,
+    payee: &Option<Payee>,
+) -> Result<Option<(Uuid, BigDecimal)>, AccountingProjectionError> {
+    let Some(payee) = payee else {
+        return Ok(None);
+    };
+    let restaurant_id =
+        payee
+            .restaurant_id
+            .parse()
+            .map_err(|_| AccountingProjectionError::InvalidEvent {
+                type_: type_.to_string(),
+                key: "payee.restaurant_id".to_string(),
+            })?;
+    let commission = parse_amount(type_, &payee.commission)?;
+    Ok(Some((restaurant_id, commission)))
+}
+
+impl<'a> LedgerProjection<'a> {
+    /// `recorded_at` is the time the processed event was appended
+    pub fn new(conn: &'a mut AsyncPgConnection, recorded_at: DateTime<Utc>) -> Self {
+        Self { conn, recorded_at }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::aggregate::account::{Account, AccountError, OrderPayee};
+    use crate::establish_connection;
+    use diesel_async::AsyncConnection;
+    use std::collections::HashMap;
+
+    // Every test runs in a transaction that is never committed
+    async fn setup_connection() -> AsyncPgConnection {
+        let mut conn = establish_connection().await;
+        conn.begin_test_transaction().await.unwrap();
+        conn
+    }
+
+    fn decided(event: Result<AccountingEvent, AccountError>) -> AccountingEvent {
+        event.unwrap_or_else(|err| panic!("{}", err))
+    }
+
+    fn amount(amount: &str) -> BigDecimal {
+        amount.parse().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_every_transaction_balances_to_zero() {
+        let conn = &mut setup_connection().await;
+        let aid = Uuid::new_v4();
+        let payee = OrderPayee {
+            restaurant_id: Uuid::new_v4(),
+            commission_rate: amount("0.1"),
+        };
+        let mut account = Account::new(aid);
+        account.balance = amount("100");
+        account
+            .holds
+            .insert(Uuid::new_v4().to_string(), amount("10.05"));
+        let authorization_id = account.holds.keys().next().unwrap().clone();
+
+        let events = [
+            // Top-up from outside the platform
+            decided(account.deposit(amount("100"), None, None)),
+            // Order payment, whose commission has to be rounded
+            decided(account.capture_authorization(authorization_id, Some(payee.clone()))),
+            // Refund of an order payment
+            decided(account.deposit(amount("3.33"), None, Some(payee.clone()))),
+            decided(account.withdraw(amount("7.77"), None, Some(payee))),
+            decided(account.withdraw(amount("20"), None, None)),
+        ];
+        for (sequence, event) in events.iter().enumerate() {
+            LedgerProjection::new(conn, Utc::now())
+                .process(event, sequence as i64)
+                .await
+                .unwrap();
+        }
+
+        let entries = ledger_entries::table
+            .select(LedgerEntry::as_select())
+            .filter(ledger_entries::source_account_id.eq(aid))
+            .load::<LedgerEntry>(conn)
+            .await
+            .unwrap();
+        let mut transactions: HashMap<i64, BigDecimal> = HashMap::new();
+        for entry in entries {
+            let signed = if entry.direction == DIRECTION_DEBIT {
+                entry.amount
+            } else {
+                -entry.amount
+            };
+            *transactions.entry(entry.source_sequence).or_default() += signed;
+        }
+        assert_eq!(transactions.len(), events.len());
+        for (sequence, sum) in transactions {
+            assert_eq!(sum, BigDecimal::from(0), "transaction {}", sequence);
+        }
+    }
+}
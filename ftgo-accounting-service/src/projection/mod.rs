@@ -1,8 +1,24 @@
-use ftgo_proto::accounting_service::AccountingEvent;
+use std::{fmt::Display, str::FromStr};
+
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use diesel::delete;
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use ftgo_proto::{accounting_service::AccountingEvent, common::Money};
 use thiserror::Error;
+use uuid::Uuid;
+
+use crate::schema;
+
+use self::{
+    account_details::AccountDetailsProjection, account_infos::AccountInfosProjection,
+    account_transactions::AccountTransactionsProjection, ledger::LedgerProjection,
+};
 
 pub mod account_details;
 pub mod account_infos;
+pub mod account_transactions;
+pub mod ledger;
 
 pub trait AccountingProjection {
     fn process(
@@ -12,6 +28,111 @@ pub trait AccountingProjection {
     ) -> impl std::future::Future<Output = Result<(), AccountingProjectionError>> + Send;
 }
 
+/// Projections fed by the projector. Each one reads through its own subscription, so it
+/// can fall behind or be rebuilt without affecting the others.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ProjectionName {
+    AccountDetails,
+    AccountInfos,
+    AccountTransactions,
+    Ledger,
+}
+
+impl ProjectionName {
+    pub const ALL: [ProjectionName; 4] = [
+        ProjectionName::AccountDetails,
+        ProjectionName::AccountInfos,
+        ProjectionName::AccountTransactions,
+        ProjectionName::Ledger,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ProjectionName::AccountDetails => "account_details",
+            ProjectionName::AccountInfos => "account_infos",
+            ProjectionName::AccountTransactions => "account_transactions",
+            ProjectionName::Ledger => "ledger",
+        }
+    }
+
+    pub fn subscription_id(&self) -> String {
+        format!("projector:{}", self.as_str())
+    }
+
+    pub async fn process(
+        &self,
+        conn: &mut AsyncPgConnection,
+        event: &AccountingEvent,
+        sequence: i64,
+        recorded_at: DateTime<Utc>,
+    ) -> Result<(), AccountingProjectionError> {
+        match self {
+            ProjectionName::AccountDetails => {
+                AccountDetailsProjection::new(conn)
+                    .process(event, sequence)
+                    .await
+            }
+            ProjectionName::AccountInfos => {
+                AccountInfosProjection::new(conn)
+                    .process(event, sequence)
+                    .await
+            }
+            ProjectionName::AccountTransactions => {
+                AccountTransactionsProjection::new(conn, recorded_at)
+                    .process(event, sequence)
+                    .await
+            }
+            ProjectionName::Ledger => {
+                LedgerProjection::new(conn, recorded_at)
+                    .process(event, sequence)
+                    .await
+            }
+        }
+    }
+
+    /// Deletes every row of the projection tables
+    pub async fn clear(&self, conn: &mut AsyncPgConnection) -> Result<(), diesel::result::Error> {
+        match self {
+            ProjectionName::AccountDetails => {
+                delete(schema::account_details::table).execute(conn).await?
+            }
+            ProjectionName::AccountInfos => {
+                delete(schema::account_infos::table).execute(conn).await?
+            }
+            ProjectionName::AccountTransactions => {
+                delete(schema::account_transactions::table)
+                    .execute(conn)
+                    .await?
+            }
+            ProjectionName::Ledger => delete(schema::ledger_entries::table).execute(conn).await?,
+        };
+        Ok(())
+    }
+}
+
+impl Display for ProjectionName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ProjectionName {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ProjectionName::ALL
+            .into_iter()
+            .find(|name| name.as_str() == s)
+            .ok_or_else(|| {
+                format!(
+                    "Unknown projection `{}`. Expected one of: {}",
+                    s,
+                    ProjectionName::ALL.map(|name| name.as_str()).join(", ")
+                )
+            })
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum AccountingProjectionError {
     #[error("event {type_} key {key} is not valid")]
@@ -19,3 +140,24 @@ pub enum AccountingProjectionError {
     #[error("error while executing database query")]
     Connection(#[from] diesel::result::Error),
 }
+
+fn parse_account_id(type_: &str, id: &str) -> Result<Uuid, AccountingProjectionError> {
+    id.parse()
+        .map_err(|_| AccountingProjectionError::InvalidEvent {
+            type_: type_.to_string(),
+            key: "id".to_string(),
+        })
+}
+
+fn parse_amount(
+    type_: &str,
+    amount: &Option<Money>,
+) -> Result<BigDecimal, AccountingProjectionError> {
+    amount
+        .as_ref()
+        .and_then(|amount| amount.amount.parse().ok())
+        .ok_or_else(|| AccountingProjectionError::InvalidEvent {
+            type_: type_.to_string(),
+            key: "amount".to_string(),
+        })
+}
@@ -6,6 +6,7 @@ diesel::table! {
         amount -> Numeric,
         version -> Int8,
         last_processed_sequence -> Int8,
+        held_amount -> Numeric,
     }
 }
 
@@ -21,11 +22,15 @@ diesel::table! {
 }
 
 diesel::table! {
-    checkpoints (subscription_id, stream_name) {
-        subscription_id -> Text,
-        stream_name -> Text,
+    account_transactions (account_id, sequence) {
+        account_id -> Uuid,
         sequence -> Int8,
-        checkpointed_at -> Timestamptz,
+        direction -> Text,
+        amount -> Numeric,
+        description -> Nullable<Text>,
+        order_id -> Nullable<Uuid>,
+        resulting_balance -> Numeric,
+        created_at -> Timestamptz,
     }
 }
 
@@ -44,16 +49,52 @@ diesel::table! {
         payload -> Bytea,
         metadata -> Jsonb,
         created_at -> Timestamptz,
+        position -> Int8,
+    }
+}
+
+diesel::table! {
+    ledger_entries (source_account_id, source_sequence, line) {
+        source_account_id -> Uuid,
+        source_sequence -> Int8,
+        line -> Int4,
+        account_kind -> Text,
+        account_id -> Nullable<Uuid>,
+        direction -> Text,
+        amount -> Numeric,
+        order_id -> Nullable<Uuid>,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    snapshots (stream_name) {
+        stream_name -> Text,
+        sequence -> Int8,
+        version -> Int4,
+        payload -> Jsonb,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    subscriptions (subscription_id) {
+        subscription_id -> Text,
+        position -> Int8,
+        checkpointed_at -> Timestamptz,
     }
 }
 
-diesel::joinable!(checkpoints -> event_stream (stream_name));
 diesel::joinable!(events -> event_stream (stream_name));
+diesel::joinable!(snapshots -> event_stream (stream_name));
 
 diesel::allow_tables_to_appear_in_same_query!(
     account_details,
     account_infos,
-    checkpoints,
+    account_transactions,
     event_stream,
     events,
+    ledger_entries,
+    snapshots,
+    subscriptions,
 );
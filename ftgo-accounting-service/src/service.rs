@@ -1,7 +1,11 @@
 use std::collections::HashMap;
 
-use bigdecimal::BigDecimal;
-use diesel::{prelude::*, ExpressionMethods, SelectableHelper};
+use bigdecimal::{BigDecimal, Zero};
+use diesel::{
+    prelude::*,
+    result::{DatabaseErrorKind, Error::DatabaseError},
+    sql_query, ExpressionMethods, SelectableHelper,
+};
 use diesel_async::{AsyncPgConnection, RunQueryDsl};
 use ftgo_proto::{
     accounting_service::{accounting_event, AccountingEvent, CommandReplyRequested},
@@ -11,11 +15,23 @@ use thiserror::Error;
 use uuid::Uuid;
 
 use crate::{
-    aggregate::account::{Account, AccountStore},
-    projection, schema,
+    aggregate::account::{Account, AccountError, AccountStore, OrderPayee},
+    platform_commission_rate, projection, schema,
     store::event::{AppendCondition, EventStoreError},
 };
 
+/// How many times a command is decided again after losing an append race
+const MAX_APPEND_ATTEMPTS: usize = 3;
+
+/// Identifies the event of a command, so that a redelivered command is applied once
+#[derive(Clone, Copy, Debug)]
+pub struct CommandEventId {
+    pub event_id: Uuid,
+    /// Event of the command undone by a compensation. The compensation has nothing to undo
+    /// when that event does not exist.
+    pub compensated_event_id: Option<Uuid>,
+}
+
 pub struct AccountingService<'a> {
     store: AccountStore<'a>,
     projection_conn: &'a mut AsyncPgConnection,
@@ -37,10 +53,10 @@ impl AccountingService<'_> {
             .await
             .map_err(|err| match err {
                 EventStoreError::AppendConditionFailed(_) => AccountingError::AccountAlreadyExists,
-                EventStoreError::UnexpectedInternal(_) => AccountingError::Internal,
+                _ => AccountingError::Internal,
             })?;
 
-        Ok(account.apply(event))
+        account.apply(event).map_err(|_| AccountingError::Internal)
     }
 
     pub async fn deposit(
@@ -48,141 +64,222 @@ impl AccountingService<'_> {
         account_id: Uuid,
         amount: BigDecimal,
         description: Option<String>,
-        event_id: Option<Uuid>,
+        restaurant_id: Option<Uuid>,
+        event_id: Option<CommandEventId>,
         command_metadata: Option<(&str, &HashMap<String, String>)>,
     ) -> Result<Account, AccountingError> {
-        let (account, last_sequence) = self
-            .store
-            .get(&account_id)
-            .await
-            .map_err(|_| AccountingError::Internal)?;
+        let payee = restaurant_id.map(order_payee);
+        self.execute(account_id, event_id, command_metadata, |account| {
+            account.deposit(amount.clone(), description.clone(), payee.clone())
+        })
+        .await
+    }
 
-        let events = match account.deposit(amount, description) {
-            Ok(event) => {
-                let mut events = vec![(event_id, event)];
-                if let Some((reply_channel, state)) = command_metadata {
-                    events.push((
-                        None,
-                        AccountingEvent {
-                            event: Some(accounting_event::Event::CommandReplyRequested(
-                                CommandReplyRequested {
-                                    reply: Some(CommandReply {
-                                        state: state.clone(),
-                                        succeed: true,
-                                        body: None,
-                                    }),
-                                    reply_channel: reply_channel.to_string(),
-                                },
-                            )),
-                        },
-                    ));
-                }
-                events
-            }
-            Err(_) => {
-                if let Some((reply_channel, state)) = command_metadata {
-                    vec![(
-                        None,
-                        AccountingEvent {
-                            event: Some(accounting_event::Event::CommandReplyRequested(
-                                CommandReplyRequested {
-                                    reply: Some(CommandReply {
-                                        state: state.clone(),
-                                        succeed: false,
-                                        body: None,
-                                    }),
-                                    reply_channel: reply_channel.to_string(),
-                                },
-                            )),
-                        },
-                    )]
-                } else {
-                    vec![]
-                }
-            }
-        };
+    pub async fn withdraw(
+        &mut self,
+        account_id: Uuid,
+        amount: BigDecimal,
+        description: Option<String>,
+        restaurant_id: Option<Uuid>,
+        event_id: Option<CommandEventId>,
+        command_metadata: Option<(&str, &HashMap<String, String>)>,
+    ) -> Result<Account, AccountingError> {
+        let payee = restaurant_id.map(order_payee);
+        self.execute(account_id, event_id, command_metadata, |account| {
+            account.withdraw(amount.clone(), description.clone(), payee.clone())
+        })
+        .await
+    }
 
-        let _ = self
-            .store
-            .append(
-                &account_id,
-                &events,
-                Some(AppendCondition::ExpectLastSequence(last_sequence)),
+    pub async fn authorize_funds(
+        &mut self,
+        account_id: Uuid,
+        authorization_id: String,
+        amount: BigDecimal,
+        description: Option<String>,
+        event_id: Option<CommandEventId>,
+        command_metadata: Option<(&str, &HashMap<String, String>)>,
+    ) -> Result<Account, AccountingError> {
+        self.execute(account_id, event_id, command_metadata, |account| {
+            account.authorize_funds(
+                authorization_id.clone(),
+                amount.clone(),
+                description.clone(),
             )
-            .await;
+        })
+        .await
+    }
 
-        Ok(events.into_iter().fold(account, |acc, (_, e)| acc.apply(e)))
+    pub async fn capture_authorization(
+        &mut self,
+        account_id: Uuid,
+        authorization_id: String,
+        restaurant_id: Option<Uuid>,
+        event_id: Option<CommandEventId>,
+        command_metadata: Option<(&str, &HashMap<String, String>)>,
+    ) -> Result<Account, AccountingError> {
+        let payee = restaurant_id.map(order_payee);
+        self.execute(account_id, event_id, command_metadata, |account| {
+            account.capture_authorization(authorization_id.clone(), payee.clone())
+        })
+        .await
     }
 
-    pub async fn withdraw(
+    pub async fn release_authorization(
         &mut self,
         account_id: Uuid,
-        amount: BigDecimal,
-        description: Option<String>,
-        event_id: Option<Uuid>,
+        authorization_id: String,
+        event_id: Option<CommandEventId>,
         command_metadata: Option<(&str, &HashMap<String, String>)>,
     ) -> Result<Account, AccountingError> {
-        let (account, last_sequence) = self
-            .store
-            .get(&account_id)
-            .await
-            .map_err(|_| AccountingError::Internal)?;
+        self.execute(account_id, event_id, command_metadata, |account| {
+            account.release_authorization(authorization_id.clone())
+        })
+        .await
+    }
+
+    /// Runs `command` against the latest state of the account and appends its event along
+    /// with the requested command reply. The account is reloaded and the command decided
+    /// again when another writer appended to the stream in the meantime. A compensation
+    /// whose undone command was never applied is replied to without appending its event.
+    async fn execute(
+        &mut self,
+        account_id: Uuid,
+        event_id: Option<CommandEventId>,
+        command_metadata: Option<(&str, &HashMap<String, String>)>,
+        command: impl Fn(&Account) -> Result<AccountingEvent, AccountError>,
+    ) -> Result<Account, AccountingError> {
+        for attempt in 1..=MAX_APPEND_ATTEMPTS {
+            let (account, last_sequence) = self.load(&account_id).await?;
+            if let Some(event_id) = &event_id {
+                if self.replay_command(&account_id, &event_id.event_id).await? {
+                    return Ok(account);
+                }
+            }
+            let compensates_applied_command =
+                match event_id.and_then(|event_id| event_id.compensated_event_id) {
+                    Some(compensated_event_id) => self
+                        .store
+                        .find_event(&account_id, &compensated_event_id)
+                        .await
+                        .map_err(|_| AccountingError::Internal)?
+                        .is_some(),
+                    None => true,
+                };
 
-        let events = match account.withdraw(amount, description) {
-            Ok(event) => {
-                let mut events = vec![(event_id, event)];
-                if let Some((reply_channel, state)) = command_metadata {
-                    events.push((
-                        None,
-                        AccountingEvent {
-                            event: Some(accounting_event::Event::CommandReplyRequested(
-                                CommandReplyRequested {
-                                    reply: Some(CommandReply {
-                                        state: state.clone(),
-                                        succeed: true,
-                                        body: None,
-                                    }),
-                                    reply_channel: reply_channel.to_string(),
-                                },
-                            )),
-                        },
-                    ));
+            let event_id = event_id.map(|event_id| event_id.event_id);
+            let (succeed, mut events) = if compensates_applied_command {
+                match command(&account) {
+                    Ok(event) => (true, vec![(event_id, event)]),
+                    Err(_) => (false, vec![]),
                 }
-                events
+            } else {
+                // The undone command never took effect, so the compensation only replies
+                println!(
+                    "Skip compensation of a command not applied to Account-{}",
+                    account_id
+                );
+                (true, vec![])
+            };
+            if let Some((reply_channel, state)) = command_metadata {
+                events.push((
+                    event_id.as_ref().map(reply_event_id),
+                    command_reply_requested(reply_channel, state, succeed),
+                ));
+            }
+            if events.is_empty() {
+                return Ok(account);
             }
-            Err(_) => {
-                if let Some((reply_channel, state)) = command_metadata {
-                    vec![(
-                        None,
-                        AccountingEvent {
-                            event: Some(accounting_event::Event::CommandReplyRequested(
-                                CommandReplyRequested {
-                                    reply: Some(CommandReply {
-                                        state: state.clone(),
-                                        succeed: false,
-                                        body: None,
-                                    }),
-                                    reply_channel: reply_channel.to_string(),
-                                },
-                            )),
-                        },
-                    )]
-                } else {
-                    vec![]
+
+            match self
+                .store
+                .append(
+                    &account_id,
+                    &events,
+                    Some(AppendCondition::ExpectLastSequence(last_sequence)),
+                )
+                .await
+            {
+                Ok(()) => {
+                    return events
+                        .into_iter()
+                        .try_fold(account, |acc, (_, e)| acc.apply(e))
+                        .map_err(|_| AccountingError::Internal)
+                }
+                Err(EventStoreError::AppendConditionFailed(_)) => {
+                    println!(
+                        "Account-{} was modified concurrently (attempt {}/{})",
+                        account_id, attempt, MAX_APPEND_ATTEMPTS
+                    );
                 }
+                // A concurrent delivery of the same command appended its event first
+                Err(EventStoreError::UnexpectedInternal(DatabaseError(
+                    DatabaseErrorKind::UniqueViolation,
+                    _,
+                ))) if event_id.is_some() => {}
+                Err(_) => return Err(AccountingError::Internal),
             }
-        };
+        }
 
-        let _ = self
+        if let Some(event_id) = &event_id {
+            // The last attempt may have lost against a concurrent delivery of the command
+            if self.replay_command(&account_id, &event_id.event_id).await? {
+                let (account, _) = self.load(&account_id).await?;
+                return Ok(account);
+            }
+        }
+        // Reply with a failure, so that the sender does not wait on the command forever
+        if let Some((reply_channel, state)) = command_metadata {
+            let reply = (
+                event_id.map(|event_id| reply_event_id(&event_id.event_id)),
+                command_reply_requested(reply_channel, state, false),
+            );
+            match self.store.append(&account_id, &vec![reply], None).await {
+                Ok(())
+                | Err(EventStoreError::UnexpectedInternal(DatabaseError(
+                    DatabaseErrorKind::UniqueViolation,
+                    _,
+                ))) => {}
+                Err(_) => return Err(AccountingError::Internal),
+            }
+        }
+        Err(AccountingError::ConcurrentModification)
+    }
+
+    async fn load(&mut self, account_id: &Uuid) -> Result<(Account, i64), AccountingError> {
+        self.store.get(account_id).await.map_err(|err| match err {
+            EventStoreError::InvalidEvent { .. } => AccountingError::InvalidEvent(err.to_string()),
+            _ => AccountingError::Internal,
+        })
+    }
+
+    /// Returns true when the command identified by `event_id` was already applied to the
+    /// account. Its reply is requested once more, since the sender is still waiting for it.
+    async fn replay_command(
+        &mut self,
+        account_id: &Uuid,
+        event_id: &Uuid,
+    ) -> Result<bool, AccountingError> {
+        let reply = self
             .store
-            .append(
-                &account_id,
-                &events,
-                Some(AppendCondition::ExpectLastSequence(last_sequence)),
-            )
-            .await;
+            .find_event(account_id, &reply_event_id(event_id))
+            .await
+            .map_err(|_| AccountingError::Internal)?;
+        if let Some(reply) = reply {
+            self.store
+                .append(account_id, &vec![(None, reply)], None)
+                .await
+                .map_err(|_| AccountingError::Internal)?;
+            return Ok(true);
+        }
 
-        Ok(events.into_iter().fold(account, |acc, (_, e)| acc.apply(e)))
+        let applied = self
+            .store
+            .find_event(account_id, event_id)
+            .await
+            .map_err(|_| AccountingError::Internal)?
+            .is_some();
+        Ok(applied)
     }
 
     pub async fn get_account(
@@ -203,6 +300,128 @@ impl AccountingService<'_> {
         }
     }
 
+    /// Lists transactions of the account in sequence order. `after` and `before` are
+    /// sequences of the boundary transactions.
+    pub async fn list_account_transactions(
+        &mut self,
+        account_id: &Uuid,
+        first: Option<u32>,
+        after: Option<i64>,
+        last: Option<u32>,
+        before: Option<i64>,
+    ) -> Result<Vec<projection::account_transactions::AccountTransaction>, AccountingError> {
+        use projection::account_transactions::AccountTransaction;
+        use schema::account_transactions;
+
+        let base_query = account_transactions::table
+            .select(AccountTransaction::as_select())
+            .filter(account_transactions::account_id.eq(account_id));
+        let query = match (after, before, first, last) {
+            (None, None, Some(first), None) => base_query
+                .order_by(account_transactions::sequence.asc())
+                .limit(first.into())
+                .into_boxed(),
+            (None, None, None, Some(last)) => base_query
+                .order_by(account_transactions::sequence.desc())
+                .limit(last.into())
+                .into_boxed(),
+            (Some(after), None, Some(first), None) => base_query
+                .filter(account_transactions::sequence.gt(after))
+                .order_by(account_transactions::sequence.asc())
+                .limit(first.into())
+                .into_boxed(),
+            (None, Some(before), None, Some(last)) => base_query
+                .filter(account_transactions::sequence.lt(before))
+                .order_by(account_transactions::sequence.desc())
+                .limit(last.into())
+                .into_boxed(),
+            (Some(_), Some(_), _, _) => {
+                return Err(AccountingError::InvalidPagination(
+                    "Only one of `after` or `before` can be given.",
+                ))
+            }
+            (_, _, Some(_), Some(_)) => {
+                return Err(AccountingError::InvalidPagination(
+                    "Only one of `first` or `last` can be given.",
+                ))
+            }
+            (_, _, None, None) => {
+                return Err(AccountingError::InvalidPagination(
+                    "One of `first` or `last` must be given.",
+                ))
+            }
+            (Some(_), _, None, _) => {
+                return Err(AccountingError::InvalidPagination(
+                    "`first` required if `after` is given.",
+                ))
+            }
+            (_, Some(_), _, None) => {
+                return Err(AccountingError::InvalidPagination(
+                    "`last` required if `before` is given.",
+                ))
+            }
+        };
+        query
+            .get_results(self.projection_conn)
+            .await
+            .map_err(|_| AccountingError::Internal)
+    }
+
+    /// Amount owed to the restaurant: its order payments net of commission, minus refunds
+    pub async fn get_restaurant_balance(
+        &mut self,
+        restaurant_id: &Uuid,
+    ) -> Result<BigDecimal, AccountingError> {
+        use projection::ledger::ACCOUNT_KIND_RESTAURANT;
+        use schema::ledger_entries;
+
+        let totals = ledger_entries::table
+            .filter(ledger_entries::account_kind.eq(ACCOUNT_KIND_RESTAURANT))
+            .filter(ledger_entries::account_id.eq(restaurant_id))
+            .group_by(ledger_entries::direction)
+            .select((
+                ledger_entries::direction,
+                diesel::dsl::sum(ledger_entries::amount),
+            ))
+            .load::<(String, Option<BigDecimal>)>(self.projection_conn)
+            .await
+            .map_err(|_| AccountingError::Internal)?;
+        let (debits, credits) = split_totals(totals);
+        Ok(credits - debits)
+    }
+
+    /// Checks that every ledger transaction, and so the whole ledger, sums to zero
+    pub async fn reconcile_ledger(&mut self) -> Result<LedgerReconciliation, AccountingError> {
+        use schema::ledger_entries;
+
+        let totals = ledger_entries::table
+            .group_by(ledger_entries::direction)
+            .select((
+                ledger_entries::direction,
+                diesel::dsl::sum(ledger_entries::amount),
+            ))
+            .load::<(String, Option<BigDecimal>)>(self.projection_conn)
+            .await
+            .map_err(|_| AccountingError::Internal)?;
+        let (total_debits, total_credits) = split_totals(totals);
+
+        let unbalanced_transactions = sql_query(
+            "SELECT source_account_id, source_sequence FROM ledger_entries \
+             GROUP BY source_account_id, source_sequence \
+             HAVING sum(CASE WHEN direction = 'DEBIT' THEN amount ELSE -amount END) <> 0 \
+             ORDER BY source_account_id, source_sequence",
+        )
+        .load::<projection::ledger::UnbalancedTransaction>(self.projection_conn)
+        .await
+        .map_err(|_| AccountingError::Internal)?;
+
+        Ok(LedgerReconciliation {
+            total_debits,
+            total_credits,
+            unbalanced_transactions,
+        })
+    }
+
     pub async fn list_accounts(
         &mut self,
         page: u32,
@@ -229,10 +448,184 @@ impl<'a> AccountingService<'a> {
     }
 }
 
+pub struct LedgerReconciliation {
+    pub total_debits: BigDecimal,
+    pub total_credits: BigDecimal,
+    pub unbalanced_transactions: Vec<projection::ledger::UnbalancedTransaction>,
+}
+
+impl LedgerReconciliation {
+    pub fn is_balanced(&self) -> bool {
+        self.total_debits == self.total_credits && self.unbalanced_transactions.is_empty()
+    }
+}
+
+/// Splits sums of ledger amounts grouped by direction into debits and credits
+fn split_totals(totals: Vec<(String, Option<BigDecimal>)>) -> (BigDecimal, BigDecimal) {
+    use projection::account_transactions::DIRECTION_DEBIT;
+
+    totals.into_iter().fold(
+        (BigDecimal::zero(), BigDecimal::zero()),
+        |(debits, credits), (direction, amount)| {
+            let amount = amount.unwrap_or_else(BigDecimal::zero);
+            if direction == DIRECTION_DEBIT {
+                (debits + amount, credits)
+            } else {
+                (debits, credits + amount)
+            }
+        },
+    )
+}
+
+/// Order payments are split between the restaurant and the platform commission
+fn order_payee(restaurant_id: Uuid) -> OrderPayee {
+    OrderPayee {
+        restaurant_id,
+        commission_rate: platform_commission_rate(),
+    }
+}
+
+fn command_reply_requested(
+    reply_channel: &str,
+    state: &HashMap<String, String>,
+    succeed: bool,
+) -> AccountingEvent {
+    AccountingEvent {
+        event: Some(accounting_event::Event::CommandReplyRequested(
+            CommandReplyRequested {
+                reply: Some(CommandReply {
+                    state: state.clone(),
+                    succeed,
+                    body: None,
+                }),
+                reply_channel: reply_channel.to_string(),
+            },
+        )),
+    }
+}
+
+/// Id of the `CommandReplyRequested` event appended along with the command event `event_id`
+fn reply_event_id(event_id: &Uuid) -> Uuid {
+    Uuid::new_v5(event_id, b"CommandReplyRequested")
+}
+
 #[derive(Error, Debug)]
 pub enum AccountingError {
     #[error("Internal error")]
     Internal,
     #[error("account already exists")]
     AccountAlreadyExists,
+    #[error("account was modified concurrently")]
+    ConcurrentModification,
+    #[error("{0}")]
+    InvalidPagination(&'static str),
+    #[error("account cannot be loaded: {0}")]
+    InvalidEvent(String),
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::establish_connection;
+    use chrono::Utc;
+    use diesel_async::AsyncConnection;
+    use projection::account_transactions::{DIRECTION_CREDIT, DIRECTION_DEBIT};
+    use projection::ledger::{LedgerEntry, ACCOUNT_KIND_CONSUMER, ACCOUNT_KIND_EXTERNAL};
+
+    // Every test runs in a transaction that is never committed
+    async fn setup_connection() -> AsyncPgConnection {
+        let mut conn = establish_connection().await;
+        conn.begin_test_transaction().await.unwrap();
+        conn
+    }
+
+    fn amount(value: &str) -> BigDecimal {
+        value.parse().unwrap()
+    }
+
+    fn entry(source_account_id: Uuid, line: i32, direction: &str, amount: &str) -> LedgerEntry {
+        LedgerEntry {
+            source_account_id,
+            source_sequence: 0,
+            line,
+            account_kind: if line == 0 {
+                ACCOUNT_KIND_CONSUMER
+            } else {
+                ACCOUNT_KIND_EXTERNAL
+            }
+            .to_string(),
+            account_id: (line == 0).then_some(source_account_id),
+            direction: direction.to_string(),
+            amount: amount.parse().unwrap(),
+            order_id: None,
+            created_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_reconcile_ledger_reports_unbalanced_transactions() {
+        let store_conn = &mut establish_connection().await;
+        let projection_conn = &mut setup_connection().await;
+        let balanced = Uuid::new_v4();
+        let unbalanced = Uuid::new_v4();
+        diesel::insert_into(schema::ledger_entries::table)
+            .values(vec![
+                entry(balanced, 0, DIRECTION_CREDIT, "10"),
+                entry(balanced, 1, DIRECTION_DEBIT, "10"),
+                entry(unbalanced, 0, DIRECTION_CREDIT, "10"),
+                entry(unbalanced, 1, DIRECTION_DEBIT, "9.99"),
+            ])
+            .execute(projection_conn)
+            .await
+            .unwrap();
+
+        let mut service = AccountingService::new(AccountStore::new(store_conn), projection_conn);
+        let reconciliation = service.reconcile_ledger().await.unwrap();
+        let unbalanced_ids = reconciliation
+            .unbalanced_transactions
+            .iter()
+            .map(|transaction| transaction.source_account_id)
+            .collect::<Vec<_>>();
+        assert!(unbalanced_ids.contains(&unbalanced));
+        assert!(!unbalanced_ids.contains(&balanced));
+        assert!(!reconciliation.is_balanced());
+    }
+
+    #[tokio::test]
+    async fn test_compensation_of_unapplied_command_is_skipped() {
+        let store_conn = &mut setup_connection().await;
+        let projection_conn = &mut setup_connection().await;
+        let mut service = AccountingService::new(AccountStore::new(store_conn), projection_conn);
+        let account_id = service.create(None).await.unwrap().id;
+        service
+            .deposit(account_id, amount("10"), None, None, None, None)
+            .await
+            .unwrap();
+
+        let withdrawal = CommandEventId {
+            event_id: Uuid::new_v4(),
+            compensated_event_id: None,
+        };
+        let refund = CommandEventId {
+            event_id: Uuid::new_v4(),
+            compensated_event_id: Some(withdrawal.event_id),
+        };
+
+        // The withdrawal never took effect, so there is nothing to refund
+        let account = service
+            .deposit(account_id, amount("5"), None, None, Some(refund), None)
+            .await
+            .unwrap();
+        assert_eq!(account.balance, amount("10"));
+
+        service
+            .withdraw(account_id, amount("5"), None, None, Some(withdrawal), None)
+            .await
+            .unwrap();
+        let account = service
+            .deposit(account_id, amount("5"), None, None, Some(refund), None)
+            .await
+            .unwrap();
+        assert_eq!(account.balance, amount("10"));
+    }
 }
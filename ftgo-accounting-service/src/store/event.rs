@@ -1,22 +1,127 @@
-use diesel::{insert_into, prelude::*, update, QueryResult};
+use diesel::{
+    insert_into,
+    prelude::*,
+    sql_query,
+    sql_types::{BigInt, Text},
+    update, QueryResult,
+};
 use diesel_async::{
     scoped_futures::ScopedFutureExt, AsyncConnection, AsyncPgConnection, RunQueryDsl,
 };
 use futures::Stream;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use thiserror::Error;
 use uuid::Uuid;
 
 use crate::{
-    models::{Event, EventStream, NewEvent},
+    models::{Event, EventStream, NewEvent, NewSnapshot, Snapshot},
     schema,
 };
 
+/// Channel notified with the stream name whenever events are appended
+pub const EVENT_APPENDED_CHANNEL: &str = "event_appended";
+
+/// Key of the advisory lock serializing appends across streams.
+///
+/// The lock is held from taking positions until the commit, so appends to all streams
+/// commit one after another and throughput is bounded by a single commit at a time. This
+/// is accepted because accounting appends a few events per command, far below that
+/// bound, while a lock-free scheme would need every subscription to track in-flight
+/// transactions to avoid skipping positions that commit late.
+const EVENT_POSITION_LOCK_KEY: i64 = 0x6674_676f_6576_656e;
+
 pub struct EventData {
     pub payload: Vec<u8>,
     pub metadata: Value,
 }
 
+/// Schema version of events appended before versions were recorded
+pub const INITIAL_SCHEMA_VERSION: i32 = 1;
+
+/// Metadata stored along with every event
+#[derive(Serialize, Deserialize, Debug)]
+pub struct EventMetadata {
+    pub event_type: String,
+    #[serde(default = "initial_schema_version")]
+    pub schema_version: i32,
+}
+
+fn initial_schema_version() -> i32 {
+    INITIAL_SCHEMA_VERSION
+}
+
+/// Migrates payloads of `event_type` stored with `from_version` to `from_version + 1`
+pub struct Upcaster {
+    pub event_type: &'static str,
+    pub from_version: i32,
+    pub upcast: fn(Vec<u8>) -> Result<Vec<u8>, String>,
+}
+
+/// Upcasters of a stream type. The current schema version of an event type is the one
+/// produced by its last upcaster.
+#[derive(Default)]
+pub struct UpcasterChain {
+    upcasters: Vec<Upcaster>,
+}
+
+impl UpcasterChain {
+    pub fn register(mut self, upcaster: Upcaster) -> Self {
+        assert_eq!(
+            upcaster.from_version,
+            self.current_version(upcaster.event_type),
+            "Upcasters of `{}` must be registered in version order",
+            upcaster.event_type
+        );
+        self.upcasters.push(upcaster);
+        self
+    }
+
+    /// Version appended events of `event_type` are stamped with
+    pub fn current_version(&self, event_type: &str) -> i32 {
+        self.upcasters
+            .iter()
+            .filter(|upcaster| upcaster.event_type == event_type)
+            .map(|upcaster| upcaster.from_version + 1)
+            .max()
+            .unwrap_or(INITIAL_SCHEMA_VERSION)
+    }
+
+    /// Returns the payload of the stored event migrated to the current schema version
+    pub fn upcast(&self, event: &Event) -> Result<Vec<u8>, EventStoreError> {
+        let invalid = |reason: String| EventStoreError::InvalidEvent {
+            stream_name: event.stream_name.to_string(),
+            sequence: event.sequence,
+            reason,
+        };
+        let metadata = serde_json::from_value::<EventMetadata>(event.metadata.clone())
+            .map_err(|err| invalid(format!("invalid metadata: {}", err)))?;
+        let current_version = self.current_version(&metadata.event_type);
+        if metadata.schema_version > current_version {
+            return Err(invalid(format!(
+                "schema version {} of {} is newer than the supported version {}",
+                metadata.schema_version, metadata.event_type, current_version
+            )));
+        }
+
+        let mut payload = event.payload.clone();
+        for version in metadata.schema_version..current_version {
+            let upcaster = self
+                .upcasters
+                .iter()
+                .find(|u| u.event_type == metadata.event_type && u.from_version == version)
+                .expect("Upcasters are registered in version order");
+            payload = (upcaster.upcast)(payload).map_err(|reason| {
+                invalid(format!(
+                    "cannot upcast {} from version {}: {}",
+                    metadata.event_type, version, reason
+                ))
+            })?;
+        }
+        Ok(payload)
+    }
+}
+
 #[derive(Debug)]
 pub enum AppendCondition {
     NoStream,
@@ -24,10 +129,35 @@ pub enum AppendCondition {
     ExpectLastSequence(i64),
 }
 
+/// Decides when an aggregate loaded from its stream should be snapshotted
+#[derive(Debug, Clone, Copy)]
+pub enum SnapshotPolicy {
+    Never,
+    /// Take a snapshot once this many events were replayed on top of the latest one
+    EveryNEvents(i64),
+}
+
+impl SnapshotPolicy {
+    pub fn should_snapshot(&self, snapshot_sequence: Option<i64>, last_sequence: i64) -> bool {
+        match self {
+            SnapshotPolicy::Never => false,
+            SnapshotPolicy::EveryNEvents(n) => {
+                last_sequence - snapshot_sequence.unwrap_or(-1) >= *n
+            }
+        }
+    }
+}
+
 #[derive(Error, Debug)]
 pub enum EventStoreError {
     #[error("append condition check failed")]
     AppendConditionFailed(AppendCondition),
+    #[error("event {sequence} of {stream_name} is invalid: {reason}")]
+    InvalidEvent {
+        stream_name: String,
+        sequence: i64,
+        reason: String,
+    },
     #[error("database error")]
     UnexpectedInternal(#[from] diesel::result::Error),
 }
@@ -88,6 +218,15 @@ impl<'a> EventStore<'a> {
                         }
                     }
 
+                    // Positions are taken and committed one append at a time, so a subscriber
+                    // never moves past a position whose transaction commits later. Taken after
+                    // the append condition, so rejected appends never wait for it
+                    sql_query("SELECT pg_advisory_xact_lock($1)")
+                        .bind::<BigInt, _>(EVENT_POSITION_LOCK_KEY)
+                        .execute(conn)
+                        .await
+                        .map_err(EventStoreError::UnexpectedInternal)?;
+
                     let new_events = events
                         .into_iter()
                         .map(|(event_id, event_data)| {
@@ -111,6 +250,13 @@ impl<'a> EventStore<'a> {
                         .execute(conn)
                         .await
                         .map_err(EventStoreError::UnexpectedInternal)?;
+                    // Delivered to listeners once the transaction commits
+                    sql_query("SELECT pg_notify($1, $2)")
+                        .bind::<Text, _>(EVENT_APPENDED_CHANNEL)
+                        .bind::<Text, _>(stream_name)
+                        .execute(conn)
+                        .await
+                        .map_err(EventStoreError::UnexpectedInternal)?;
 
                     Ok(new_events
                         .into_iter()
@@ -137,4 +283,199 @@ impl<'a> EventStore<'a> {
             .map_err(|err| EventStoreError::UnexpectedInternal(err))?;
         Ok(stream)
     }
+
+    pub async fn find_event(&mut self, id: &Uuid) -> Result<Option<Event>, EventStoreError> {
+        schema::events::table
+            .select(Event::as_select())
+            .find((&self.stream_name, id))
+            .first::<Event>(self.conn)
+            .await
+            .optional()
+            .map_err(EventStoreError::UnexpectedInternal)
+    }
+
+    /// Reads events whose sequence is greater than `sequence`
+    pub async fn read_stream_after(
+        &mut self,
+        sequence: i64,
+    ) -> Result<impl Stream<Item = QueryResult<Event>>, EventStoreError> {
+        let stream = schema::events::table
+            .select(Event::as_select())
+            .filter(schema::events::stream_name.eq(self.stream_name.to_string()))
+            .filter(schema::events::sequence.gt(sequence))
+            .order_by((
+                schema::events::stream_name.asc(),
+                schema::events::sequence.asc(),
+            ))
+            .load_stream(self.conn)
+            .await
+            .map_err(EventStoreError::UnexpectedInternal)?;
+        Ok(stream)
+    }
+
+    pub async fn load_snapshot(&mut self) -> Result<Option<Snapshot>, EventStoreError> {
+        schema::snapshots::table
+            .select(Snapshot::as_select())
+            .find(&self.stream_name)
+            .first::<Snapshot>(self.conn)
+            .await
+            .optional()
+            .map_err(EventStoreError::UnexpectedInternal)
+    }
+
+    /// Replaces the snapshot of the stream with the state as of `sequence`
+    pub async fn save_snapshot(
+        &mut self,
+        sequence: i64,
+        version: i32,
+        payload: Value,
+    ) -> Result<(), EventStoreError> {
+        let snapshot = NewSnapshot {
+            stream_name: self.stream_name.to_string(),
+            sequence,
+            version,
+            payload,
+        };
+        insert_into(schema::snapshots::table)
+            .values(&snapshot)
+            .on_conflict(schema::snapshots::stream_name)
+            .do_update()
+            .set(&snapshot)
+            .execute(self.conn)
+            .await
+            .map_err(EventStoreError::UnexpectedInternal)?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::establish_connection;
+    use chrono::Utc;
+    use serde_json::json;
+
+    // Every test runs in a transaction that is never committed
+    async fn setup_connection() -> AsyncPgConnection {
+        let mut conn = establish_connection().await;
+        conn.begin_test_transaction().await.unwrap();
+        conn
+    }
+
+    fn stored_event(metadata: Value, payload: &[u8]) -> Event {
+        Event {
+            stream_name: "Test-stream".to_string(),
+            id: Uuid::new_v4(),
+            sequence: 0,
+            payload: payload.to_vec(),
+            metadata,
+            created_at: Utc::now(),
+            position: 0,
+        }
+    }
+
+    fn upcasters() -> UpcasterChain {
+        UpcasterChain::default()
+            .register(Upcaster {
+                event_type: "TestEvent",
+                from_version: 1,
+                upcast: |payload| Ok([payload, b"+v2".to_vec()].concat()),
+            })
+            .register(Upcaster {
+                event_type: "TestEvent",
+                from_version: 2,
+                upcast: |payload| Ok([payload, b"+v3".to_vec()].concat()),
+            })
+    }
+
+    #[test]
+    fn test_snapshot_policy() {
+        assert!(!SnapshotPolicy::Never.should_snapshot(None, 1000));
+
+        let policy = SnapshotPolicy::EveryNEvents(3);
+        assert!(!policy.should_snapshot(None, 1));
+        assert!(policy.should_snapshot(None, 2));
+        assert!(!policy.should_snapshot(Some(2), 4));
+        assert!(policy.should_snapshot(Some(2), 5));
+    }
+
+    #[test]
+    fn test_upcast_v1_payload() {
+        let upcasters = upcasters();
+        assert_eq!(upcasters.current_version("TestEvent"), 3);
+        assert_eq!(
+            upcasters.current_version("OtherEvent"),
+            INITIAL_SCHEMA_VERSION
+        );
+
+        let event = stored_event(
+            json!({"event_type": "TestEvent", "schema_version": 1}),
+            b"payload",
+        );
+        assert_eq!(upcasters.upcast(&event).unwrap(), b"payload+v2+v3");
+
+        // Events appended before versions were recorded are read as version 1
+        let event = stored_event(json!({"event_type": "TestEvent"}), b"payload");
+        assert_eq!(upcasters.upcast(&event).unwrap(), b"payload+v2+v3");
+
+        let event = stored_event(
+            json!({"event_type": "TestEvent", "schema_version": 3}),
+            b"payload",
+        );
+        assert_eq!(upcasters.upcast(&event).unwrap(), b"payload");
+    }
+
+    #[test]
+    fn test_upcast_rejects_newer_version() {
+        let event = stored_event(
+            json!({"event_type": "TestEvent", "schema_version": 4}),
+            b"payload",
+        );
+        assert!(matches!(
+            upcasters().upcast(&event),
+            Err(EventStoreError::InvalidEvent { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_snapshot_round_trip() {
+        let conn = &mut setup_connection().await;
+        let stream_name = format!("Test-{}", Uuid::new_v4());
+        let mut store = EventStore::new(&stream_name, conn);
+        store
+            .append(
+                vec![(
+                    None,
+                    EventData {
+                        payload: b"payload".to_vec(),
+                        metadata: json!({"event_type": "TestEvent"}),
+                    },
+                )],
+                Some(AppendCondition::NoStream),
+            )
+            .await
+            .unwrap();
+        assert_eq!(store.load_snapshot().await.unwrap(), None);
+
+        store
+            .save_snapshot(3, 1, json!({"balance": "10"}))
+            .await
+            .unwrap();
+        let snapshot = store.load_snapshot().await.unwrap().unwrap();
+        assert_eq!(
+            (snapshot.sequence, snapshot.version, snapshot.payload),
+            (3, 1, json!({"balance": "10"}))
+        );
+
+        // A later snapshot replaces the previous one
+        store
+            .save_snapshot(7, 2, json!({"balance": "25"}))
+            .await
+            .unwrap();
+        let snapshot = store.load_snapshot().await.unwrap().unwrap();
+        assert_eq!(
+            (snapshot.sequence, snapshot.version, snapshot.payload),
+            (7, 2, json!({"balance": "25"}))
+        );
+    }
 }
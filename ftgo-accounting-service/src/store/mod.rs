@@ -1,5 +1,5 @@
-pub mod checkpoint;
 pub mod event;
+pub mod subscription;
 
 // pub mod account;
 // pub mod checkpoint;
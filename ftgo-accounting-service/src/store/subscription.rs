@@ -0,0 +1,152 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use diesel::{delete, insert_into, prelude::*, QueryResult};
+use diesel_async::{AsyncPgConnection, RunQueryDsl};
+use futures::{stream::poll_fn, Stream, StreamExt};
+use tokio::sync::Notify;
+use tokio_postgres::{AsyncMessage, NoTls};
+
+use crate::{
+    models::{Event, Subscription},
+    schema,
+};
+
+use super::event::EVENT_APPENDED_CHANNEL;
+
+/// How often subscribers read the event store when no notification arrives
+pub const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long to wait before listening again after the notification connection is lost
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Reads the event store in global position order, remembering a single position per
+/// subscription
+pub struct SubscriptionStore<'a> {
+    subscription_id: String,
+    conn: &'a mut AsyncPgConnection,
+}
+
+impl<'a> SubscriptionStore<'a> {
+    /// Marks every event up to `position` as processed by the subscription
+    pub async fn checkpoint(&mut self, position: i64) -> Result<(), diesel::result::Error> {
+        let subscription = Subscription {
+            subscription_id: self.subscription_id.to_string(),
+            position,
+            checkpointed_at: Utc::now(),
+        };
+        insert_into(schema::subscriptions::table)
+            .values(&subscription)
+            .on_conflict(schema::subscriptions::subscription_id)
+            .do_update()
+            .set(&subscription)
+            .execute(self.conn)
+            .await?;
+        Ok(())
+    }
+
+    /// Forgets the checkpoint of the subscription, so that it starts over from the first event
+    pub async fn clear(&mut self) -> Result<(), diesel::result::Error> {
+        delete(schema::subscriptions::table)
+            .filter(schema::subscriptions::subscription_id.eq(&self.subscription_id))
+            .execute(self.conn)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn retrieve_events_after_checkpoint(
+        &mut self,
+    ) -> Result<impl Stream<Item = QueryResult<Event>>, diesel::result::Error> {
+        let position = schema::subscriptions::table
+            .select(schema::subscriptions::position)
+            .find(&self.subscription_id)
+            .first::<i64>(self.conn)
+            .await
+            .optional()?
+            .unwrap_or(-1);
+        schema::events::table
+            .filter(schema::events::position.gt(position))
+            .order_by(schema::events::position.asc())
+            .select(Event::as_select())
+            .load_stream::<Event>(self.conn)
+            .await
+    }
+
+    pub fn new(subscription_id: &str, conn: &'a mut AsyncPgConnection) -> Self {
+        Self {
+            subscription_id: subscription_id.to_string(),
+            conn,
+        }
+    }
+}
+
+/// Wakes a subscriber when events are appended, using `LISTEN` on the channel notified by
+/// `EventStore::append`. Subscribers still poll every `poll_interval`, which covers
+/// notifications lost while the listening connection is down.
+pub struct EventListener {
+    notify: Arc<Notify>,
+    poll_interval: Duration,
+}
+
+impl EventListener {
+    pub fn spawn(database_url: String, poll_interval: Duration) -> Self {
+        let notify = Arc::new(Notify::new());
+        tokio::spawn(Self::listen(database_url, notify.clone()));
+        Self {
+            notify,
+            poll_interval,
+        }
+    }
+
+    /// Returns once events were appended since the last call, or after the poll interval
+    pub async fn wait(&self) {
+        let _ = tokio::time::timeout(self.poll_interval, self.notify.notified()).await;
+    }
+
+    async fn listen(database_url: String, notify: Arc<Notify>) {
+        loop {
+            if let Err(err) = Self::listen_once(&database_url, &notify).await {
+                eprintln!(
+                    "Event notifications are unavailable, polling meanwhile: {}",
+                    err
+                );
+            }
+            tokio::time::sleep(RECONNECT_INTERVAL).await;
+        }
+    }
+
+    async fn listen_once(database_url: &str, notify: &Notify) -> Result<(), tokio_postgres::Error> {
+        let (client, mut connection) = tokio_postgres::connect(database_url, NoTls).await?;
+        let mut messages = poll_fn(move |cx| connection.poll_message(cx));
+
+        // The connection only makes progress while its messages are polled
+        let statement = format!("LISTEN {}", EVENT_APPENDED_CHANNEL);
+        let listen = client.batch_execute(&statement);
+        tokio::pin!(listen);
+        loop {
+            tokio::select! {
+                result = &mut listen => {
+                    result?;
+                    break;
+                }
+                message = messages.next() => match message {
+                    Some(message) => Self::handle(message?, notify),
+                    None => return Ok(()),
+                },
+            }
+        }
+        // Events appended while no one was listening are picked up by the next read
+        notify.notify_one();
+
+        while let Some(message) = messages.next().await {
+            Self::handle(message?, notify);
+        }
+        Ok(())
+    }
+
+    fn handle(message: AsyncMessage, notify: &Notify) {
+        if let AsyncMessage::Notification(_) = message {
+            notify.notify_one();
+        }
+    }
+}
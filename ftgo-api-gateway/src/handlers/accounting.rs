@@ -1,20 +1,22 @@
 use axum::{
     Router,
-    extract::{Path, State},
+    extract::{Path, Query, State},
     http::HeaderMap,
     response::Json,
     routing::{get, post},
 };
 use ftgo_proto::accounting_service::{
-    DepositAccountPayload, GetAccountPayload, WithdrawAccountPayload,
+    DepositAccountPayload, GetAccountPayload, GetRestaurantBalancePayload,
+    ListAccountTransactionsPayload, ReconcileLedgerPayload, WithdrawAccountPayload,
 };
 use ftgo_proto::common::Money;
+use serde::Deserialize;
 use tracing::instrument;
 
 use crate::error::ApiError;
 use crate::models::*;
 
-use super::{AppState, verify_consumer_access};
+use super::{AppState, verify_admin_access, verify_consumer_access, verify_restaurant_access};
 
 pub fn router() -> Router<AppState> {
     Router::new()
@@ -27,6 +29,15 @@ pub fn router() -> Router<AppState> {
             "/consumers/{consumer_id}/account/withdraw",
             post(withdraw_account),
         )
+        .route(
+            "/consumers/{consumer_id}/account/transactions",
+            get(list_account_transactions),
+        )
+        .route(
+            "/restaurants/{restaurant_id}/balance",
+            get(get_restaurant_balance),
+        )
+        .route("/admin/ledger/reconciliation", get(reconcile_ledger))
 }
 
 #[utoipa::path(
@@ -78,6 +89,7 @@ pub async fn get_account(
         account_id: account.id.parse().map_err(|_| ApiError::InvalidToken)?,
         consumer_id: consumer_id.parse().map_err(|_| ApiError::InvalidToken)?,
         balance: account.balance.map(|b| b.amount).unwrap_or_default(),
+        held_balance: account.held_balance.map(|b| b.amount).unwrap_or_default(),
     }))
 }
 
@@ -144,6 +156,7 @@ pub async fn deposit_account(
         account_id: account.id.parse().map_err(|_| ApiError::InvalidToken)?,
         consumer_id: consumer_id.parse().map_err(|_| ApiError::InvalidToken)?,
         balance: account.balance.map(|b| b.amount).unwrap_or_default(),
+        held_balance: account.held_balance.map(|b| b.amount).unwrap_or_default(),
     }))
 }
 
@@ -216,5 +229,180 @@ pub async fn withdraw_account(
         account_id: account.id.parse().map_err(|_| ApiError::InvalidToken)?,
         consumer_id: consumer_id.parse().map_err(|_| ApiError::InvalidToken)?,
         balance: account.balance.map(|b| b.amount).unwrap_or_default(),
+        held_balance: account.held_balance.map(|b| b.amount).unwrap_or_default(),
+    }))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct ListAccountTransactionsQuery {
+    pub first: Option<u32>,
+    pub after: Option<String>,
+    pub last: Option<u32>,
+    pub before: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/consumers/{consumer_id}/account/transactions",
+    responses(
+        (status = 200, description = "List of account transactions", body = ListAccountTransactionsResponse),
+        (status = 401, description = "Unauthorized", body = ApiErrorResponse),
+        (status = 503, description = "Service unavailable", body = ApiErrorResponse),
+    ),
+    params(
+        ("consumer_id" = String, Path, description = "Consumer ID (equals Account ID)"),
+        ("first" = Option<u32>, Query, description = "Number of items to fetch"),
+        ("after" = Option<String>, Query, description = "Cursor for pagination"),
+        ("last" = Option<u32>, Query, description = "Number of items to fetch from end"),
+        ("before" = Option<String>, Query, description = "Cursor for reverse pagination")
+    ),
+    security(
+        ("bearer" = [])
+    ),
+    tag = "accounting"
+)]
+#[instrument(skip(state))]
+pub async fn list_account_transactions(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(consumer_id): Path<String>,
+    Query(query): Query<ListAccountTransactionsQuery>,
+) -> Result<Json<ListAccountTransactionsResponse>, ApiError> {
+    let mut auth_client = state.auth_client.clone();
+
+    // Verify user has access to this consumer (since account_id = consumer_id)
+    verify_consumer_access(&headers, &mut auth_client, &consumer_id).await?;
+
+    let mut accounting_client = state.accounting_client.clone();
+
+    let request = tonic::Request::new(ListAccountTransactionsPayload {
+        account_id: consumer_id,
+        first: query.first,
+        after: query.after,
+        last: query.last,
+        before: query.before,
+    });
+
+    let response = accounting_client
+        .list_account_transactions(request)
+        .await
+        .map_err(|e| ApiError::ServiceUnavailable(format!("Accounting service error: {e}")))?;
+
+    let edges = response
+        .into_inner()
+        .edges
+        .into_iter()
+        .map(|edge| {
+            let t = edge.node.ok_or(ApiError::ServiceUnavailable(
+                "Invalid transaction data".to_string(),
+            ))?;
+            Ok(AccountTransactionEdge {
+                node: AccountTransactionResponse {
+                    direction: t.direction().as_str_name().to_string(),
+                    amount: t.amount.map(|a| a.amount).unwrap_or_default(),
+                    description: t.description,
+                    order_id: t.order_id.and_then(|id| id.parse().ok()),
+                    resulting_balance: t.resulting_balance.map(|b| b.amount).unwrap_or_default(),
+                    created_at: t.created_at.and_then(|ts| {
+                        chrono::DateTime::from_timestamp(ts.seconds, ts.nanos as u32)
+                    }),
+                },
+                cursor: edge.cursor,
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(Json(ListAccountTransactionsResponse { edges }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/restaurants/{restaurant_id}/balance",
+    responses(
+        (status = 200, description = "Amount owed to the restaurant", body = RestaurantBalanceResponse),
+        (status = 401, description = "Unauthorized", body = ApiErrorResponse),
+        (status = 503, description = "Service unavailable", body = ApiErrorResponse),
+    ),
+    params(
+        ("restaurant_id" = String, Path, description = "Restaurant ID")
+    ),
+    security(
+        ("bearer" = [])
+    ),
+    tag = "accounting"
+)]
+#[instrument(skip(state))]
+pub async fn get_restaurant_balance(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(restaurant_id): Path<String>,
+) -> Result<Json<RestaurantBalanceResponse>, ApiError> {
+    let mut auth_client = state.auth_client.clone();
+    verify_restaurant_access(&headers, &mut auth_client, &restaurant_id).await?;
+
+    let mut accounting_client = state.accounting_client.clone();
+
+    let request = tonic::Request::new(GetRestaurantBalancePayload { restaurant_id });
+
+    let response = accounting_client
+        .get_restaurant_balance(request)
+        .await
+        .map_err(|e| ApiError::ServiceUnavailable(format!("Accounting service error: {e}")))?;
+
+    let balance = response.into_inner();
+
+    Ok(Json(RestaurantBalanceResponse {
+        restaurant_id: balance
+            .restaurant_id
+            .parse()
+            .map_err(|_| ApiError::InvalidToken)?,
+        balance: balance.balance.map(|b| b.amount).unwrap_or_default(),
+    }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/ledger/reconciliation",
+    responses(
+        (status = 200, description = "Ledger reconciliation result", body = LedgerReconciliationResponse),
+        (status = 401, description = "Unauthorized", body = ApiErrorResponse),
+        (status = 403, description = "Forbidden", body = ApiErrorResponse),
+        (status = 503, description = "Service unavailable", body = ApiErrorResponse),
+    ),
+    security(
+        ("bearer" = [])
+    ),
+    tag = "admin"
+)]
+#[instrument(skip(state))]
+pub async fn reconcile_ledger(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Result<Json<LedgerReconciliationResponse>, ApiError> {
+    let mut auth_client = state.auth_client.clone();
+    verify_admin_access(&headers, &mut auth_client, &state.admin_user_ids).await?;
+
+    let mut accounting_client = state.accounting_client.clone();
+
+    let request = tonic::Request::new(ReconcileLedgerPayload {});
+
+    let response = accounting_client
+        .reconcile_ledger(request)
+        .await
+        .map_err(|e| ApiError::ServiceUnavailable(format!("Accounting service error: {e}")))?;
+
+    let reconciliation = response.into_inner();
+
+    Ok(Json(LedgerReconciliationResponse {
+        total_debits: reconciliation
+            .total_debits
+            .map(|m| m.amount)
+            .unwrap_or_default(),
+        total_credits: reconciliation
+            .total_credits
+            .map(|m| m.amount)
+            .unwrap_or_default(),
+        balanced: reconciliation.balanced,
+        unbalanced_transactions: reconciliation.unbalanced_transactions,
     }))
 }
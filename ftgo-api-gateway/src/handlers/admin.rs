@@ -0,0 +1,327 @@
+use axum::{
+    Router,
+    extract::{Path, Query, State},
+    http::HeaderMap,
+    response::Json,
+    routing::{get, post},
+};
+use ftgo_proto::order_service::{
+    CompensateSagaPayload, GetSagaHistoryPayload, GetSagaInstancePayload, ListSagaInstancesPayload,
+    RetrySagaStepPayload, SagaInstance, SagaStepLogEntry,
+};
+use serde::Deserialize;
+use tracing::instrument;
+
+use crate::error::ApiError;
+use crate::models::*;
+
+use super::{AppState, verify_admin_access};
+
+#[derive(Debug, Deserialize)]
+pub struct ListSagaInstancesQuery {
+    pub saga_type: Option<String>,
+    pub end_state: Option<bool>,
+    pub compensating: Option<bool>,
+    pub failed: Option<bool>,
+    pub first: Option<u32>,
+    pub after: Option<String>,
+}
+
+pub fn router() -> Router<AppState> {
+    Router::new()
+        .route("/admin/sagas", get(list_saga_instances))
+        .route("/admin/sagas/{saga_type}/{saga_id}", get(get_saga_instance))
+        .route(
+            "/admin/sagas/{saga_type}/{saga_id}/history",
+            get(get_saga_history),
+        )
+        .route(
+            "/admin/sagas/{saga_type}/{saga_id}/retry",
+            post(retry_saga_step),
+        )
+        .route(
+            "/admin/sagas/{saga_type}/{saga_id}/compensate",
+            post(compensate_saga),
+        )
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/sagas",
+    responses(
+        (status = 200, description = "List of saga instances", body = ListSagaInstancesResponse),
+        (status = 401, description = "Unauthorized", body = ApiErrorResponse),
+        (status = 403, description = "Forbidden", body = ApiErrorResponse),
+        (status = 503, description = "Service unavailable", body = ApiErrorResponse),
+    ),
+    params(
+        ("saga_type" = Option<String>, Query, description = "Filter by saga type"),
+        ("end_state" = Option<bool>, Query, description = "Filter by whether the saga has finished"),
+        ("compensating" = Option<bool>, Query, description = "Filter by whether the saga is compensating"),
+        ("failed" = Option<bool>, Query, description = "Filter by whether the saga failed to compensate"),
+        ("first" = Option<u32>, Query, description = "Number of saga instances to fetch"),
+        ("after" = Option<String>, Query, description = "Cursor for pagination"),
+    ),
+    security(
+        ("bearer" = [])
+    ),
+    tag = "admin"
+)]
+#[instrument(skip(state))]
+pub async fn list_saga_instances(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Query(query): Query<ListSagaInstancesQuery>,
+) -> Result<Json<ListSagaInstancesResponse>, ApiError> {
+    let mut auth_client = state.auth_client.clone();
+    verify_admin_access(&headers, &mut auth_client, &state.admin_user_ids).await?;
+
+    let mut saga_admin_client = state.saga_admin_client.clone();
+
+    let request = tonic::Request::new(ListSagaInstancesPayload {
+        saga_type: query.saga_type,
+        end_state: query.end_state,
+        compensating: query.compensating,
+        failed: query.failed,
+        first: query.first,
+        after: query.after,
+    });
+
+    let response = saga_admin_client
+        .list_saga_instances(request)
+        .await
+        .map_err(|e| ApiError::ServiceUnavailable(format!("Order service error: {e}")))?;
+
+    let edges = response
+        .into_inner()
+        .edges
+        .into_iter()
+        .filter_map(|edge| {
+            Some(SagaInstanceEdge {
+                node: serialize_saga_instance(edge.node?),
+                cursor: edge.cursor,
+            })
+        })
+        .collect();
+
+    Ok(Json(ListSagaInstancesResponse { edges }))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/sagas/{saga_type}/{saga_id}",
+    responses(
+        (status = 200, description = "Saga instance details", body = SagaInstanceResponse),
+        (status = 401, description = "Unauthorized", body = ApiErrorResponse),
+        (status = 403, description = "Forbidden", body = ApiErrorResponse),
+        (status = 503, description = "Service unavailable or saga instance not found", body = ApiErrorResponse),
+    ),
+    params(
+        ("saga_type" = String, Path, description = "Saga type"),
+        ("saga_id" = String, Path, description = "Saga ID")
+    ),
+    security(
+        ("bearer" = [])
+    ),
+    tag = "admin"
+)]
+#[instrument(skip(state))]
+pub async fn get_saga_instance(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((saga_type, saga_id)): Path<(String, String)>,
+) -> Result<Json<SagaInstanceResponse>, ApiError> {
+    let mut auth_client = state.auth_client.clone();
+    verify_admin_access(&headers, &mut auth_client, &state.admin_user_ids).await?;
+
+    let mut saga_admin_client = state.saga_admin_client.clone();
+
+    let request = tonic::Request::new(GetSagaInstancePayload { saga_type, saga_id });
+
+    let response = saga_admin_client
+        .get_saga_instance(request)
+        .await
+        .map_err(map_saga_admin_error)?;
+
+    Ok(Json(serialize_saga_instance(response.into_inner())))
+}
+
+#[utoipa::path(
+    get,
+    path = "/admin/sagas/{saga_type}/{saga_id}/history",
+    responses(
+        (status = 200, description = "Step log of the saga, oldest entry first", body = SagaHistoryResponse),
+        (status = 401, description = "Unauthorized", body = ApiErrorResponse),
+        (status = 403, description = "Forbidden", body = ApiErrorResponse),
+        (status = 503, description = "Service unavailable or saga instance not found", body = ApiErrorResponse),
+    ),
+    params(
+        ("saga_type" = String, Path, description = "Saga type"),
+        ("saga_id" = String, Path, description = "Saga ID")
+    ),
+    security(
+        ("bearer" = [])
+    ),
+    tag = "admin"
+)]
+#[instrument(skip(state))]
+pub async fn get_saga_history(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((saga_type, saga_id)): Path<(String, String)>,
+) -> Result<Json<SagaHistoryResponse>, ApiError> {
+    let mut auth_client = state.auth_client.clone();
+    verify_admin_access(&headers, &mut auth_client, &state.admin_user_ids).await?;
+
+    let mut saga_admin_client = state.saga_admin_client.clone();
+
+    let request = tonic::Request::new(GetSagaHistoryPayload { saga_type, saga_id });
+
+    let response = saga_admin_client
+        .get_saga_history(request)
+        .await
+        .map_err(map_saga_admin_error)?;
+
+    let entries = response
+        .into_inner()
+        .entries
+        .into_iter()
+        .map(serialize_saga_step_log_entry)
+        .collect();
+
+    Ok(Json(SagaHistoryResponse { entries }))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/sagas/{saga_type}/{saga_id}/retry",
+    responses(
+        (status = 200, description = "Command of the current step re-sent", body = SagaInstanceResponse),
+        (status = 401, description = "Unauthorized", body = ApiErrorResponse),
+        (status = 403, description = "Forbidden", body = ApiErrorResponse),
+        (status = 503, description = "Service unavailable, saga instance not found or saga has already ended", body = ApiErrorResponse),
+    ),
+    params(
+        ("saga_type" = String, Path, description = "Saga type"),
+        ("saga_id" = String, Path, description = "Saga ID")
+    ),
+    security(
+        ("bearer" = [])
+    ),
+    tag = "admin"
+)]
+#[instrument(skip(state))]
+pub async fn retry_saga_step(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((saga_type, saga_id)): Path<(String, String)>,
+) -> Result<Json<SagaInstanceResponse>, ApiError> {
+    let mut auth_client = state.auth_client.clone();
+    verify_admin_access(&headers, &mut auth_client, &state.admin_user_ids).await?;
+
+    let mut saga_admin_client = state.saga_admin_client.clone();
+
+    let request = tonic::Request::new(RetrySagaStepPayload { saga_type, saga_id });
+
+    let response = saga_admin_client
+        .retry_saga_step(request)
+        .await
+        .map_err(map_saga_admin_error)?;
+
+    Ok(Json(serialize_saga_instance(response.into_inner())))
+}
+
+#[utoipa::path(
+    post,
+    path = "/admin/sagas/{saga_type}/{saga_id}/compensate",
+    responses(
+        (status = 200, description = "Saga started compensating", body = SagaInstanceResponse),
+        (status = 401, description = "Unauthorized", body = ApiErrorResponse),
+        (status = 403, description = "Forbidden", body = ApiErrorResponse),
+        (status = 503, description = "Service unavailable, saga instance not found or saga cannot be compensated", body = ApiErrorResponse),
+    ),
+    params(
+        ("saga_type" = String, Path, description = "Saga type"),
+        ("saga_id" = String, Path, description = "Saga ID")
+    ),
+    security(
+        ("bearer" = [])
+    ),
+    tag = "admin"
+)]
+#[instrument(skip(state))]
+pub async fn compensate_saga(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((saga_type, saga_id)): Path<(String, String)>,
+) -> Result<Json<SagaInstanceResponse>, ApiError> {
+    let mut auth_client = state.auth_client.clone();
+    verify_admin_access(&headers, &mut auth_client, &state.admin_user_ids).await?;
+
+    let mut saga_admin_client = state.saga_admin_client.clone();
+
+    let request = tonic::Request::new(CompensateSagaPayload { saga_type, saga_id });
+
+    let response = saga_admin_client
+        .compensate_saga(request)
+        .await
+        .map_err(map_saga_admin_error)?;
+
+    Ok(Json(serialize_saga_instance(response.into_inner())))
+}
+
+fn map_saga_admin_error(e: tonic::Status) -> ApiError {
+    match e.code() {
+        tonic::Code::NotFound => {
+            ApiError::ServiceUnavailable("Saga instance not found".to_string())
+        }
+        tonic::Code::FailedPrecondition => ApiError::ServiceUnavailable(e.message().to_string()),
+        _ => ApiError::ServiceUnavailable(format!("Order service error: {e}")),
+    }
+}
+
+fn serialize_saga_instance(instance: SagaInstance) -> SagaInstanceResponse {
+    SagaInstanceResponse {
+        saga_type: instance.saga_type,
+        saga_id: instance.saga_id,
+        currently_executing: instance.currently_executing,
+        last_request_id: instance.last_request_id,
+        end_state: instance.end_state,
+        compensating: instance.compensating,
+        failed: instance.failed,
+        saga_data: serde_json::from_str(&instance.saga_data_json)
+            .unwrap_or(serde_json::Value::String(instance.saga_data_json)),
+        step_deadline: instance
+            .step_deadline
+            .and_then(|ts| chrono::DateTime::from_timestamp(ts.seconds, ts.nanos as u32)),
+        step_attempts: instance.step_attempts,
+    }
+}
+
+fn serialize_saga_step_log_entry(entry: SagaStepLogEntry) -> SagaStepLogEntryResponse {
+    // Convert the SagaStepEvent enum to string
+    let event_str = match entry.event {
+        0 => "INVOKED",
+        1 => "REPLIED",
+        2 => "TIMED_OUT",
+        3 => "COMPENSATION_STARTED",
+        4 => "ENDED",
+        5 => "FAILED",
+        6 => "PARKED",
+        _ => "UNKNOWN",
+    };
+
+    SagaStepLogEntryResponse {
+        step_index: entry.step_index,
+        compensating: entry.compensating,
+        event: event_str.to_string(),
+        request_id: entry.request_id,
+        reply_succeed: entry.reply_succeed,
+        reply_body: entry
+            .reply_body
+            .map(|body| body.iter().map(|b| format!("{b:02x}")).collect()),
+        created_at: entry
+            .created_at
+            .and_then(|ts| chrono::DateTime::from_timestamp(ts.seconds, ts.nanos as u32)),
+    }
+}
@@ -1,16 +1,23 @@
+use std::convert::Infallible;
+
 use axum::{
     Router,
     extract::{Path, Query, State},
     http::HeaderMap,
-    response::Json,
+    response::{
+        Json,
+        sse::{Event, KeepAlive, Sse},
+    },
     routing::{get, post},
 };
 use ftgo_proto::kitchen_service::{
-    AcceptTicketPayload, GetTicketPayload, ListTicketPayload, PreparingTicketPayload,
-    ReadyForPickupTicketPayload,
+    AcceptTicketPayload, GetKitchenCapacityPayload, GetTicketPayload, KitchenCapacity,
+    ListTicketPayload, PreparingTicketPayload, ReadyForPickupTicketPayload, RejectTicketPayload,
+    SetKitchenCapacityPayload, WatchTicketsPayload,
 };
 use prost_types::Timestamp;
 use serde::Deserialize;
+use tokio_stream::{Stream, StreamExt};
 use tracing::instrument;
 
 use crate::error::ApiError;
@@ -66,6 +73,10 @@ fn ticket_to_kitchen_ticket(t: Ticket) -> Result<KitchenTicket, ApiError> {
 pub fn router() -> Router<AppState> {
     Router::new()
         .route("/restaurants/{restaurant_id}/tickets", get(list_tickets))
+        .route(
+            "/restaurants/{restaurant_id}/tickets/stream",
+            get(watch_tickets),
+        )
         .route(
             "/restaurants/{restaurant_id}/tickets/{ticket_id}",
             get(get_ticket),
@@ -82,6 +93,14 @@ pub fn router() -> Router<AppState> {
             "/restaurants/{restaurant_id}/tickets/{ticket_id}/ready",
             post(ready_for_pickup_ticket),
         )
+        .route(
+            "/restaurants/{restaurant_id}/tickets/{ticket_id}/reject",
+            post(reject_ticket),
+        )
+        .route(
+            "/restaurants/{restaurant_id}/kitchen/capacity",
+            get(get_kitchen_capacity).put(set_kitchen_capacity),
+        )
 }
 
 #[derive(Debug, Deserialize)]
@@ -368,3 +387,211 @@ pub async fn ready_for_pickup_ticket(
 
     Ok(Json(kitchen_ticket))
 }
+
+#[utoipa::path(
+    post,
+    path = "/restaurants/{restaurant_id}/tickets/{ticket_id}/reject",
+    request_body = RejectTicketRequest,
+    responses(
+        (status = 200, description = "Ticket rejected, the order is cancelled and refunded", body = KitchenTicket),
+        (status = 401, description = "Unauthorized", body = ApiErrorResponse),
+        (status = 503, description = "Service unavailable or ticket not found", body = ApiErrorResponse),
+    ),
+    params(
+        ("restaurant_id" = String, Path, description = "Restaurant ID"),
+        ("ticket_id" = String, Path, description = "Ticket ID")
+    ),
+    security(
+        ("bearer" = [])
+    ),
+    tag = "kitchen"
+)]
+#[instrument(skip(state))]
+pub async fn reject_ticket(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((restaurant_id, ticket_id)): Path<(String, String)>,
+    Json(payload): Json<RejectTicketRequest>,
+) -> Result<Json<KitchenTicket>, ApiError> {
+    let mut auth_client = state.auth_client.clone();
+
+    // Verify user has access to this restaurant
+    verify_restaurant_access(&headers, &mut auth_client, &restaurant_id).await?;
+
+    let mut kitchen_client = state.kitchen_client.clone();
+
+    let request = tonic::Request::new(RejectTicketPayload {
+        ticket_id,
+        reason: payload.reason,
+        restaurant_id,
+    });
+
+    let response = kitchen_client.reject_ticket(request).await.map_err(|e| {
+        if e.code() == tonic::Code::NotFound {
+            ApiError::ServiceUnavailable("Ticket not found".to_string())
+        } else {
+            ApiError::ServiceUnavailable(format!("Kitchen service error: {e}"))
+        }
+    })?;
+
+    let ticket = response.into_inner();
+    let kitchen_ticket = ticket_to_kitchen_ticket(ticket)?;
+
+    Ok(Json(kitchen_ticket))
+}
+
+fn capacity_to_response(capacity: KitchenCapacity) -> Result<KitchenCapacityResponse, ApiError> {
+    Ok(KitchenCapacityResponse {
+        restaurant_id: capacity.restaurant_id.parse().map_err(|_| {
+            ApiError::ServiceUnavailable(
+                "Kitchen service returned an invalid restaurant id".to_string(),
+            )
+        })?,
+        max_open_tickets: capacity.max_open_tickets,
+        open_tickets: capacity.open_tickets,
+    })
+}
+
+#[utoipa::path(
+    get,
+    path = "/restaurants/{restaurant_id}/kitchen/capacity",
+    responses(
+        (status = 200, description = "Kitchen capacity and current load", body = KitchenCapacityResponse),
+        (status = 401, description = "Unauthorized", body = ApiErrorResponse),
+        (status = 503, description = "Service unavailable or restaurant not found", body = ApiErrorResponse),
+    ),
+    params(
+        ("restaurant_id" = String, Path, description = "Restaurant ID")
+    ),
+    security(
+        ("bearer" = [])
+    ),
+    tag = "kitchen"
+)]
+#[instrument(skip(state))]
+pub async fn get_kitchen_capacity(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(restaurant_id): Path<String>,
+) -> Result<Json<KitchenCapacityResponse>, ApiError> {
+    let mut auth_client = state.auth_client.clone();
+
+    // Verify user has access to this restaurant
+    verify_restaurant_access(&headers, &mut auth_client, &restaurant_id).await?;
+
+    let mut kitchen_client = state.kitchen_client.clone();
+
+    let request = tonic::Request::new(GetKitchenCapacityPayload { restaurant_id });
+
+    let response = kitchen_client
+        .get_kitchen_capacity(request)
+        .await
+        .map_err(|e| {
+            if e.code() == tonic::Code::NotFound {
+                ApiError::ServiceUnavailable("Restaurant not found".to_string())
+            } else {
+                ApiError::ServiceUnavailable(format!("Kitchen service error: {e}"))
+            }
+        })?;
+
+    Ok(Json(capacity_to_response(response.into_inner())?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/restaurants/{restaurant_id}/kitchen/capacity",
+    request_body = SetKitchenCapacityRequest,
+    responses(
+        (status = 200, description = "Kitchen capacity updated, new orders over it are rejected", body = KitchenCapacityResponse),
+        (status = 401, description = "Unauthorized", body = ApiErrorResponse),
+        (status = 503, description = "Service unavailable or restaurant not found", body = ApiErrorResponse),
+    ),
+    params(
+        ("restaurant_id" = String, Path, description = "Restaurant ID")
+    ),
+    security(
+        ("bearer" = [])
+    ),
+    tag = "kitchen"
+)]
+#[instrument(skip(state))]
+pub async fn set_kitchen_capacity(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(restaurant_id): Path<String>,
+    Json(payload): Json<SetKitchenCapacityRequest>,
+) -> Result<Json<KitchenCapacityResponse>, ApiError> {
+    let mut auth_client = state.auth_client.clone();
+
+    // Verify user has access to this restaurant
+    verify_restaurant_access(&headers, &mut auth_client, &restaurant_id).await?;
+
+    let mut kitchen_client = state.kitchen_client.clone();
+
+    let request = tonic::Request::new(SetKitchenCapacityPayload {
+        restaurant_id,
+        max_open_tickets: payload.max_open_tickets,
+    });
+
+    let response = kitchen_client
+        .set_kitchen_capacity(request)
+        .await
+        .map_err(|e| {
+            if e.code() == tonic::Code::NotFound {
+                ApiError::ServiceUnavailable("Restaurant not found".to_string())
+            } else {
+                ApiError::ServiceUnavailable(format!("Kitchen service error: {e}"))
+            }
+        })?;
+
+    Ok(Json(capacity_to_response(response.into_inner())?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/restaurants/{restaurant_id}/tickets/stream",
+    responses(
+        (status = 200, description = "Server-sent `ticket` events: the open tickets first, then every ticket as it changes", content_type = "text/event-stream", body = KitchenTicket),
+        (status = 401, description = "Unauthorized", body = ApiErrorResponse),
+        (status = 503, description = "Service unavailable", body = ApiErrorResponse),
+    ),
+    params(
+        ("restaurant_id" = String, Path, description = "Restaurant ID")
+    ),
+    security(
+        ("bearer" = [])
+    ),
+    tag = "kitchen"
+)]
+#[instrument(skip(state))]
+pub async fn watch_tickets(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(restaurant_id): Path<String>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, ApiError> {
+    let mut auth_client = state.auth_client.clone();
+
+    // Verify user has access to this restaurant
+    verify_restaurant_access(&headers, &mut auth_client, &restaurant_id).await?;
+
+    let mut kitchen_client = state.kitchen_client.clone();
+
+    let request = tonic::Request::new(WatchTicketsPayload { restaurant_id });
+
+    let response = kitchen_client
+        .watch_tickets(request)
+        .await
+        .map_err(|e| ApiError::ServiceUnavailable(format!("Kitchen service error: {e}")))?;
+
+    // The stream ends when the kitchen service fails, clients reconnect to resync
+    let events = response.into_inner().map_while(|ticket| {
+        let kitchen_ticket = ticket_to_kitchen_ticket(ticket.ok()?).ok()?;
+        let event = Event::default()
+            .event("ticket")
+            .json_data(kitchen_ticket)
+            .ok()?;
+        Some(Ok(event))
+    });
+
+    Ok(Sse::new(events).keep_alive(KeepAlive::default()))
+}
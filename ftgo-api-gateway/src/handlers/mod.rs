@@ -1,4 +1,5 @@
 pub mod accounting;
+pub mod admin;
 pub mod auth;
 pub mod consumer;
 pub mod delivery;
@@ -8,6 +9,7 @@ pub mod restaurant;
 
 // Re-export routers for easier importing
 pub use accounting::router as accounting_router;
+pub use admin::router as admin_router;
 pub use auth::router as auth_router;
 pub use consumer::router as consumer_router;
 pub use delivery::router as delivery_router;
@@ -30,6 +32,8 @@ pub struct AppState {
     pub consumer_client:
         ftgo_proto::consumer_service::consumer_service_client::ConsumerServiceClient<Channel>,
     pub order_client: ftgo_proto::order_service::order_service_client::OrderServiceClient<Channel>,
+    pub saga_admin_client:
+        ftgo_proto::order_service::saga_admin_service_client::SagaAdminServiceClient<Channel>,
     pub restaurant_client:
         ftgo_proto::restaurant_service::restaurant_service_client::RestaurantServiceClient<Channel>,
     pub kitchen_client:
@@ -38,6 +42,8 @@ pub struct AppState {
         ftgo_proto::delivery_service::delivery_service_client::DeliveryServiceClient<Channel>,
     pub accounting_client:
         ftgo_proto::accounting_service::accounting_service_client::AccountingServiceClient<Channel>,
+    /// Users allowed to call the `/admin` routes
+    pub admin_user_ids: Vec<String>,
 }
 
 // Shared utility functions
@@ -168,6 +174,20 @@ async fn verify_order_access(
     }
 }
 
+async fn verify_admin_access(
+    headers: &HeaderMap,
+    auth_client: &mut AuthServiceClient<Channel>,
+    admin_user_ids: &[String],
+) -> Result<(), ApiError> {
+    let user_id = extract_user_id_from_token(headers, auth_client).await?;
+
+    if admin_user_ids.contains(&user_id) {
+        Ok(())
+    } else {
+        Err(ApiError::Forbidden)
+    }
+}
+
 #[derive(OpenApi)]
 #[openapi(
     paths(
@@ -179,14 +199,21 @@ async fn verify_order_access(
         restaurant::create_restaurant,
         restaurant::list_restaurants,
         restaurant::get_restaurant,
+        restaurant::set_menu_item_availability,
         order::create_order,
         order::get_order,
         order::list_orders,
+        order::cancel_order,
+        order::revise_order,
         kitchen::list_tickets,
         kitchen::get_ticket,
         kitchen::accept_ticket,
         kitchen::preparing_ticket,
         kitchen::ready_for_pickup_ticket,
+        kitchen::reject_ticket,
+        kitchen::watch_tickets,
+        kitchen::get_kitchen_capacity,
+        kitchen::set_kitchen_capacity,
         delivery::get_delivery_status,
         delivery::create_courier,
         delivery::get_courier,
@@ -197,6 +224,14 @@ async fn verify_order_access(
         accounting::get_account,
         accounting::deposit_account,
         accounting::withdraw_account,
+        accounting::list_account_transactions,
+        accounting::get_restaurant_balance,
+        accounting::reconcile_ledger,
+        admin::list_saga_instances,
+        admin::get_saga_instance,
+        admin::get_saga_history,
+        admin::retry_saga_step,
+        admin::compensate_saga,
     ),
     components(
         schemas(
@@ -216,21 +251,36 @@ async fn verify_order_access(
             crate::models::CreateOrderRequest,
             crate::models::CreateOrderResponse,
             crate::models::OrderItemRequest,
+            crate::models::ReviseOrderRequest,
             crate::models::OrderLineItem,
             crate::models::DeliveryInformation,
             crate::models::MenuItemRequest,
+            crate::models::SetMenuItemAvailabilityRequest,
             crate::models::KitchenTicket,
+            crate::models::RejectTicketRequest,
+            crate::models::SetKitchenCapacityRequest,
+            crate::models::KitchenCapacityResponse,
             crate::models::TicketLineItem,
             crate::models::ListTicketsResponse,
             crate::models::DeliveryStatusResponse,
             crate::models::AccountDetailsResponse,
             crate::models::DepositAccountRequest,
             crate::models::WithdrawAccountRequest,
+            crate::models::AccountTransactionResponse,
+            crate::models::AccountTransactionEdge,
+            crate::models::ListAccountTransactionsResponse,
+            crate::models::RestaurantBalanceResponse,
+            crate::models::LedgerReconciliationResponse,
             crate::models::CreateCourierResponse,
             crate::models::CourierDetailsResponse,
             crate::models::UpdateCourierAvailabilityRequest,
             crate::models::CourierPlanResponse,
             crate::models::CourierActionResponse,
+            crate::models::SagaInstanceResponse,
+            crate::models::SagaInstanceEdge,
+            crate::models::ListSagaInstancesResponse,
+            crate::models::SagaStepLogEntryResponse,
+            crate::models::SagaHistoryResponse,
             crate::models::ApiErrorResponse
         )
     ),
@@ -243,7 +293,8 @@ async fn verify_order_access(
         (name = "orders", description = "Order management endpoints"),
         (name = "kitchen", description = "Kitchen management endpoints"),
         (name = "delivery", description = "Delivery tracking endpoints"),
-        (name = "accounting", description = "Account balance and transaction endpoints")
+        (name = "accounting", description = "Account balance and transaction endpoints"),
+        (name = "admin", description = "Operator endpoints for inspecting and repairing sagas")
     ),
     info(
         title = "FTGO API Gateway",
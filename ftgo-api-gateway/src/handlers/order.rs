@@ -6,7 +6,8 @@ use axum::{
     routing::{get, post},
 };
 use ftgo_proto::order_service::{
-    CreateOrderPayload, GetOrderPayload, ListOrderPayload, MenuItemIdAndQuantity,
+    CancelOrderPayload, CreateOrderPayload, GetOrderPayload, ListOrderPayload,
+    MenuItemIdAndQuantity, Order, ReviseOrderPayload, RevisedOrderLineItem,
 };
 use serde::Deserialize;
 use tracing::instrument;
@@ -31,6 +32,8 @@ pub fn router() -> Router<AppState> {
     Router::new()
         .route("/orders", post(create_order).get(list_orders))
         .route("/orders/{id}", get(get_order))
+        .route("/orders/{id}/cancel", post(cancel_order))
+        .route("/orders/{id}/revise", post(revise_order))
 }
 
 #[utoipa::path(
@@ -81,53 +84,7 @@ pub async fn create_order(
         .await
         .map_err(|e| ApiError::ServiceUnavailable(format!("Order service error: {e}")))?;
 
-    let order = response.into_inner();
-
-    // Convert the OrderState enum to string
-    let state_str = match order.state {
-        0 => "APPROVAL_PENDING",
-        1 => "APPROVED",
-        2 => "REJECTED",
-        3 => "CANCEL_PENDING",
-        4 => "CANCELLED",
-        5 => "REVISION_PENDING",
-        _ => "UNKNOWN",
-    };
-
-    Ok(Json(CreateOrderResponse {
-        id: order.id.parse().map_err(|_| ApiError::InvalidToken)?,
-        state: state_str.to_string(),
-        consumer_id: order
-            .consumer_id
-            .parse()
-            .map_err(|_| ApiError::InvalidToken)?,
-        restaurant_id: order
-            .restaurant_id
-            .parse()
-            .map_err(|_| ApiError::InvalidToken)?,
-        line_items: order
-            .line_items
-            .into_iter()
-            .map(|item| crate::models::OrderLineItem {
-                quantity: item.quantity,
-                menu_item_id: item.menu_item_id,
-                name: item.name,
-                price: item.price.map(|p| p.amount).unwrap_or_default(),
-            })
-            .collect(),
-        delivery_information: crate::models::DeliveryInformation {
-            delivery_time: order
-                .delivery_information
-                .as_ref()
-                .and_then(|di| di.delivery_time.as_ref())
-                .and_then(|ts| chrono::DateTime::from_timestamp(ts.seconds, ts.nanos as u32)),
-            delivery_address: order
-                .delivery_information
-                .map(|di| di.delivery_address)
-                .unwrap_or_default(),
-        },
-        order_minimum: order.order_minimum.map(|m| m.amount).unwrap_or_default(),
-    }))
+    Ok(Json(serialize_order(response.into_inner())?))
 }
 
 #[utoipa::path(
@@ -177,51 +134,7 @@ pub async fn get_order(
     )
     .await?;
 
-    // Convert the OrderState enum to string
-    let state_str = match order.state {
-        0 => "APPROVAL_PENDING",
-        1 => "APPROVED",
-        2 => "REJECTED",
-        3 => "CANCEL_PENDING",
-        4 => "CANCELLED",
-        5 => "REVISION_PENDING",
-        _ => "UNKNOWN",
-    };
-
-    Ok(Json(CreateOrderResponse {
-        id: order.id.parse().map_err(|_| ApiError::InvalidToken)?,
-        state: state_str.to_string(),
-        consumer_id: order
-            .consumer_id
-            .parse()
-            .map_err(|_| ApiError::InvalidToken)?,
-        restaurant_id: order
-            .restaurant_id
-            .parse()
-            .map_err(|_| ApiError::InvalidToken)?,
-        line_items: order
-            .line_items
-            .into_iter()
-            .map(|item| crate::models::OrderLineItem {
-                quantity: item.quantity,
-                menu_item_id: item.menu_item_id,
-                name: item.name,
-                price: item.price.map(|p| p.amount).unwrap_or_default(),
-            })
-            .collect(),
-        delivery_information: crate::models::DeliveryInformation {
-            delivery_time: order
-                .delivery_information
-                .as_ref()
-                .and_then(|di| di.delivery_time.as_ref())
-                .and_then(|ts| chrono::DateTime::from_timestamp(ts.seconds, ts.nanos as u32)),
-            delivery_address: order
-                .delivery_information
-                .map(|di| di.delivery_address)
-                .unwrap_or_default(),
-        },
-        order_minimum: order.order_minimum.map(|m| m.amount).unwrap_or_default(),
-    }))
+    Ok(Json(serialize_order(order)?))
 }
 
 #[utoipa::path(
@@ -342,56 +255,8 @@ pub async fn list_orders(
     let edges = filtered_edges
         .into_iter()
         .map(|edge| -> Result<crate::models::OrderEdge, ApiError> {
-            let order = edge.node.unwrap();
-
-            // Convert the OrderState enum to string
-            let state_str = match order.state {
-                0 => "APPROVAL_PENDING",
-                1 => "APPROVED",
-                2 => "REJECTED",
-                3 => "CANCEL_PENDING",
-                4 => "CANCELLED",
-                5 => "REVISION_PENDING",
-                _ => "UNKNOWN",
-            };
-
             Ok(crate::models::OrderEdge {
-                node: CreateOrderResponse {
-                    id: order.id.parse().map_err(|_| ApiError::InvalidToken)?,
-                    state: state_str.to_string(),
-                    consumer_id: order
-                        .consumer_id
-                        .parse()
-                        .map_err(|_| ApiError::InvalidToken)?,
-                    restaurant_id: order
-                        .restaurant_id
-                        .parse()
-                        .map_err(|_| ApiError::InvalidToken)?,
-                    line_items: order
-                        .line_items
-                        .into_iter()
-                        .map(|item| crate::models::OrderLineItem {
-                            quantity: item.quantity,
-                            menu_item_id: item.menu_item_id,
-                            name: item.name,
-                            price: item.price.map(|p| p.amount).unwrap_or_default(),
-                        })
-                        .collect(),
-                    delivery_information: crate::models::DeliveryInformation {
-                        delivery_time: order
-                            .delivery_information
-                            .as_ref()
-                            .and_then(|di| di.delivery_time.as_ref())
-                            .and_then(|ts| {
-                                chrono::DateTime::from_timestamp(ts.seconds, ts.nanos as u32)
-                            }),
-                        delivery_address: order
-                            .delivery_information
-                            .map(|di| di.delivery_address)
-                            .unwrap_or_default(),
-                    },
-                    order_minimum: order.order_minimum.map(|m| m.amount).unwrap_or_default(),
-                },
+                node: serialize_order(edge.node.unwrap())?,
                 cursor: edge.cursor,
             })
         })
@@ -399,3 +264,186 @@ pub async fn list_orders(
 
     Ok(Json(ListOrdersResponse { edges }))
 }
+
+#[utoipa::path(
+    post,
+    path = "/orders/{id}/cancel",
+    responses(
+        (status = 200, description = "Order cancellation started", body = CreateOrderResponse),
+        (status = 401, description = "Unauthorized", body = ApiErrorResponse),
+        (status = 403, description = "Forbidden", body = ApiErrorResponse),
+        (status = 503, description = "Service unavailable, order not found or order cannot be cancelled", body = ApiErrorResponse),
+    ),
+    params(
+        ("id" = String, Path, description = "Order ID")
+    ),
+    security(
+        ("bearer" = [])
+    ),
+    tag = "orders"
+)]
+#[instrument(skip(state))]
+pub async fn cancel_order(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(order_id): Path<String>,
+) -> Result<Json<CreateOrderResponse>, ApiError> {
+    let mut order_client = state.order_client.clone();
+
+    let request = tonic::Request::new(GetOrderPayload {
+        id: order_id.clone(),
+    });
+
+    let response = order_client.get_order(request).await.map_err(|e| {
+        if e.code() == tonic::Code::NotFound {
+            ApiError::ServiceUnavailable("Order not found".to_string())
+        } else {
+            ApiError::ServiceUnavailable(format!("Order service error: {e}"))
+        }
+    })?;
+
+    let order = response.into_inner();
+
+    // Only the consumer who placed the order can cancel it
+    let mut auth_client = state.auth_client.clone();
+    verify_consumer_access(&headers, &mut auth_client, &order.consumer_id).await?;
+
+    let request = tonic::Request::new(CancelOrderPayload { id: order_id });
+
+    let response = order_client.cancel_order(request).await.map_err(|e| {
+        if e.code() == tonic::Code::FailedPrecondition {
+            ApiError::ServiceUnavailable(
+                "Order cannot be cancelled in its current state".to_string(),
+            )
+        } else {
+            ApiError::ServiceUnavailable(format!("Order service error: {e}"))
+        }
+    })?;
+
+    Ok(Json(serialize_order(response.into_inner())?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/orders/{id}/revise",
+    request_body = ReviseOrderRequest,
+    responses(
+        (status = 200, description = "Order revision started", body = CreateOrderResponse),
+        (status = 401, description = "Unauthorized", body = ApiErrorResponse),
+        (status = 403, description = "Forbidden", body = ApiErrorResponse),
+        (status = 503, description = "Service unavailable, order not found or order cannot be revised", body = ApiErrorResponse),
+    ),
+    params(
+        ("id" = String, Path, description = "Order ID")
+    ),
+    security(
+        ("bearer" = [])
+    ),
+    tag = "orders"
+)]
+#[instrument(skip(state))]
+pub async fn revise_order(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(order_id): Path<String>,
+    Json(payload): Json<ReviseOrderRequest>,
+) -> Result<Json<CreateOrderResponse>, ApiError> {
+    let mut order_client = state.order_client.clone();
+
+    let request = tonic::Request::new(GetOrderPayload {
+        id: order_id.clone(),
+    });
+
+    let response = order_client.get_order(request).await.map_err(|e| {
+        if e.code() == tonic::Code::NotFound {
+            ApiError::ServiceUnavailable("Order not found".to_string())
+        } else {
+            ApiError::ServiceUnavailable(format!("Order service error: {e}"))
+        }
+    })?;
+
+    let order = response.into_inner();
+
+    // Only the consumer who placed the order can revise it
+    let mut auth_client = state.auth_client.clone();
+    verify_consumer_access(&headers, &mut auth_client, &order.consumer_id).await?;
+
+    let request = tonic::Request::new(ReviseOrderPayload {
+        id: order_id,
+        delivery_time: payload.delivery_time.map(|t| prost_types::Timestamp {
+            seconds: t.timestamp(),
+            nanos: t.timestamp_subsec_nanos() as i32,
+        }),
+        revised_order_line_items: payload
+            .items
+            .into_iter()
+            .map(|item| RevisedOrderLineItem {
+                menu_item_id: item.menu_item_id,
+                quantity: item.quantity,
+            })
+            .collect(),
+    });
+
+    let response = order_client
+        .revise_order(request)
+        .await
+        .map_err(|e| match e.code() {
+            tonic::Code::FailedPrecondition => ApiError::ServiceUnavailable(
+                "Order cannot be revised in its current state".to_string(),
+            ),
+            tonic::Code::InvalidArgument => {
+                ApiError::ServiceUnavailable(format!("Invalid revision: {}", e.message()))
+            }
+            _ => ApiError::ServiceUnavailable(format!("Order service error: {e}")),
+        })?;
+
+    Ok(Json(serialize_order(response.into_inner())?))
+}
+
+fn serialize_order(order: Order) -> Result<CreateOrderResponse, ApiError> {
+    // Convert the OrderState enum to string
+    let state_str = match order.state {
+        0 => "APPROVAL_PENDING",
+        1 => "APPROVED",
+        2 => "REJECTED",
+        3 => "CANCEL_PENDING",
+        4 => "CANCELLED",
+        5 => "REVISION_PENDING",
+        _ => "UNKNOWN",
+    };
+
+    Ok(CreateOrderResponse {
+        id: order.id.parse().map_err(|_| ApiError::InvalidToken)?,
+        state: state_str.to_string(),
+        consumer_id: order
+            .consumer_id
+            .parse()
+            .map_err(|_| ApiError::InvalidToken)?,
+        restaurant_id: order
+            .restaurant_id
+            .parse()
+            .map_err(|_| ApiError::InvalidToken)?,
+        line_items: order
+            .line_items
+            .into_iter()
+            .map(|item| crate::models::OrderLineItem {
+                quantity: item.quantity,
+                menu_item_id: item.menu_item_id,
+                name: item.name,
+                price: item.price.map(|p| p.amount).unwrap_or_default(),
+            })
+            .collect(),
+        delivery_information: crate::models::DeliveryInformation {
+            delivery_time: order
+                .delivery_information
+                .as_ref()
+                .and_then(|di| di.delivery_time.as_ref())
+                .and_then(|ts| chrono::DateTime::from_timestamp(ts.seconds, ts.nanos as u32)),
+            delivery_address: order
+                .delivery_information
+                .map(|di| di.delivery_address)
+                .unwrap_or_default(),
+        },
+        order_minimum: order.order_minimum.map(|m| m.amount).unwrap_or_default(),
+    })
+}
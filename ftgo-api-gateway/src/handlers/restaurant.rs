@@ -3,19 +3,21 @@ use axum::{
     extract::{Path, State},
     http::HeaderMap,
     response::Json,
-    routing::{get, post},
+    routing::{get, post, put},
 };
 use ftgo_proto::{
     auth_service::GrantRestaurantToUserPayload,
     common::Money,
-    restaurant_service::{CreateRestaurantPayload, GetRestaurantPayload, MenuItem},
+    restaurant_service::{
+        CreateRestaurantPayload, GetRestaurantPayload, MenuItem, SetMenuItemAvailabilityPayload,
+    },
 };
 use tracing::instrument;
 
 use crate::error::ApiError;
 use crate::models::*;
 
-use super::{AppState, extract_user_id_from_token};
+use super::{AppState, extract_user_id_from_token, verify_restaurant_access};
 
 pub fn router() -> Router<AppState> {
     Router::new()
@@ -24,6 +26,10 @@ pub fn router() -> Router<AppState> {
             post(create_restaurant).get(list_restaurants),
         )
         .route("/restaurants/{id}", get(get_restaurant))
+        .route(
+            "/restaurants/{restaurant_id}/menu-items/{menu_item_id}/availability",
+            put(set_menu_item_availability),
+        )
 }
 
 #[utoipa::path(
@@ -58,6 +64,7 @@ pub async fn create_restaurant(
             id: item.id,
             name: item.name,
             price: Some(Money { amount: item.price }),
+            available: None,
         })
         .collect();
 
@@ -128,6 +135,7 @@ pub async fn list_restaurants(
                         id: item.id,
                         name: item.name,
                         price: item.price.map(|p| p.amount).unwrap_or_default(),
+                        available: item.available.unwrap_or(true),
                     })
                     .collect(),
             })
@@ -188,7 +196,67 @@ pub async fn get_restaurant(
                 id: item.id,
                 name: item.name,
                 price: item.price.map(|p| p.amount).unwrap_or_default(),
+                available: item.available.unwrap_or(true),
             })
             .collect(),
     }))
 }
+
+#[utoipa::path(
+    put,
+    path = "/restaurants/{restaurant_id}/menu-items/{menu_item_id}/availability",
+    request_body = SetMenuItemAvailabilityRequest,
+    responses(
+        (status = 200, description = "Menu item availability updated", body = MenuItemResponse),
+        (status = 401, description = "Unauthorized", body = ApiErrorResponse),
+        (status = 404, description = "Menu item not found", body = ApiErrorResponse),
+        (status = 503, description = "Service unavailable", body = ApiErrorResponse),
+    ),
+    params(
+        ("restaurant_id" = String, Path, description = "Restaurant ID"),
+        ("menu_item_id" = String, Path, description = "Menu item ID")
+    ),
+    security(
+        ("bearer" = [])
+    ),
+    tag = "restaurants"
+)]
+#[instrument(skip(state))]
+pub async fn set_menu_item_availability(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path((restaurant_id, menu_item_id)): Path<(String, String)>,
+    Json(payload): Json<SetMenuItemAvailabilityRequest>,
+) -> Result<Json<MenuItemResponse>, ApiError> {
+    let mut auth_client = state.auth_client.clone();
+
+    // Verify user has access to this restaurant
+    verify_restaurant_access(&headers, &mut auth_client, &restaurant_id).await?;
+
+    let mut restaurant_client = state.restaurant_client.clone();
+
+    let request = tonic::Request::new(SetMenuItemAvailabilityPayload {
+        restaurant_id,
+        menu_item_id,
+        available: payload.available,
+    });
+
+    let response = restaurant_client
+        .set_menu_item_availability(request)
+        .await
+        .map_err(|e| {
+            if e.code() == tonic::Code::NotFound {
+                ApiError::ServiceUnavailable("Menu item not found".to_string())
+            } else {
+                ApiError::ServiceUnavailable(format!("Restaurant service error: {e}"))
+            }
+        })?;
+
+    let item = response.into_inner();
+    Ok(Json(MenuItemResponse {
+        id: item.id,
+        name: item.name,
+        price: item.price.map(|p| p.amount).unwrap_or_default(),
+        available: item.available.unwrap_or(true),
+    }))
+}
@@ -6,7 +6,9 @@ use ftgo_proto::{
     consumer_service::consumer_service_client::ConsumerServiceClient,
     delivery_service::delivery_service_client::DeliveryServiceClient,
     kitchen_service::kitchen_service_client::KitchenServiceClient,
-    order_service::order_service_client::OrderServiceClient,
+    order_service::{
+        order_service_client::OrderServiceClient, saga_admin_service_client::SagaAdminServiceClient,
+    },
     restaurant_service::restaurant_service_client::RestaurantServiceClient,
 };
 use tower_http::cors::CorsLayer;
@@ -19,8 +21,8 @@ mod handlers;
 mod models;
 
 use handlers::{
-    ApiDoc, AppState, accounting_router, auth_router, consumer_router, delivery_router,
-    kitchen_router, order_router, restaurant_router,
+    ApiDoc, AppState, accounting_router, admin_router, auth_router, consumer_router,
+    delivery_router, kitchen_router, order_router, restaurant_router,
 };
 
 #[tokio::main]
@@ -42,10 +44,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         std::env::var("DELIVERY_SERVICE_ENDPOINT").expect("DELIVERY_SERVICE_ENDPOINT required");
     let accounting_service_endpoint =
         std::env::var("ACCOUNTING_SERVICE_ENDPOINT").expect("ACCOUNTING_SERVICE_ENDPOINT required");
+    let admin_user_ids = std::env::var("ADMIN_USER_IDS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|id| id.trim().to_string())
+        .filter(|id| !id.is_empty())
+        .collect();
 
     let auth_client = AuthServiceClient::connect(auth_service_endpoint).await?;
     let consumer_client = ConsumerServiceClient::connect(consumer_service_endpoint).await?;
-    let order_client = OrderServiceClient::connect(order_service_endpoint).await?;
+    let order_client = OrderServiceClient::connect(order_service_endpoint.clone()).await?;
+    let saga_admin_client = SagaAdminServiceClient::connect(order_service_endpoint).await?;
     let restaurant_client = RestaurantServiceClient::connect(restaurant_service_endpoint).await?;
     let kitchen_client = KitchenServiceClient::connect(kitchen_service_endpoint).await?;
     let delivery_client = DeliveryServiceClient::connect(delivery_service_endpoint).await?;
@@ -55,10 +64,12 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         auth_client,
         consumer_client,
         order_client,
+        saga_admin_client,
         restaurant_client,
         kitchen_client,
         delivery_client,
         accounting_client,
+        admin_user_ids,
     };
 
     let app = Router::new()
@@ -69,6 +80,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .merge(kitchen_router())
         .merge(delivery_router())
         .merge(accounting_router())
+        .merge(admin_router())
         .merge(SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi()))
         .with_state(state)
         .layer(CorsLayer::permissive());
@@ -123,6 +123,14 @@ pub struct MenuItemResponse {
     pub name: String,
     /// Price of the menu item (as string)
     pub price: String,
+    /// Whether the menu item can be ordered
+    pub available: bool,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetMenuItemAvailabilityRequest {
+    /// Whether the menu item can be ordered
+    pub available: bool,
 }
 
 #[derive(Debug, Serialize, ToSchema)]
@@ -151,6 +159,15 @@ pub struct OrderItemRequest {
     pub quantity: i32,
 }
 
+#[derive(Debug, Serialize, Deserialize, ToSchema)]
+pub struct ReviseOrderRequest {
+    /// New delivery time (keeps the current one when omitted)
+    pub delivery_time: Option<DateTime<Utc>>,
+    /// Menu items with their new quantities. A quantity of 0 removes the item and
+    /// menu items not yet in the order are added
+    pub items: Vec<OrderItemRequest>,
+}
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct CreateOrderResponse {
     /// Unique identifier for the order
@@ -247,6 +264,26 @@ pub struct AcceptTicketRequest {
     pub ready_by: DateTime<Utc>,
 }
 
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct RejectTicketRequest {
+    /// Why the restaurant cannot fulfil the order
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct SetKitchenCapacityRequest {
+    /// Tickets the kitchen takes at once before new orders are rejected, omit to remove the limit
+    pub max_open_tickets: Option<u32>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct KitchenCapacityResponse {
+    pub restaurant_id: Uuid,
+    pub max_open_tickets: Option<u32>,
+    /// Tickets being created, accepted or prepared
+    pub open_tickets: u32,
+}
+
 #[derive(Debug, Serialize, ToSchema)]
 pub struct KitchenTicketEdge {
     /// The ticket node
@@ -287,6 +324,55 @@ pub struct AccountDetailsResponse {
     pub consumer_id: Uuid,
     /// Current account balance
     pub balance: String,
+    /// Part of the balance held by pending order authorizations
+    pub held_balance: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AccountTransactionResponse {
+    /// `CREDIT` for money added to the account, `DEBIT` for money taken out
+    pub direction: String,
+    /// Transaction amount
+    pub amount: String,
+    /// Transaction description
+    pub description: Option<String>,
+    /// Order the transaction was made for
+    pub order_id: Option<Uuid>,
+    /// Account balance after the transaction
+    pub resulting_balance: String,
+    /// When the transaction was made
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct AccountTransactionEdge {
+    /// The transaction node
+    pub node: AccountTransactionResponse,
+    /// Cursor for pagination
+    pub cursor: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ListAccountTransactionsResponse {
+    /// List of account transaction edges with cursor information
+    pub edges: Vec<AccountTransactionEdge>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct RestaurantBalanceResponse {
+    pub restaurant_id: Uuid,
+    /// Amount owed to the restaurant for its orders, net of platform commission and refunds
+    pub balance: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct LedgerReconciliationResponse {
+    pub total_debits: String,
+    pub total_credits: String,
+    /// Whether debits and credits match, overall and within every ledger transaction
+    pub balanced: bool,
+    /// Ledger transactions whose entries do not sum to zero, as `{account_id}:{sequence}`
+    pub unbalanced_transactions: Vec<String>,
 }
 
 #[derive(Debug, Deserialize, ToSchema)]
@@ -344,3 +430,66 @@ pub struct CourierActionResponse {
     /// Scheduled time for the action
     pub scheduled_time: DateTime<Utc>,
 }
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SagaInstanceResponse {
+    /// Saga type (e.g., "create-order")
+    pub saga_type: String,
+    /// Unique identifier of the saga instance
+    pub saga_id: String,
+    /// Index of the step currently executing
+    pub currently_executing: i32,
+    /// Request ID of the last command sent by the saga
+    pub last_request_id: Option<String>,
+    /// Whether the saga has finished
+    pub end_state: bool,
+    /// Whether the saga is running its compensations
+    pub compensating: bool,
+    /// Whether the saga failed to compensate
+    pub failed: bool,
+    /// Decoded saga data
+    #[schema(value_type = Object)]
+    pub saga_data: serde_json::Value,
+    /// When the current step times out
+    pub step_deadline: Option<DateTime<Utc>>,
+    /// Number of times the current step has been re-sent
+    pub step_attempts: i32,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SagaInstanceEdge {
+    /// The saga instance node
+    pub node: SagaInstanceResponse,
+    /// Cursor for pagination
+    pub cursor: String,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ListSagaInstancesResponse {
+    /// List of saga instance edges with cursor information
+    pub edges: Vec<SagaInstanceEdge>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SagaStepLogEntryResponse {
+    /// Index of the step the entry belongs to
+    pub step_index: i32,
+    /// Whether the saga was compensating at the time
+    pub compensating: bool,
+    /// What happened (INVOKED, REPLIED, TIMED_OUT, COMPENSATION_STARTED, ENDED, FAILED or PARKED)
+    pub event: String,
+    /// Request ID of the command in flight
+    pub request_id: Option<String>,
+    /// Whether the reply reported success
+    pub reply_succeed: Option<bool>,
+    /// Hex-encoded body of the reply
+    pub reply_body: Option<String>,
+    /// When the entry was written
+    pub created_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct SagaHistoryResponse {
+    /// Step log entries, oldest first
+    pub entries: Vec<SagaStepLogEntryResponse>,
+}
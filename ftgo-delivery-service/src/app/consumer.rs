@@ -5,11 +5,12 @@ use std::{
 };
 
 use chrono::{DateTime, TimeDelta, Utc};
-use diesel::{dsl::insert_into, prelude::*, update, PgConnection};
+use diesel::{delete, dsl::insert_into, prelude::*, update, PgConnection};
 use dotenvy::dotenv;
 use ftgo_delivery_service::{establish_connection, models, schema};
 use ftgo_proto::{
     kitchen_service::{kitchen_event, KitchenEvent},
+    order_service::{order_event, OrderEvent},
     restaurant_service::{restaurant_event, RestaurantEvent},
 };
 use kafka::{
@@ -20,13 +21,18 @@ use prost::Message;
 use rand::seq::IndexedRandom;
 use uuid::Uuid;
 
-const RESTAURANT_EVENT_CHANNEL: &'static str = "restaurant.event";
-const KITCHEN_EVENT_CHANNEL: &'static str = "kitchen.event";
-const GROUP: &'static str = "delivery-service";
+const RESTAURANT_EVENT_CHANNEL: &str = "restaurant.event";
+const KITCHEN_EVENT_CHANNEL: &str = "kitchen.event";
+const ORDER_EVENT_CHANNEL: &str = "order.event";
+const GROUP: &str = "delivery-service";
 
+// Variants are named after the message type they carry. Every topic read here carries
+// events, so unlike in the other consumers all of them end in `Event`.
+#[allow(clippy::enum_variant_names)]
 enum AcceptedMessage {
     RestaurantEvent(RestaurantEvent),
     KitchenEvent(KitchenEvent),
+    OrderEvent(OrderEvent),
 }
 
 impl AcceptedMessage {
@@ -38,6 +44,9 @@ impl AcceptedMessage {
             KITCHEN_EVENT_CHANNEL => Some(AcceptedMessage::KitchenEvent(
                 KitchenEvent::decode(value).expect("Cannot decode kitchen event"),
             )),
+            ORDER_EVENT_CHANNEL => Some(AcceptedMessage::OrderEvent(
+                OrderEvent::decode(value).expect("Cannot decode order event"),
+            )),
             _ => None,
         }
     }
@@ -64,6 +73,7 @@ impl AcceptedMessage {
                         Ok(())
                     }
                     restaurant_event::Event::RestaurantMenuRevised(_) => Ok(()),
+                    restaurant_event::Event::MenuItemAvailabilityChanged(_) => Ok(()),
                 }
             }
 
@@ -83,11 +93,19 @@ impl AcceptedMessage {
                         })
                         .ok_or(())?;
 
-                    let delivery = deliveries
+                    let delivery = match deliveries
                         .select(models::Delivery::as_select())
                         .find(&did)
                         .first::<models::Delivery>(conn)
-                        .map_err(|_| ())?;
+                        .optional()
+                        .map_err(|_| ())?
+                    {
+                        Some(delivery) => delivery,
+                        None => {
+                            println!("Skip scheduling unknown delivery {}", did);
+                            return Ok(());
+                        }
+                    };
 
                     let mut rng = rand::rng();
                     let candidates = couriers
@@ -136,11 +154,106 @@ impl AcceptedMessage {
                 kitchen_event::Event::TicketCreated(_) => Ok(()),
                 kitchen_event::Event::TicketPreparingStarted(_) => Ok(()),
                 kitchen_event::Event::TicketPreparingCompleted(_) => Ok(()),
+                kitchen_event::Event::TicketPickedUp(_) => Ok(()),
+                kitchen_event::Event::TicketRejected(_) => Ok(()),
+            },
+
+            AcceptedMessage::OrderEvent(order_event) => match order_event.event.unwrap() {
+                order_event::Event::OrderCreated(event) => {
+                    use schema::deliveries::dsl::*;
+                    use schema::restaurants::dsl::*;
+
+                    let did = event.id.parse::<Uuid>().expect("Invalid order id");
+                    let rid = event
+                        .order_details
+                        .ok_or(())?
+                        .restaurant_id
+                        .parse::<Uuid>()
+                        .expect("Invalid restaurant id");
+
+                    let restaurant = match restaurants
+                        .select(models::Restaurant::as_select())
+                        .find(&rid)
+                        .first::<models::Restaurant>(conn)
+                        .optional()
+                        .map_err(|_| ())?
+                    {
+                        Some(restaurant) => restaurant,
+                        None => {
+                            // The restaurant is replicated from another topic and may lag behind
+                            println!(
+                                "Skip delivery of order {}: restaurant {} is not replicated yet",
+                                did, rid
+                            );
+                            return Ok(());
+                        }
+                    };
+
+                    // Delivery shares its id with the order (and the kitchen ticket)
+                    let delivery = models::Delivery {
+                        id: did,
+                        pickup_address: restaurant.address,
+                        state: models::DeliveryState::Pending,
+                        restaurant_id: rid,
+                        pickup_time: None,
+                        delivery_address: event.delivery_address,
+                        delivery_time: None,
+                        assigned_courier_id: None,
+                        ready_by: None,
+                    };
+
+                    insert_into(deliveries)
+                        .values(&delivery)
+                        .on_conflict_do_nothing()
+                        .execute(conn)
+                        .map_err(|_| ())?;
+
+                    Ok(())
+                }
+                order_event::Event::OrderRejected(event) => {
+                    let did = event.id.parse::<Uuid>().expect("Invalid order id");
+                    cancel_delivery(conn, &did)
+                }
+                order_event::Event::OrderCancelled(event) => {
+                    let did = event.id.parse::<Uuid>().expect("Invalid order id");
+                    cancel_delivery(conn, &did)
+                }
+                order_event::Event::OrderAuthorized(_) => Ok(()),
+                order_event::Event::OrderRevisionProposed(_) => Ok(()),
+                order_event::Event::OrderRevised(_) => Ok(()),
+                order_event::Event::OrderRevisionRejected(_) => Ok(()),
+                order_event::Event::OrderCancelRequested(_) => Ok(()),
+                order_event::Event::OrderCancelRejected(_) => Ok(()),
             },
         }
     }
 }
 
+/// Cancels a delivery that has not been picked up yet and drops its scheduled courier actions
+fn cancel_delivery(conn: &mut PgConnection, did: &Uuid) -> Result<(), ()> {
+    use schema::deliveries::dsl::*;
+
+    conn.transaction(|conn| {
+        let cancelled = update(deliveries)
+            .set(state.eq(models::DeliveryState::Cancelled))
+            .filter(id.eq(did))
+            .filter(state.eq_any([
+                models::DeliveryState::Pending,
+                models::DeliveryState::Scheduled,
+            ]))
+            .filter(pickup_time.is_null())
+            .execute(conn)?;
+        if cancelled > 0 {
+            delete(
+                schema::courier_actions::table.filter(schema::courier_actions::delivery_id.eq(did)),
+            )
+            .execute(conn)?;
+        }
+        Ok::<_, diesel::result::Error>(())
+    })
+    .map_err(|_| ())
+}
+
 pub fn main() {
     dotenv().ok();
     let kafka_url = env::var("KAFKA_URL").expect("KAFKA_URL must be set");
@@ -149,6 +262,7 @@ pub fn main() {
     let mut consumer = Consumer::from_hosts(vec![kafka_url])
         .with_topic(RESTAURANT_EVENT_CHANNEL.to_string())
         .with_topic(KITCHEN_EVENT_CHANNEL.to_string())
+        .with_topic(ORDER_EVENT_CHANNEL.to_string())
         .with_group(GROUP.to_string())
         .with_fallback_offset(FetchOffset::Earliest)
         .with_offset_storage(Some(GroupOffsetStorage::Kafka))
@@ -0,0 +1,2 @@
+DROP TRIGGER tickets_notify_changed ON tickets;
+DROP FUNCTION notify_ticket_changed();
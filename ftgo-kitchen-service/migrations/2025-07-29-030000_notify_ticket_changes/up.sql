@@ -0,0 +1,10 @@
+CREATE FUNCTION notify_ticket_changed() RETURNS trigger AS $$
+BEGIN
+    PERFORM pg_notify('ticket_changed', NEW.restaurant_id::text || ':' || NEW.id::text);
+    RETURN NEW;
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER tickets_notify_changed
+    AFTER INSERT OR UPDATE ON tickets
+    FOR EACH ROW EXECUTE FUNCTION notify_ticket_changed();
@@ -0,0 +1 @@
+ALTER TABLE restaurants DROP COLUMN max_open_tickets;
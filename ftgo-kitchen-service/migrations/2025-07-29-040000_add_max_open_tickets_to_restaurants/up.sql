@@ -0,0 +1 @@
+ALTER TABLE restaurants ADD COLUMN max_open_tickets integer;
@@ -0,0 +1 @@
+ALTER TABLE restaurant_menu_items ADD COLUMN available boolean not null default true;
@@ -1,12 +1,13 @@
 use std::{collections::HashMap, env, thread::sleep, time::Duration};
 
+use chrono::{DateTime, Utc};
 use diesel::{
     delete,
     dsl::{exists, insert_into},
-    query_dsl::methods::{FilterDsl, FindDsl, OrderDsl, SelectDsl},
+    query_dsl::QueryDsl,
     result::Error::NotFound,
-    select, update, BelongingToDsl, Connection, ExpressionMethods, PgConnection, RunQueryDsl,
-    SelectableHelper,
+    select, update, BelongingToDsl, Connection, ExpressionMethods, OptionalExtension, PgConnection,
+    RunQueryDsl, SelectableHelper,
 };
 use dotenvy::dotenv;
 use ftgo_kitchen_service::{
@@ -17,7 +18,12 @@ use ftgo_kitchen_service::{
 };
 use ftgo_proto::{
     common::CommandReply,
-    kitchen_service::{CreateTicketCommandReply, KitchenCommand},
+    delivery_service::{delivery_event, DeliveryEvent},
+    kitchen_service::{
+        CreateTicketCommandFailure, CreateTicketCommandReply, CreateTicketFailureReason,
+        KitchenCommand,
+    },
+    order_service::{order_event, OrderEvent},
     restaurant_service::{restaurant_event, RestaurantEvent},
 };
 use kafka::{
@@ -27,13 +33,17 @@ use kafka::{
 use prost::Message;
 use uuid::Uuid;
 
-const GROUP: &'static str = "kitchen-service";
+const GROUP: &str = "kitchen-service";
 
-const RESTAURANT_EVENT_CHANNEL: &'static str = "restaurant.event";
+const RESTAURANT_EVENT_CHANNEL: &str = "restaurant.event";
+const ORDER_EVENT_CHANNEL: &str = "order.event";
+const DELIVERY_EVENT_CHANNEL: &str = "delivery.event";
 
 enum AcceptedMessage {
     KitchenCommand(KitchenCommand),
     RestaurantEvent(RestaurantEvent),
+    OrderEvent(OrderEvent),
+    DeliveryEvent(DeliveryEvent),
 }
 
 impl AcceptedMessage {
@@ -45,6 +55,12 @@ impl AcceptedMessage {
             RESTAURANT_EVENT_CHANNEL => Some(AcceptedMessage::RestaurantEvent(
                 RestaurantEvent::decode(value).expect("Cannot decode restaurant event"),
             )),
+            ORDER_EVENT_CHANNEL => Some(AcceptedMessage::OrderEvent(
+                OrderEvent::decode(value).expect("Cannot decode order event"),
+            )),
+            DELIVERY_EVENT_CHANNEL => Some(AcceptedMessage::DeliveryEvent(
+                DeliveryEvent::decode(value).expect("Cannot decode delivery event"),
+            )),
             _ => None,
         }
     }
@@ -79,6 +95,49 @@ impl AcceptedMessage {
         Ok(())
     }
 
+    /// Moves a ticket to the state decided by `transition` and replies to the command.
+    ///
+    /// `transition` returns `Ok(Some((state, previous_state)))` to update the ticket,
+    /// `Ok(None)` when the command was already applied, and `Err(())` when the ticket
+    /// cannot make the transition.
+    fn transition_ticket(
+        conn: &mut PgConnection,
+        reply_channel: &Option<String>,
+        reply_state: &HashMap<String, String>,
+        tid: &Uuid,
+        transition: impl Fn(
+            &models::Ticket,
+        )
+            -> Result<Option<(models::TicketState, Option<models::TicketState>)>, ()>,
+    ) -> Result<(), ()> {
+        use schema::tickets::dsl::*;
+
+        conn.transaction(|conn| {
+            let succeed = match tickets
+                .select(models::Ticket::as_select())
+                .find(tid)
+                .for_update()
+                .first::<models::Ticket>(conn)
+            {
+                Ok(ticket) => match transition(&ticket) {
+                    Ok(Some((next_state, next_previous_state))) => {
+                        update(tickets)
+                            .set((state.eq(next_state), previous_state.eq(next_previous_state)))
+                            .filter(id.eq(ticket.id))
+                            .execute(conn)?;
+                        true
+                    }
+                    Ok(None) => true,
+                    Err(()) => false,
+                },
+                Err(NotFound) => false,
+                Err(err) => return Err(err),
+            };
+            Self::reply(conn, reply_channel, reply_state, succeed, None)
+        })
+        .map_err(|_| ())
+    }
+
     fn process(self, conn: &mut PgConnection) -> Result<(), ()> {
         match self {
             AcceptedMessage::KitchenCommand(kitchen_command) => {
@@ -96,19 +155,52 @@ impl AcceptedMessage {
 
                         let _ = conn
                             .transaction::<_, diesel::result::Error, _>(|conn| {
-                                let restaurant_exists = select(exists(
-                                    restaurants.filter(schema::restaurants::id.eq(rid)),
-                                ))
-                                .get_result::<bool>(conn)?;
-                                if !restaurant_exists {
+                                // Locked so concurrent creations cannot both take the last slot
+                                let Some(restaurant) = restaurants
+                                    .find(rid)
+                                    .select(models::Restaurant::as_select())
+                                    .for_update()
+                                    .first(conn)
+                                    .optional()?
+                                else {
+                                    let failure = CreateTicketCommandFailure {
+                                        reason: CreateTicketFailureReason::RestaurantNotFound
+                                            .into(),
+                                        open_tickets: 0,
+                                        max_open_tickets: None,
+                                    };
                                     Self::reply(
                                         conn,
                                         &kitchen_command.reply_channel,
                                         &kitchen_command.state,
                                         false,
-                                        None,
+                                        Some(failure.encode_to_vec()),
                                     )?;
                                     return Ok(());
+                                };
+
+                                if let Some(max) = restaurant.max_open_tickets {
+                                    let open_tickets = tickets
+                                        .filter(restaurant_id.eq(rid))
+                                        .filter(state.eq_any(models::ACTIVE_TICKET_STATES))
+                                        .count()
+                                        .get_result::<i64>(conn)?;
+                                    if open_tickets >= max.into() {
+                                        let failure = CreateTicketCommandFailure {
+                                            reason: CreateTicketFailureReason::KitchenAtCapacity
+                                                .into(),
+                                            open_tickets: open_tickets as u32,
+                                            max_open_tickets: Some(max as u32),
+                                        };
+                                        Self::reply(
+                                            conn,
+                                            &kitchen_command.reply_channel,
+                                            &kitchen_command.state,
+                                            false,
+                                            Some(failure.encode_to_vec()),
+                                        )?;
+                                        return Ok(());
+                                    }
                                 }
 
                                 let next_sequence = tickets
@@ -300,6 +392,145 @@ impl AcceptedMessage {
 
                         Ok(())
                     }
+                    ftgo_proto::kitchen_service::kitchen_command::Command::BeginCancelTicket(
+                        command,
+                    ) => {
+                        let tid = command.id.parse::<Uuid>().expect("Cannot decode ticket_id");
+                        Self::transition_ticket(conn, &kitchen_command.reply_channel, &kitchen_command.state, &tid, |ticket| {
+                            match ticket.state {
+                                models::TicketState::AwaitingAcceptance
+                                | models::TicketState::Accepted => Ok(Some((
+                                    models::TicketState::CancelPending,
+                                    Some(ticket.state),
+                                ))),
+                                // Cancelled already when the restaurant rejected the ticket
+                                models::TicketState::CancelPending
+                                | models::TicketState::Cancelled => Ok(None),
+                                _ => Err(()),
+                            }
+                        })
+                    }
+                    ftgo_proto::kitchen_service::kitchen_command::Command::UndoBeginCancelTicket(
+                        command,
+                    ) => {
+                        let tid = command.id.parse::<Uuid>().expect("Cannot decode ticket_id");
+                        Self::transition_ticket(conn, &kitchen_command.reply_channel, &kitchen_command.state, &tid, |ticket| {
+                            match (ticket.state, ticket.previous_state) {
+                                (models::TicketState::CancelPending, Some(previous)) => {
+                                    Ok(Some((previous, None)))
+                                }
+                                (models::TicketState::CancelPending, None) => Err(()),
+                                // Cancellation was never begun or already undone
+                                _ => Ok(None),
+                            }
+                        })
+                    }
+                    ftgo_proto::kitchen_service::kitchen_command::Command::ConfirmCancelTicket(
+                        command,
+                    ) => {
+                        let tid = command.id.parse::<Uuid>().expect("Cannot decode ticket_id");
+                        Self::transition_ticket(conn, &kitchen_command.reply_channel, &kitchen_command.state, &tid, |ticket| {
+                            match ticket.state {
+                                models::TicketState::CancelPending => Ok(Some((
+                                    models::TicketState::Cancelled,
+                                    Some(ticket.state),
+                                ))),
+                                models::TicketState::Cancelled => Ok(None),
+                                _ => Err(()),
+                            }
+                        })
+                    }
+                    ftgo_proto::kitchen_service::kitchen_command::Command::BeginReviseTicket(
+                        command,
+                    ) => {
+                        let tid = command.id.parse::<Uuid>().expect("Cannot decode ticket_id");
+                        Self::transition_ticket(conn, &kitchen_command.reply_channel, &kitchen_command.state, &tid, |ticket| {
+                            match ticket.state {
+                                // Revisions are only accepted before the kitchen accepts the ticket
+                                models::TicketState::AwaitingAcceptance => Ok(Some((
+                                    models::TicketState::RevisionPending,
+                                    Some(ticket.state),
+                                ))),
+                                models::TicketState::RevisionPending => Ok(None),
+                                _ => Err(()),
+                            }
+                        })
+                    }
+                    ftgo_proto::kitchen_service::kitchen_command::Command::UndoBeginReviseTicket(
+                        command,
+                    ) => {
+                        let tid = command.id.parse::<Uuid>().expect("Cannot decode ticket_id");
+                        Self::transition_ticket(conn, &kitchen_command.reply_channel, &kitchen_command.state, &tid, |ticket| {
+                            match (ticket.state, ticket.previous_state) {
+                                (models::TicketState::RevisionPending, Some(previous)) => {
+                                    Ok(Some((previous, None)))
+                                }
+                                (models::TicketState::RevisionPending, None) => Err(()),
+                                // Revision was never begun or already undone
+                                _ => Ok(None),
+                            }
+                        })
+                    }
+                    ftgo_proto::kitchen_service::kitchen_command::Command::ConfirmReviseTicket(
+                        command,
+                    ) => {
+                        use schema::tickets::dsl::*;
+                        let tid = command.id.parse::<Uuid>().expect("Cannot decode ticket_id");
+                        conn.transaction(|conn| {
+                            let succeed = match tickets
+                                .select(models::Ticket::as_select())
+                                .find(tid)
+                                .for_update()
+                                .first::<models::Ticket>(conn)
+                            {
+                                Ok(ticket) => match (ticket.state, ticket.previous_state) {
+                                    (models::TicketState::RevisionPending, Some(previous)) => {
+                                        use schema::ticket_line_items::dsl::*;
+
+                                        let line_items: Vec<models::TicketLineItem> = command
+                                            .details
+                                            .unwrap_or_default()
+                                            .line_items
+                                            .into_iter()
+                                            .map(|item| models::TicketLineItem {
+                                                ticket_id: ticket.id,
+                                                id: Uuid::new_v4(),
+                                                quantity: item.quantity,
+                                                menu_item_id: item.menu_item_id,
+                                                name: item.name,
+                                            })
+                                            .collect();
+
+                                        delete(ticket_line_items.filter(ticket_id.eq(ticket.id)))
+                                            .execute(conn)?;
+                                        insert_into(ticket_line_items)
+                                            .values(&line_items)
+                                            .execute(conn)?;
+
+                                        update(tickets)
+                                            .set((
+                                                state.eq(previous),
+                                                previous_state.eq(None::<models::TicketState>),
+                                            ))
+                                            .filter(schema::tickets::id.eq(ticket.id))
+                                            .execute(conn)?;
+                                        true
+                                    }
+                                    _ => false,
+                                },
+                                Err(NotFound) => false,
+                                Err(err) => return Err(err),
+                            };
+                            Self::reply(
+                                conn,
+                                &kitchen_command.reply_channel,
+                                &kitchen_command.state,
+                                succeed,
+                                None,
+                            )
+                        })
+                        .map_err(|_| ())
+                    }
                 }
             }
 
@@ -311,6 +542,7 @@ impl AcceptedMessage {
 
                         let restaurant = models::Restaurant {
                             id: event.id.parse::<Uuid>().unwrap(),
+                            max_open_tickets: None,
                         };
                         let menu_items = event
                             .menu_items
@@ -320,6 +552,7 @@ impl AcceptedMessage {
                                 id: i.id,
                                 name: i.name,
                                 price: i.price.unwrap().amount.parse().unwrap(),
+                                available: i.available.unwrap_or(true),
                             })
                             .collect::<Vec<_>>();
 
@@ -355,6 +588,7 @@ impl AcceptedMessage {
                                 id: i.id,
                                 name: i.name,
                                 price: i.price.unwrap().amount.parse().unwrap(),
+                                available: i.available.unwrap_or(true),
                             })
                             .collect::<Vec<_>>();
 
@@ -376,8 +610,131 @@ impl AcceptedMessage {
 
                         Ok(())
                     }
+                    restaurant_event::Event::MenuItemAvailabilityChanged(event) => {
+                        use schema::restaurant_menu_items::dsl::*;
+
+                        let rid = event.id.parse::<Uuid>().unwrap();
+                        update(restaurant_menu_items.find((rid, &event.menu_item_id)))
+                            .set(available.eq(event.available))
+                            .execute(conn)
+                            .expect("Error while change menu item availability");
+
+                        Ok(())
+                    }
                 }
             }
+
+            AcceptedMessage::OrderEvent(order_event) => match order_event.event.unwrap() {
+                order_event::Event::OrderCancelled(event) => {
+                    use schema::tickets::dsl::*;
+
+                    // The cancel-order saga confirms the ticket cancellation before the order,
+                    // so this only finishes a cancellation whose confirmation was lost
+                    let tid = event.id.parse::<Uuid>().expect("Invalid order id");
+                    update(tickets)
+                        .set((
+                            state.eq(models::TicketState::Cancelled),
+                            previous_state.eq(None::<models::TicketState>),
+                        ))
+                        .filter(id.eq(tid))
+                        .filter(state.eq(models::TicketState::CancelPending))
+                        .execute(conn)
+                        .map_err(|_| ())?;
+
+                    Ok(())
+                }
+                order_event::Event::OrderRevised(event) => {
+                    use schema::ticket_line_items::dsl::*;
+
+                    // Keep ticket line items in line with the revised order
+                    let tid = event.id.parse::<Uuid>().expect("Invalid order id");
+                    let line_items: Vec<models::TicketLineItem> = event
+                        .order_details
+                        .unwrap_or_default()
+                        .line_items
+                        .into_iter()
+                        .map(|item| models::TicketLineItem {
+                            ticket_id: tid,
+                            id: Uuid::new_v4(),
+                            quantity: item.quantity,
+                            menu_item_id: item.menu_item_id,
+                            name: item.name,
+                        })
+                        .collect();
+
+                    conn.transaction(|conn| {
+                        let ticket_exists = select(exists(
+                            schema::tickets::table.filter(schema::tickets::id.eq(tid)),
+                        ))
+                        .get_result::<bool>(conn)?;
+                        if !ticket_exists {
+                            return Ok(());
+                        }
+
+                        delete(ticket_line_items.filter(ticket_id.eq(tid))).execute(conn)?;
+                        insert_into(ticket_line_items)
+                            .values(&line_items)
+                            .execute(conn)?;
+
+                        Ok::<_, diesel::result::Error>(())
+                    })
+                    .map_err(|_| ())
+                }
+                order_event::Event::OrderCreated(_) => Ok(()),
+                order_event::Event::OrderAuthorized(_) => Ok(()),
+                order_event::Event::OrderRejected(_) => Ok(()),
+                order_event::Event::OrderRevisionProposed(_) => Ok(()),
+                order_event::Event::OrderRevisionRejected(_) => Ok(()),
+                order_event::Event::OrderCancelRequested(_) => Ok(()),
+                order_event::Event::OrderCancelRejected(_) => Ok(()),
+            },
+
+            AcceptedMessage::DeliveryEvent(delivery_event) => match delivery_event.event.unwrap() {
+                delivery_event::Event::DeliveryPickedUp(event) => {
+                    use schema::tickets::dsl::*;
+
+                    // Delivery shares its id with the order and the ticket
+                    let tid = event.id.parse::<Uuid>().expect("Invalid delivery id");
+                    let picked_up_at = event
+                        .picked_up_at
+                        .and_then(|t| DateTime::from_timestamp(t.seconds, t.nanos as u32))
+                        .unwrap_or_else(Utc::now);
+
+                    conn.transaction(|conn| {
+                        let ticket = match tickets
+                            .select(models::Ticket::as_select())
+                            .find(tid)
+                            .for_update()
+                            .first::<models::Ticket>(conn)
+                        {
+                            Ok(ticket) => ticket,
+                            Err(NotFound) => return Ok(()),
+                            Err(err) => return Err(err),
+                        };
+                        // Redelivered events find the ticket already picked up
+                        if ticket.state != models::TicketState::ReadyForPickup {
+                            return Ok(());
+                        }
+
+                        let ticket = update(tickets)
+                            .set((
+                                state.eq(models::TicketState::PickedUp),
+                                previous_state.eq(Some(ticket.state)),
+                                picked_up_time.eq(picked_up_at),
+                            ))
+                            .filter(id.eq(ticket.id))
+                            .returning(models::Ticket::as_returning())
+                            .get_result(conn)?;
+
+                        let mut publisher = KitchenEventPublisher::new(conn);
+                        publisher.ticket_picked_up(&ticket);
+
+                        Ok::<_, diesel::result::Error>(())
+                    })
+                    .map_err(|_| ())
+                }
+                delivery_event::Event::DeliveryDropoff(_) => Ok(()),
+            },
         }
     }
 }
@@ -390,6 +747,8 @@ pub fn main() {
     let mut consumer = Consumer::from_hosts(vec![kafka_url])
         .with_topic(COMMAND_CHANNEL.to_string())
         .with_topic(RESTAURANT_EVENT_CHANNEL.to_string())
+        .with_topic(ORDER_EVENT_CHANNEL.to_string())
+        .with_topic(DELIVERY_EVENT_CHANNEL.to_string())
         .with_group(GROUP.to_string())
         .with_fallback_offset(FetchOffset::Earliest)
         .with_offset_storage(Some(GroupOffsetStorage::Kafka))
@@ -1,29 +1,68 @@
+use std::pin::Pin;
+
 use chrono::Utc;
 use diesel::dsl::update;
 use diesel::prelude::*;
 use diesel::result::Error::NotFound;
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
 use ftgo_kitchen_service::events::KitchenEventPublisher;
+use ftgo_kitchen_service::listener::{TicketListener, TicketNotification};
 use ftgo_proto::kitchen_service::{
-    AcceptTicketPayload, GetTicketPayload, ListTicketPayload, ListTicketResponse,
-    PreparingTicketPayload, ReadyForPickupTicketPayload, Ticket, TicketEdge, TicketLineItem,
+    AcceptTicketPayload, GetKitchenCapacityPayload, GetTicketPayload, KitchenCapacity,
+    ListTicketPayload, ListTicketResponse, PreparingTicketPayload, ReadyForPickupTicketPayload,
+    RejectTicketPayload, SetKitchenCapacityPayload, Ticket, TicketEdge, TicketLineItem,
+    WatchTicketsPayload,
 };
 use prost_types::Timestamp;
+use tokio::sync::{
+    broadcast::{self, error::RecvError},
+    mpsc,
+};
+use tokio_stream::{wrappers::ReceiverStream, Stream};
 use tonic::transport::Server;
 use tonic::{Request, Response, Status};
 use uuid::Uuid;
 
 use ftgo_proto::kitchen_service::kitchen_service_server::{KitchenService, KitchenServiceServer};
 
-use ftgo_kitchen_service::{establish_connection, models, schema};
+use ftgo_kitchen_service::{database_url, establish_connection, models, schema};
 
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
 
-#[derive(Default)]
-pub struct KitchenServiceImpl {}
+/// States of the tickets shown on kitchen displays
+const OPEN_TICKET_STATES: [models::TicketState; 6] = [
+    models::TicketState::AwaitingAcceptance,
+    models::TicketState::Accepted,
+    models::TicketState::Preparing,
+    models::TicketState::ReadyForPickup,
+    models::TicketState::CancelPending,
+    models::TicketState::RevisionPending,
+];
+
+/// How many tickets are buffered for a watcher that reads slower than tickets change
+const WATCH_BUFFER: usize = 32;
+
+/// How many loaded changes a slow watcher may fall behind before it has to resync
+const UPDATE_CAPACITY: usize = 256;
+
+#[derive(Clone, Debug)]
+enum TicketUpdate {
+    Changed {
+        restaurant_id: Uuid,
+        tickets: Vec<Ticket>,
+    },
+    /// Changes may have been missed, so every watcher reloads its open tickets
+    Resync,
+}
+
+pub struct KitchenServiceImpl {
+    ticket_updates: broadcast::Sender<TicketUpdate>,
+}
 
 #[tonic::async_trait]
 impl KitchenService for KitchenServiceImpl {
+    type WatchTicketsStream = Pin<Box<dyn Stream<Item = Result<Ticket, Status>> + Send>>;
+
     async fn list_tickets(
         &self,
         request: Request<ListTicketPayload>,
@@ -355,6 +394,309 @@ impl KitchenService for KitchenServiceImpl {
 
         Ok(Response::new(serialize_ticket(&ticket, &line_items)))
     }
+
+    async fn reject_ticket(
+        &self,
+        request: Request<RejectTicketPayload>,
+    ) -> Result<Response<Ticket>, Status> {
+        use schema::tickets::dsl::*;
+
+        let payload = request.into_inner();
+        let tid = payload
+            .ticket_id
+            .parse::<Uuid>()
+            .map_err(|_| Status::invalid_argument("Invalid ticket_id"))?;
+        let rid = payload
+            .restaurant_id
+            .parse::<Uuid>()
+            .map_err(|_| Status::invalid_argument("Invalid restaurant_id"))?;
+        if payload.reason.trim().is_empty() {
+            return Err(Status::invalid_argument("reason must be set"));
+        }
+
+        enum Error {
+            NotFound,
+            UnsupportedStateTransition,
+            Unexpected,
+        }
+
+        impl From<diesel::result::Error> for Error {
+            fn from(_: diesel::result::Error) -> Self {
+                Error::Unexpected
+            }
+        }
+
+        let conn = &mut establish_connection();
+        let (ticket, line_items) = conn
+            .transaction(|conn| {
+                // A ticket of another restaurant is reported as missing
+                let result: models::Ticket = tickets
+                    .find(&tid)
+                    .filter(restaurant_id.eq(rid))
+                    .select(models::Ticket::as_select())
+                    .for_update()
+                    .first(conn)
+                    .map_err(|err| match err {
+                        diesel::result::Error::NotFound => Error::NotFound,
+                        _ => Error::Unexpected,
+                    })?;
+                let line_items = models::TicketLineItem::belonging_to(&result)
+                    .select(models::TicketLineItem::as_select())
+                    .load(conn)
+                    .map_err(|_| Error::Unexpected)?;
+
+                // Same states the cancel-order saga can cancel from, so the order can be refunded
+                if !matches!(
+                    result.state,
+                    models::TicketState::AwaitingAcceptance | models::TicketState::Accepted
+                ) {
+                    return Err(Error::UnsupportedStateTransition);
+                }
+
+                let result = update(tickets)
+                    .set((
+                        state.eq(models::TicketState::Cancelled),
+                        previous_state.eq(Some(result.state)),
+                    ))
+                    .filter(id.eq(tid))
+                    .returning(models::Ticket::as_returning())
+                    .get_result(conn)?;
+
+                let mut publisher = KitchenEventPublisher::new(conn);
+                publisher.ticket_rejected(&result, &payload.reason);
+
+                Ok((result, line_items))
+            })
+            .map_err(|err| match err {
+                Error::NotFound => Status::not_found("Ticket not found"),
+                Error::UnsupportedStateTransition => {
+                    Status::failed_precondition("Unsupported state transition")
+                }
+                _ => Status::internal("Error loading ticket"),
+            })?;
+
+        Ok(Response::new(serialize_ticket(&ticket, &line_items)))
+    }
+
+    async fn watch_tickets(
+        &self,
+        request: Request<WatchTicketsPayload>,
+    ) -> Result<Response<Self::WatchTicketsStream>, Status> {
+        let payload = request.into_inner();
+        let rid = payload
+            .restaurant_id
+            .parse::<Uuid>()
+            .map_err(|_| Status::invalid_argument("Invalid restaurant_id"))?;
+
+        // Subscribed before reading the open tickets, so that no change falls in between
+        let mut updates = self.ticket_updates.subscribe();
+        let open_tickets = load_blocking(move |conn| load_open_tickets(conn, &rid)).await?;
+
+        let (sender, receiver) = mpsc::channel(WATCH_BUFFER);
+        tokio::spawn(async move {
+            let mut pending = open_tickets;
+            loop {
+                for ticket in pending.drain(..) {
+                    if sender.send(Ok(ticket)).await.is_err() {
+                        return;
+                    }
+                }
+                let update = tokio::select! {
+                    _ = sender.closed() => return,
+                    update = updates.recv() => update,
+                };
+                let loaded = match update {
+                    Ok(TicketUpdate::Changed {
+                        restaurant_id,
+                        tickets,
+                    }) if restaurant_id == rid => Ok(tickets),
+                    Ok(TicketUpdate::Changed { .. }) => continue,
+                    // Changes may have been missed, send every open ticket again
+                    Ok(TicketUpdate::Resync) | Err(RecvError::Lagged(_)) => {
+                        load_blocking(move |conn| load_open_tickets(conn, &rid)).await
+                    }
+                    Err(RecvError::Closed) => return,
+                };
+                pending = match loaded {
+                    Ok(tickets) => tickets,
+                    Err(status) => {
+                        let _ = sender.send(Err(status)).await;
+                        return;
+                    }
+                };
+            }
+        });
+
+        Ok(Response::new(Box::pin(ReceiverStream::new(receiver))))
+    }
+
+    async fn get_kitchen_capacity(
+        &self,
+        request: Request<GetKitchenCapacityPayload>,
+    ) -> Result<Response<KitchenCapacity>, Status> {
+        use schema::restaurants::dsl::*;
+
+        let payload = request.into_inner();
+        let rid = payload
+            .restaurant_id
+            .parse::<Uuid>()
+            .map_err(|_| Status::invalid_argument("Invalid restaurant_id"))?;
+
+        let conn = &mut establish_connection();
+        let restaurant = restaurants
+            .find(rid)
+            .select(models::Restaurant::as_select())
+            .first(conn)
+            .map_err(|err| match err {
+                NotFound => Status::not_found("Restaurant not found"),
+                _ => Status::internal("Error loading restaurant"),
+            })?;
+        let capacity = load_capacity(conn, &restaurant)
+            .map_err(|_| Status::internal("Error loading tickets"))?;
+        Ok(Response::new(capacity))
+    }
+
+    async fn set_kitchen_capacity(
+        &self,
+        request: Request<SetKitchenCapacityPayload>,
+    ) -> Result<Response<KitchenCapacity>, Status> {
+        use schema::restaurants::dsl::*;
+
+        let payload = request.into_inner();
+        let rid = payload
+            .restaurant_id
+            .parse::<Uuid>()
+            .map_err(|_| Status::invalid_argument("Invalid restaurant_id"))?;
+        let max = payload
+            .max_open_tickets
+            .map(i32::try_from)
+            .transpose()
+            .map_err(|_| Status::invalid_argument("max_open_tickets is too large"))?;
+
+        let conn = &mut establish_connection();
+        let restaurant = update(restaurants.find(rid))
+            .set(max_open_tickets.eq(max))
+            .returning(models::Restaurant::as_returning())
+            .get_result(conn)
+            .map_err(|err| match err {
+                NotFound => Status::not_found("Restaurant not found"),
+                _ => Status::internal("Error updating restaurant"),
+            })?;
+        let capacity = load_capacity(conn, &restaurant)
+            .map_err(|_| Status::internal("Error loading tickets"))?;
+        Ok(Response::new(capacity))
+    }
+}
+
+fn load_capacity(
+    conn: &mut PgConnection,
+    restaurant: &models::Restaurant,
+) -> QueryResult<KitchenCapacity> {
+    use schema::tickets::dsl::*;
+
+    let open_tickets = tickets
+        .filter(restaurant_id.eq(restaurant.id))
+        .filter(state.eq_any(models::ACTIVE_TICKET_STATES))
+        .count()
+        .get_result::<i64>(conn)?;
+    Ok(KitchenCapacity {
+        restaurant_id: restaurant.id.to_string(),
+        max_open_tickets: restaurant.max_open_tickets.map(|max| max as u32),
+        open_tickets: open_tickets as u32,
+    })
+}
+
+/// Loads each changed ticket once on a single connection and shares it with every watcher,
+/// instead of letting each watcher query the ticket on its own
+fn spawn_ticket_loader(listener: TicketListener) -> broadcast::Sender<TicketUpdate> {
+    let (sender, _) = broadcast::channel(UPDATE_CAPACITY);
+    let mut notifications = listener.subscribe();
+    let updates = sender.clone();
+    tokio::spawn(async move {
+        let mut conn: Option<PgConnection> = None;
+        loop {
+            let update = match notifications.recv().await {
+                Ok(TicketNotification::Changed {
+                    restaurant_id,
+                    ticket_id,
+                }) => {
+                    if updates.receiver_count() == 0 {
+                        continue;
+                    }
+                    let idle = conn.take();
+                    let loaded = tokio::task::spawn_blocking(move || {
+                        let mut conn = idle.unwrap_or_else(establish_connection);
+                        load_ticket(&mut conn, &ticket_id).map(|tickets| (conn, tickets))
+                    })
+                    .await;
+                    match loaded {
+                        Ok(Ok((idle, tickets))) => {
+                            conn = Some(idle);
+                            TicketUpdate::Changed {
+                                restaurant_id,
+                                tickets,
+                            }
+                        }
+                        // The connection is dropped and opened again on the next change
+                        _ => TicketUpdate::Resync,
+                    }
+                }
+                Ok(TicketNotification::Resync) | Err(RecvError::Lagged(_)) => TicketUpdate::Resync,
+                Err(RecvError::Closed) => return,
+            };
+            // Fails only when no watcher is subscribed
+            let _ = updates.send(update);
+        }
+    });
+    sender
+}
+
+/// Runs a ticket query on its own connection off the async runtime
+async fn load_blocking(
+    load: impl FnOnce(&mut PgConnection) -> QueryResult<Vec<Ticket>> + Send + 'static,
+) -> Result<Vec<Ticket>, Status> {
+    tokio::task::spawn_blocking(move || load(&mut establish_connection()))
+        .await
+        .ok()
+        .and_then(|loaded| loaded.ok())
+        .ok_or_else(|| Status::internal("Error loading tickets"))
+}
+
+fn load_open_tickets(conn: &mut PgConnection, rid: &Uuid) -> QueryResult<Vec<Ticket>> {
+    use schema::tickets::dsl::*;
+
+    let results: Vec<models::Ticket> = tickets
+        .select(models::Ticket::as_select())
+        .filter(restaurant_id.eq(rid))
+        .filter(state.eq_any(OPEN_TICKET_STATES))
+        .order_by(sequence.asc())
+        .load(conn)?;
+    let line_items = models::TicketLineItem::belonging_to(&results)
+        .select(models::TicketLineItem::as_select())
+        .load(conn)?
+        .grouped_by(&results);
+    Ok(results
+        .iter()
+        .zip(line_items)
+        .map(|(ticket, line_items)| serialize_ticket(ticket, &line_items))
+        .collect())
+}
+
+/// Loads a changed ticket, unless it is still being created by the create-order saga
+fn load_ticket(conn: &mut PgConnection, tid: &Uuid) -> QueryResult<Vec<Ticket>> {
+    use schema::tickets::dsl::*;
+
+    let ticket: models::Ticket = tickets
+        .find(tid)
+        .select(models::Ticket::as_select())
+        .first(conn)?;
+    if ticket.state == models::TicketState::CreatePending {
+        return Ok(vec![]);
+    }
+    let line_items = models::TicketLineItem::belonging_to(&ticket)
+        .select(models::TicketLineItem::as_select())
+        .load(conn)?;
+    Ok(vec![serialize_ticket(&ticket, &line_items)])
 }
 
 fn serialize_ticket(ticket: &models::Ticket, line_items: &Vec<models::TicketLineItem>) -> Ticket {
@@ -400,7 +742,9 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .expect("Failed to run migrations");
 
     let addr = "0.0.0.0:8105".parse().unwrap();
-    let restaurant_service = KitchenServiceImpl::default();
+    let restaurant_service = KitchenServiceImpl {
+        ticket_updates: spawn_ticket_loader(TicketListener::spawn(database_url())),
+    };
 
     let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
     health_reporter
@@ -3,7 +3,8 @@ use crate::{models, models::NewOutbox, EVENT_CHANNEL};
 use diesel::{prelude::*, PgConnection};
 use ftgo_proto::kitchen_service::{
     kitchen_event, KitchenEvent, TicketAcceptedEvent, TicketCreatedEvent, TicketDetails,
-    TicketLineItem, TicketPreparingCompletedEvent, TicketPreparingStartedEvent,
+    TicketLineItem, TicketPickedUpEvent, TicketPreparingCompletedEvent,
+    TicketPreparingStartedEvent, TicketRejectedEvent,
 };
 use prost::Message;
 use prost_types::Timestamp;
@@ -110,4 +111,45 @@ impl<'a> KitchenEventPublisher<'a> {
             })
             .execute(self.conn);
     }
+
+    pub fn ticket_picked_up(&mut self, ticket: &models::Ticket) {
+        let event = KitchenEvent {
+            event: Some(kitchen_event::Event::TicketPickedUp(TicketPickedUpEvent {
+                id: ticket.id.to_string(),
+                picked_up_at: ticket.picked_up_time.map(|t| Timestamp {
+                    seconds: t.timestamp(),
+                    nanos: t.timestamp_subsec_nanos() as i32,
+                }),
+            })),
+        };
+        let mut buf = Vec::new();
+        event.encode(&mut buf).unwrap();
+
+        let _ = diesel::insert_into(schema::outbox::table)
+            .values(NewOutbox {
+                topic: EVENT_CHANNEL.to_string(),
+                key: ticket.restaurant_id.to_string(),
+                value: buf,
+            })
+            .execute(self.conn);
+    }
+
+    pub fn ticket_rejected(&mut self, ticket: &models::Ticket, reason: &str) {
+        let event = KitchenEvent {
+            event: Some(kitchen_event::Event::TicketRejected(TicketRejectedEvent {
+                id: ticket.id.to_string(),
+                reason: reason.to_string(),
+            })),
+        };
+        let mut buf = Vec::new();
+        event.encode(&mut buf).unwrap();
+
+        let _ = diesel::insert_into(schema::outbox::table)
+            .values(NewOutbox {
+                topic: EVENT_CHANNEL.to_string(),
+                key: ticket.restaurant_id.to_string(),
+                value: buf,
+            })
+            .execute(self.conn);
+    }
 }
@@ -4,15 +4,19 @@ use diesel::{Connection, PgConnection};
 use dotenvy::dotenv;
 
 pub mod events;
+pub mod listener;
 pub mod models;
 pub mod schema;
 
 pub const EVENT_CHANNEL: &str = "kitchen.event";
 pub const COMMAND_CHANNEL: &str = "kitchen.command";
 
-pub fn establish_connection() -> PgConnection {
+pub fn database_url() -> String {
     dotenv().ok();
 
-    let database_url = env::var("DATABASE_URL").expect("DATABASE_URL must be set");
-    PgConnection::establish(&database_url).unwrap()
+    env::var("DATABASE_URL").expect("DATABASE_URL must be set")
+}
+
+pub fn establish_connection() -> PgConnection {
+    PgConnection::establish(&database_url()).unwrap()
 }
@@ -0,0 +1,98 @@
+use std::time::Duration;
+
+use futures::{stream::poll_fn, StreamExt};
+use tokio::sync::broadcast;
+use tokio_postgres::{AsyncMessage, NoTls};
+use uuid::Uuid;
+
+/// Channel notified by the `tickets` trigger with `{restaurant_id}:{ticket_id}`
+pub const TICKET_CHANGED_CHANNEL: &str = "ticket_changed";
+
+/// How long to wait before listening again after the notification connection is lost
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// How many notifications a slow subscriber may fall behind before it has to resync
+const CAPACITY: usize = 256;
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TicketNotification {
+    Changed {
+        restaurant_id: Uuid,
+        ticket_id: Uuid,
+    },
+    /// Started listening, possibly after missing notifications while disconnected
+    Resync,
+}
+
+/// Shares a single `LISTEN` connection on ticket changes among the subscribers of a process
+pub struct TicketListener {
+    sender: broadcast::Sender<TicketNotification>,
+}
+
+impl TicketListener {
+    pub fn spawn(database_url: String) -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        tokio::spawn(Self::listen(database_url, sender.clone()));
+        Self { sender }
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<TicketNotification> {
+        self.sender.subscribe()
+    }
+
+    async fn listen(database_url: String, sender: broadcast::Sender<TicketNotification>) {
+        loop {
+            if let Err(err) = Self::listen_once(&database_url, &sender).await {
+                eprintln!("Ticket notifications are unavailable: {}", err);
+            }
+            tokio::time::sleep(RECONNECT_INTERVAL).await;
+        }
+    }
+
+    async fn listen_once(
+        database_url: &str,
+        sender: &broadcast::Sender<TicketNotification>,
+    ) -> Result<(), tokio_postgres::Error> {
+        let (client, mut connection) = tokio_postgres::connect(database_url, NoTls).await?;
+        let mut messages = poll_fn(move |cx| connection.poll_message(cx));
+
+        // The connection only makes progress while its messages are polled
+        let statement = format!("LISTEN {}", TICKET_CHANGED_CHANNEL);
+        let listen = client.batch_execute(&statement);
+        tokio::pin!(listen);
+        loop {
+            tokio::select! {
+                result = &mut listen => {
+                    result?;
+                    break;
+                }
+                message = messages.next() => match message {
+                    Some(message) => Self::handle(message?, sender),
+                    None => return Ok(()),
+                },
+            }
+        }
+        let _ = sender.send(TicketNotification::Resync);
+
+        while let Some(message) = messages.next().await {
+            Self::handle(message?, sender);
+        }
+        Ok(())
+    }
+
+    fn handle(message: AsyncMessage, sender: &broadcast::Sender<TicketNotification>) {
+        if let AsyncMessage::Notification(notification) = message {
+            let ids = notification
+                .payload()
+                .split_once(':')
+                .and_then(|(rid, tid)| Some((rid.parse().ok()?, tid.parse().ok()?)));
+            if let Some((restaurant_id, ticket_id)) = ids {
+                // Fails only when no one is subscribed
+                let _ = sender.send(TicketNotification::Changed {
+                    restaurant_id,
+                    ticket_id,
+                });
+            }
+        }
+    }
+}
@@ -27,6 +27,16 @@ pub enum TicketState {
     RevisionPending,
 }
 
+/// States of the tickets the kitchen still has to prepare, counted against its capacity
+pub const ACTIVE_TICKET_STATES: [TicketState; 6] = [
+    TicketState::CreatePending,
+    TicketState::AwaitingAcceptance,
+    TicketState::Accepted,
+    TicketState::Preparing,
+    TicketState::CancelPending,
+    TicketState::RevisionPending,
+];
+
 impl ToSql<crate::schema::sql_types::TicketState, Pg> for TicketState {
     fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
         match *self {
@@ -105,6 +115,8 @@ impl From<TicketState> for ftgo_proto::kitchen_service::TicketState {
 #[diesel(table_name = restaurants)]
 pub struct Restaurant {
     pub id: Uuid,
+    /// Tickets the kitchen takes at once before rejecting new orders, unlimited when unset
+    pub max_open_tickets: Option<i32>,
 }
 
 #[derive(Queryable, Selectable, Identifiable, Associations, Insertable, Debug, PartialEq)]
@@ -115,6 +127,7 @@ pub struct RestaurantMenuItem {
     pub id: String,
     pub name: String,
     pub price: BigDecimal,
+    pub available: bool,
 }
 
 #[derive(Queryable, Selectable, Identifiable, Insertable, Debug, PartialEq)]
@@ -22,12 +22,14 @@ diesel::table! {
         id -> Text,
         name -> Text,
         price -> Numeric,
+        available -> Bool,
     }
 }
 
 diesel::table! {
     restaurants (id) {
         id -> Uuid,
+        max_open_tickets -> Nullable<Int4>,
     }
 }
 
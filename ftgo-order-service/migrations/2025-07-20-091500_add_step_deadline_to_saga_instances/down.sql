@@ -0,0 +1,5 @@
+DROP INDEX ix_saga_instances_step_deadline;
+
+ALTER TABLE saga_instances
+    DROP COLUMN step_deadline,
+    DROP COLUMN step_attempts;
@@ -0,0 +1,5 @@
+ALTER TABLE saga_instances
+    ADD COLUMN step_deadline timestamptz,
+    ADD COLUMN step_attempts int NOT NULL DEFAULT 0;
+
+CREATE INDEX ix_saga_instances_step_deadline ON saga_instances (step_deadline) WHERE NOT end_state;
@@ -0,0 +1,2 @@
+DROP TABLE saga_step_log;
+DROP TYPE saga_step_event;
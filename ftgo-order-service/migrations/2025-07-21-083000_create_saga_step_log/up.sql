@@ -0,0 +1,24 @@
+CREATE TYPE saga_step_event AS ENUM (
+    'INVOKED',
+    'REPLIED',
+    'TIMED_OUT',
+    'COMPENSATION_STARTED',
+    'ENDED',
+    'FAILED'
+);
+
+CREATE TABLE saga_step_log (
+    id              bigserial       primary key,
+    saga_type       text            not null,
+    saga_id         text            not null,
+    step_index      int             not null,
+    compensating    boolean         not null,
+    event           saga_step_event not null,
+    request_id      text,
+    reply_succeed   boolean,
+    reply_body      bytea,
+    created_at      timestamptz     not null default now(),
+    foreign key (saga_type, saga_id) references saga_instances (saga_type, saga_id)
+);
+
+CREATE INDEX ix_saga_step_log_saga ON saga_step_log (saga_type, saga_id, id);
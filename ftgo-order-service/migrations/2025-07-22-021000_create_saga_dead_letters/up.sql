@@ -0,0 +1,9 @@
+CREATE TABLE saga_dead_letters (
+    id              bigserial       primary key,
+    saga_type       text,
+    saga_id         text,
+    request_id      text,
+    reason          text            not null,
+    reply           bytea           not null,
+    created_at      timestamptz     not null default now()
+);
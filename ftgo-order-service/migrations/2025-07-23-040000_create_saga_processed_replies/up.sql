@@ -0,0 +1,7 @@
+CREATE TABLE saga_processed_replies (
+    request_id      text            primary key,
+    saga_type       text            not null,
+    saga_id         text            not null,
+    processed_at    timestamptz     not null default now(),
+    foreign key (saga_type, saga_id) references saga_instances (saga_type, saga_id)
+);
@@ -0,0 +1 @@
+ALTER TABLE orders DROP COLUMN ticket_rejection_reason;
@@ -0,0 +1,12 @@
+DELETE FROM saga_step_log WHERE event = 'PARKED';
+ALTER TYPE saga_step_event RENAME TO saga_step_event_old;
+CREATE TYPE saga_step_event AS ENUM (
+    'INVOKED',
+    'REPLIED',
+    'TIMED_OUT',
+    'COMPENSATION_STARTED',
+    'ENDED',
+    'FAILED'
+);
+ALTER TABLE saga_step_log ALTER COLUMN event TYPE saga_step_event USING event::text::saga_step_event;
+DROP TYPE saga_step_event_old;
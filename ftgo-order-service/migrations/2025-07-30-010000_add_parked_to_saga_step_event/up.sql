@@ -0,0 +1 @@
+ALTER TYPE saga_step_event ADD VALUE 'PARKED';
@@ -1,16 +1,19 @@
 use std::{collections::HashMap, env, thread::sleep, time::Duration};
 
-use diesel::{delete, insert_into, prelude::*, Connection, ExpressionMethods, PgConnection};
+use diesel::{
+    delete, insert_into, prelude::*, update, Connection, ExpressionMethods, PgConnection,
+};
 use dotenvy::dotenv;
 use ftgo_order_service::{
-    command_handlers::handle_command,
+    command_handlers::{handle_command, handle_ticket_rejected},
     establish_connection,
     models::{self, NewOutbox},
-    saga::{create_order::CreateOrderSaga, SagaManager},
+    saga::{self, registry::SagaRegistry},
     schema, COMMAND_CHANNEL, REPLY_CHANNEL,
 };
 use ftgo_proto::{
     common::CommandReply,
+    kitchen_service::{kitchen_event, KitchenEvent},
     order_service::OrderCommand,
     restaurant_service::{restaurant_event, RestaurantEvent},
 };
@@ -21,13 +24,15 @@ use kafka::{
 use prost::Message;
 use uuid::Uuid;
 
-const GROUP: &'static str = "order-service";
+const GROUP: &str = "order-service";
 
-const RESTAURANT_EVENT_CHANNEL: &'static str = "restaurant.event";
+const RESTAURANT_EVENT_CHANNEL: &str = "restaurant.event";
+const KITCHEN_EVENT_CHANNEL: &str = "kitchen.event";
 
 enum AcceptedMessage {
     OrderCommand(OrderCommand),
     RestaurantEvent(RestaurantEvent),
+    KitchenEvent(KitchenEvent),
     CommandReply(CommandReply),
 }
 
@@ -40,6 +45,9 @@ impl AcceptedMessage {
             RESTAURANT_EVENT_CHANNEL => Some(AcceptedMessage::RestaurantEvent(
                 RestaurantEvent::decode(value).expect("Cannot decode restaurant event"),
             )),
+            KITCHEN_EVENT_CHANNEL => Some(AcceptedMessage::KitchenEvent(
+                KitchenEvent::decode(value).expect("Cannot decode kitchen event"),
+            )),
             REPLY_CHANNEL => Some(AcceptedMessage::CommandReply(
                 CommandReply::decode(value).expect("Cannot decode command reply"),
             )),
@@ -77,7 +85,7 @@ impl AcceptedMessage {
         Ok(())
     }
 
-    fn process(self, conn: &mut PgConnection) -> Result<(), ()> {
+    fn process(self, conn: &mut PgConnection, sagas: &SagaRegistry) -> Result<(), ()> {
         match self {
             AcceptedMessage::OrderCommand(order_command) => conn
                 .transaction(|conn| match handle_command(order_command.clone(), conn) {
@@ -99,12 +107,8 @@ impl AcceptedMessage {
                 .map_err(|_| ()),
 
             AcceptedMessage::CommandReply(command_reply) => {
-                let create_order_saga = CreateOrderSaga::new();
-                conn.transaction(|conn| {
-                    let mut saga_manager = SagaManager::new(create_order_saga, conn);
-                    saga_manager.handle_reply(&command_reply)
-                })
-                .map_err(|_| ())?;
+                conn.transaction(|conn| sagas.handle_reply(conn, &command_reply))
+                    .map_err(|_| ())?;
                 Ok(())
             }
 
@@ -123,6 +127,7 @@ impl AcceptedMessage {
                                 id: i.id,
                                 name: i.name,
                                 price: i.price.unwrap().amount.parse().unwrap(),
+                                available: i.available.unwrap_or(true),
                             })
                             .collect::<Vec<_>>();
 
@@ -156,6 +161,7 @@ impl AcceptedMessage {
                                 id: i.id,
                                 name: i.name,
                                 price: i.price.unwrap().amount.parse().unwrap(),
+                                available: i.available.unwrap_or(true),
                             })
                             .collect::<Vec<_>>();
 
@@ -175,10 +181,34 @@ impl AcceptedMessage {
                             })
                             .expect("Error while revise restaurant menu items");
 
+                        Ok(())
+                    }
+                    restaurant_event::Event::MenuItemAvailabilityChanged(event) => {
+                        let rid = event.id.parse::<Uuid>().unwrap();
+                        update(
+                            schema::restaurant_menu_items::table.find((rid, &event.menu_item_id)),
+                        )
+                        .set(schema::restaurant_menu_items::available.eq(event.available))
+                        .execute(conn)
+                        .expect("Error while change menu item availability");
+
                         Ok(())
                     }
                 }
             }
+
+            AcceptedMessage::KitchenEvent(kitchen_event) => match kitchen_event.event.unwrap() {
+                kitchen_event::Event::TicketRejected(event) => {
+                    // Ticket shares its id with the order
+                    let oid = event.id.parse::<Uuid>().expect("Invalid ticket id");
+                    handle_ticket_rejected(conn, &oid, &event.reason).map_err(|_| ())
+                }
+                kitchen_event::Event::TicketCreated(_) => Ok(()),
+                kitchen_event::Event::TicketAccepted(_) => Ok(()),
+                kitchen_event::Event::TicketPreparingStarted(_) => Ok(()),
+                kitchen_event::Event::TicketPreparingCompleted(_) => Ok(()),
+                kitchen_event::Event::TicketPickedUp(_) => Ok(()),
+            },
         }
     }
 }
@@ -188,9 +218,11 @@ pub fn main() {
     let kafka_url = env::var("KAFKA_URL").expect("KAFKA_URL must be set");
 
     let mut conn = establish_connection();
+    let sagas = saga::registry();
     let mut consumer = Consumer::from_hosts(vec![kafka_url])
         .with_topic(COMMAND_CHANNEL.to_string())
         .with_topic(RESTAURANT_EVENT_CHANNEL.to_string())
+        .with_topic(KITCHEN_EVENT_CHANNEL.to_string())
         .with_topic(REPLY_CHANNEL.to_string())
         .with_group(GROUP.to_string())
         .with_fallback_offset(FetchOffset::Earliest)
@@ -209,11 +241,14 @@ pub fn main() {
             for m in ms.messages() {
                 match AcceptedMessage::from(ms.topic(), m.value) {
                     Some(message) => {
-                        message.process(&mut conn).expect(&format!(
-                            "Failed to process message {} {}",
-                            ms.topic(),
-                            m.offset
-                        ));
+                        message.process(&mut conn, &sagas).unwrap_or_else(|err| {
+                            panic!(
+                                "Failed to process message {} {}: {:?}",
+                                ms.topic(),
+                                m.offset,
+                                err
+                            )
+                        });
                     }
                     None => {}
                 }
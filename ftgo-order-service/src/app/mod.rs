@@ -1,3 +1,6 @@
 pub mod consumer;
 pub mod producer;
 pub mod rpc;
+pub mod saga_admin;
+pub mod saga_history;
+pub mod saga_watchdog;
@@ -1,14 +1,17 @@
 use bigdecimal::BigDecimal;
-use chrono::{Duration, Utc};
-use diesel::{insert_into, prelude::*};
+use chrono::{DateTime, Duration, Utc};
+use diesel::{insert_into, prelude::*, update};
 use diesel_migrations::{embed_migrations, EmbeddedMigrations, MigrationHarness};
+use ftgo_order_service::command_handlers::begin_cancel;
 use ftgo_order_service::events::OrderEventPublisher;
 use ftgo_order_service::saga::create_order::{CreateOrderSaga, CreateOrderSagaState};
+use ftgo_order_service::saga::revise_order::{ReviseOrderSaga, ReviseOrderSagaState};
 use ftgo_order_service::saga::SagaManager;
 use ftgo_proto::common::Money;
 use ftgo_proto::order_service::{
-    CreateOrderPayload, DeliveryInformation, GetOrderPayload, ListOrderPayload, ListOrderResponse,
-    Order, OrderEdge, OrderLineItem, OrderState, PaymentInformation,
+    CancelOrderPayload, CreateOrderPayload, DeliveryInformation, GetOrderPayload, ListOrderPayload,
+    ListOrderResponse, Order, OrderEdge, OrderLineItem, OrderRevision, OrderState,
+    PaymentInformation, ReviseOrderPayload,
 };
 use prost_types::Timestamp;
 use tonic::transport::Server;
@@ -16,9 +19,12 @@ use tonic::{Request, Response, Status};
 use uuid::Uuid;
 
 use ftgo_proto::order_service::order_service_server::{OrderService, OrderServiceServer};
+use ftgo_proto::order_service::saga_admin_service_server::SagaAdminServiceServer;
 
 use ftgo_order_service::{establish_connection, models, schema};
 
+use super::saga_admin::SagaAdminServiceImpl;
+
 pub const MIGRATIONS: EmbeddedMigrations = embed_migrations!("./migrations");
 
 #[derive(Default)]
@@ -94,6 +100,7 @@ impl OrderService for OrderServiceImpl {
             delivery_address: payload.delivery_address.clone(),
             payment_token: None,
             created_at: Utc::now(),
+            ticket_rejection_reason: None,
         };
         let line_items = payload
             .items
@@ -106,6 +113,12 @@ impl OrderService for OrderServiceImpl {
                         "Menu item {} not exists",
                         i.menu_item_id
                     )))?;
+                if !menu_item.available {
+                    return Err(Status::invalid_argument(format!(
+                        "Menu item {} is not available",
+                        i.menu_item_id
+                    )));
+                }
                 Ok(models::OrderLineItem {
                     id: Uuid::new_v4(),
                     order_id: order.id.clone(),
@@ -229,6 +242,229 @@ impl OrderService for OrderServiceImpl {
 
         Ok(Response::new(ListOrderResponse { edges }))
     }
+
+    async fn cancel_order(
+        &self,
+        request: Request<CancelOrderPayload>,
+    ) -> Result<Response<Order>, Status> {
+        let payload = request.into_inner();
+        let oid: Uuid = payload
+            .id
+            .parse()
+            .map_err(|_| Status::invalid_argument("Invalid order id"))?;
+
+        enum Error {
+            NotFound,
+            UnsupportedStateTransition,
+            Unexpected,
+        }
+
+        impl From<diesel::result::Error> for Error {
+            fn from(_: diesel::result::Error) -> Self {
+                Error::Unexpected
+            }
+        }
+
+        let conn = &mut establish_connection();
+        conn.transaction(|conn| {
+            let order = schema::orders::table
+                .select(models::Order::as_select())
+                .find(&oid)
+                .for_update()
+                .get_result::<models::Order>(conn)
+                .map_err(|err| match err {
+                    diesel::result::Error::NotFound => Error::NotFound,
+                    _ => Error::Unexpected,
+                })?;
+            if order.state != models::OrderState::Approved {
+                return Err(Error::UnsupportedStateTransition);
+            }
+            let line_items = schema::order_line_items::table
+                .select(models::OrderLineItem::as_select())
+                .filter(schema::order_line_items::order_id.eq(&oid))
+                .get_results(conn)?;
+
+            let order = begin_cancel(conn, order, &line_items)?;
+
+            Ok(Response::new(serialize_order(order, line_items)))
+        })
+        .map_err(|err| match err {
+            Error::NotFound => Status::not_found("order not found"),
+            Error::UnsupportedStateTransition => {
+                Status::failed_precondition("Unsupported state transition")
+            }
+            Error::Unexpected => Status::internal("Internal server error"),
+        })
+    }
+
+    async fn revise_order(
+        &self,
+        request: Request<ReviseOrderPayload>,
+    ) -> Result<Response<Order>, Status> {
+        let payload = request.into_inner();
+        let oid: Uuid = payload
+            .id
+            .parse()
+            .map_err(|_| Status::invalid_argument("Invalid order id"))?;
+        let new_delivery_time = match payload.delivery_time {
+            Some(ts) => Some(
+                DateTime::<Utc>::from_timestamp(ts.seconds, ts.nanos as u32)
+                    .ok_or(Status::invalid_argument("Invalid delivery time"))?,
+            ),
+            None => None,
+        };
+        if payload
+            .revised_order_line_items
+            .iter()
+            .any(|i| i.quantity < 0)
+        {
+            return Err(Status::invalid_argument("Quantity must not be negative"));
+        }
+
+        enum Error {
+            NotFound,
+            UnsupportedStateTransition,
+            InvalidRevision(String),
+            Unexpected,
+        }
+
+        impl From<diesel::result::Error> for Error {
+            fn from(_: diesel::result::Error) -> Self {
+                Error::Unexpected
+            }
+        }
+
+        let conn = &mut establish_connection();
+        conn.transaction(|conn| {
+            let order = schema::orders::table
+                .select(models::Order::as_select())
+                .find(&oid)
+                .for_update()
+                .get_result::<models::Order>(conn)
+                .map_err(|err| match err {
+                    diesel::result::Error::NotFound => Error::NotFound,
+                    _ => Error::Unexpected,
+                })?;
+            if order.state != models::OrderState::Approved {
+                return Err(Error::UnsupportedStateTransition);
+            }
+            let line_items = schema::order_line_items::table
+                .select(models::OrderLineItem::as_select())
+                .filter(schema::order_line_items::order_id.eq(&oid))
+                .get_results(conn)?;
+            let restaurant_menu_items = schema::restaurant_menu_items::table
+                .select(models::RestaurantMenuItem::as_select())
+                .filter(schema::restaurant_menu_items::restaurant_id.eq(&order.restaurant_id))
+                .get_results(conn)?;
+
+            // Menu items of which more is ordered must still be available
+            let available_menu_item = |menu_item_id: &str| {
+                let menu_item = restaurant_menu_items
+                    .iter()
+                    .find(|m| m.id == menu_item_id)
+                    .ok_or(Error::InvalidRevision(format!(
+                        "Menu item {} not exists",
+                        menu_item_id
+                    )))?;
+                if !menu_item.available {
+                    return Err(Error::InvalidRevision(format!(
+                        "Menu item {} is not available",
+                        menu_item_id
+                    )));
+                }
+                Ok(menu_item)
+            };
+
+            // Revised quantities replace existing ones; unknown menu items are added
+            let mut new_line_items = line_items.clone();
+            for revised in payload.revised_order_line_items.iter() {
+                match new_line_items
+                    .iter_mut()
+                    .find(|li| li.menu_item_id == revised.menu_item_id)
+                {
+                    Some(line_item) => {
+                        if revised.quantity > line_item.quantity {
+                            available_menu_item(&revised.menu_item_id)?;
+                        }
+                        line_item.quantity = revised.quantity;
+                    }
+                    None => {
+                        let menu_item = available_menu_item(&revised.menu_item_id)?;
+                        new_line_items.push(models::OrderLineItem {
+                            id: Uuid::new_v4(),
+                            order_id: order.id,
+                            quantity: revised.quantity,
+                            menu_item_id: menu_item.id.clone(),
+                            name: menu_item.name.clone(),
+                            price: menu_item.price.clone(),
+                        });
+                    }
+                }
+            }
+            new_line_items.retain(|li| li.quantity > 0);
+            if new_line_items.is_empty() {
+                return Err(Error::InvalidRevision(
+                    "Revised order has no line items".to_string(),
+                ));
+            }
+
+            let current_order_total: BigDecimal =
+                line_items.iter().map(|li| li.total_price()).sum();
+            let new_order_total: BigDecimal =
+                new_line_items.iter().map(|li| li.total_price()).sum();
+            let delivery_time = new_delivery_time.unwrap_or(order.delivery_time);
+
+            update(schema::orders::table)
+                .set(schema::orders::state.eq(models::OrderState::RevisionPending))
+                .filter(schema::orders::id.eq(&oid))
+                .execute(conn)?;
+            let order = models::Order {
+                state: models::OrderState::RevisionPending,
+                ..order
+            };
+
+            let order_revision = OrderRevision {
+                id: order.id.to_string(),
+                delivery_information: Some(DeliveryInformation {
+                    delivery_time: Some(Timestamp {
+                        seconds: delivery_time.timestamp(),
+                        nanos: delivery_time.timestamp_subsec_nanos() as i32,
+                    }),
+                    delivery_address: order.delivery_address.to_string(),
+                }),
+                revised_order_line_items: payload.revised_order_line_items.clone(),
+            };
+            let mut publisher = OrderEventPublisher::new(conn);
+            publisher.order_revision_proposed(
+                &order,
+                &order_revision,
+                &current_order_total,
+                &new_order_total,
+            )?;
+
+            let saga_data = ReviseOrderSagaState::new(
+                &order.id,
+                &order.restaurant_id,
+                &order.consumer_id,
+                &current_order_total,
+                &new_order_total,
+                &new_line_items,
+                &delivery_time,
+            );
+            let mut saga_manager = SagaManager::new(ReviseOrderSaga::new(), conn);
+            saga_manager.create(saga_data)?;
+
+            Ok(Response::new(serialize_order(order, line_items)))
+        })
+        .map_err(|err| match err {
+            Error::NotFound => Status::not_found("order not found"),
+            Error::UnsupportedStateTransition => {
+                Status::failed_precondition("Unsupported state transition")
+            }
+            Error::InvalidRevision(message) => Status::invalid_argument(message),
+            Error::Unexpected => Status::internal("Internal server error"),
+        })
+    }
 }
 
 fn serialize_order(order: models::Order, line_items: Vec<models::OrderLineItem>) -> Order {
@@ -272,17 +508,22 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let addr = "0.0.0.0:8103".parse().unwrap();
     let order_service = OrderServiceImpl::default();
+    let saga_admin_service = SagaAdminServiceImpl::default();
 
     let (mut health_reporter, health_service) = tonic_health::server::health_reporter();
     health_reporter
         .set_serving::<OrderServiceServer<OrderServiceImpl>>()
         .await;
+    health_reporter
+        .set_serving::<SagaAdminServiceServer<SagaAdminServiceImpl>>()
+        .await;
 
     println!("listening on {}", addr);
 
     Server::builder()
         .add_service(health_service)
         .add_service(OrderServiceServer::new(order_service))
+        .add_service(SagaAdminServiceServer::new(saga_admin_service))
         .serve(addr)
         .await?;
 
@@ -0,0 +1,268 @@
+use diesel::{prelude::*, PgConnection};
+use ftgo_order_service::{
+    establish_connection,
+    models::{SagaInstance, SagaStepLog},
+    saga::{self, load_history, registry::RegisteredSaga},
+    schema,
+};
+use ftgo_proto::order_service::{
+    saga_admin_service_server::SagaAdminService, CompensateSagaPayload, GetSagaHistoryPayload,
+    GetSagaInstancePayload, ListSagaInstancesPayload, ListSagaInstancesResponse,
+    RetrySagaStepPayload, SagaHistory, SagaInstanceEdge, SagaStepLogEntry,
+};
+use prost_types::Timestamp;
+use tonic::{Request, Response, Status};
+
+#[derive(Default)]
+pub struct SagaAdminServiceImpl {}
+
+enum Error {
+    NotFound,
+    UnsupportedSagaType,
+    UnsupportedOperation(&'static str),
+    Unexpected,
+}
+
+impl From<diesel::result::Error> for Error {
+    fn from(_: diesel::result::Error) -> Self {
+        Error::Unexpected
+    }
+}
+
+impl From<Error> for Status {
+    fn from(err: Error) -> Self {
+        match err {
+            Error::NotFound => Status::not_found("saga instance not found"),
+            Error::UnsupportedSagaType => Status::failed_precondition("Unsupported saga type"),
+            Error::UnsupportedOperation(message) => Status::failed_precondition(message),
+            Error::Unexpected => Status::internal("Internal server error"),
+        }
+    }
+}
+
+enum Operation {
+    RetryStep,
+    Compensate,
+}
+
+#[tonic::async_trait]
+impl SagaAdminService for SagaAdminServiceImpl {
+    async fn list_saga_instances(
+        &self,
+        request: Request<ListSagaInstancesPayload>,
+    ) -> Result<Response<ListSagaInstancesResponse>, Status> {
+        use schema::saga_instances::dsl::*;
+
+        let payload = request.into_inner();
+        let conn = &mut establish_connection();
+
+        let mut query = saga_instances
+            .select(SagaInstance::as_select())
+            .into_boxed();
+
+        if let Some(t) = payload.saga_type {
+            query = query.filter(saga_type.eq(t));
+        }
+        if let Some(v) = payload.end_state {
+            query = query.filter(end_state.eq(v));
+        }
+        if let Some(v) = payload.compensating {
+            query = query.filter(compensating.eq(v));
+        }
+        if let Some(v) = payload.failed {
+            query = query.filter(failed.eq(v));
+        }
+
+        let limit = payload.first.unwrap_or(10).min(100) as i64;
+
+        if let Some(after) = payload.after {
+            // Parse cursor as "saga_type:saga_id"
+            let (after_saga_type, after_saga_id) = after
+                .split_once(':')
+                .ok_or_else(|| Status::invalid_argument("Invalid cursor format"))?;
+            query = query.filter(
+                saga_type.gt(after_saga_type.to_string()).or(saga_type
+                    .eq(after_saga_type.to_string())
+                    .and(saga_id.gt(after_saga_id.to_string()))),
+            );
+        }
+
+        let instances = query
+            .order((saga_type.asc(), saga_id.asc()))
+            .limit(limit)
+            .get_results::<SagaInstance>(conn)
+            .map_err(|_| Status::internal("Internal server error"))?;
+
+        let edges = instances
+            .into_iter()
+            .map(|instance| SagaInstanceEdge {
+                cursor: format!("{}:{}", instance.saga_type, instance.saga_id),
+                node: Some(serialize_saga_instance(instance)),
+            })
+            .collect();
+
+        Ok(Response::new(ListSagaInstancesResponse { edges }))
+    }
+
+    async fn get_saga_instance(
+        &self,
+        request: Request<GetSagaInstancePayload>,
+    ) -> Result<Response<ftgo_proto::order_service::SagaInstance>, Status> {
+        let payload = request.into_inner();
+        let conn = &mut establish_connection();
+
+        let instance = schema::saga_instances::table
+            .select(SagaInstance::as_select())
+            .find((&payload.saga_type, &payload.saga_id))
+            .get_result::<SagaInstance>(conn)
+            .map_err(|err| match err {
+                diesel::result::Error::NotFound => Error::NotFound,
+                _ => Error::Unexpected,
+            })?;
+
+        Ok(Response::new(serialize_saga_instance(instance)))
+    }
+
+    async fn retry_saga_step(
+        &self,
+        request: Request<RetrySagaStepPayload>,
+    ) -> Result<Response<ftgo_proto::order_service::SagaInstance>, Status> {
+        let payload = request.into_inner();
+        let conn = &mut establish_connection();
+
+        let instance = run_operation(
+            conn,
+            &payload.saga_type,
+            &payload.saga_id,
+            Operation::RetryStep,
+        )?;
+        Ok(Response::new(serialize_saga_instance(instance)))
+    }
+
+    async fn compensate_saga(
+        &self,
+        request: Request<CompensateSagaPayload>,
+    ) -> Result<Response<ftgo_proto::order_service::SagaInstance>, Status> {
+        let payload = request.into_inner();
+        let conn = &mut establish_connection();
+
+        let instance = run_operation(
+            conn,
+            &payload.saga_type,
+            &payload.saga_id,
+            Operation::Compensate,
+        )?;
+        Ok(Response::new(serialize_saga_instance(instance)))
+    }
+
+    async fn get_saga_history(
+        &self,
+        request: Request<GetSagaHistoryPayload>,
+    ) -> Result<Response<SagaHistory>, Status> {
+        let payload = request.into_inner();
+        let conn = &mut establish_connection();
+
+        let entries = load_history(conn, &payload.saga_type, &payload.saga_id)
+            .map_err(|_| Status::internal("Internal server error"))?;
+        if entries.is_empty() {
+            // Instances created before the step log existed have no history
+            schema::saga_instances::table
+                .select(SagaInstance::as_select())
+                .find((&payload.saga_type, &payload.saga_id))
+                .get_result::<SagaInstance>(conn)
+                .map_err(|err| match err {
+                    diesel::result::Error::NotFound => Error::NotFound,
+                    _ => Error::Unexpected,
+                })?;
+        }
+
+        Ok(Response::new(SagaHistory {
+            entries: entries.into_iter().map(serialize_saga_step_log).collect(),
+        }))
+    }
+}
+
+/// Locks the saga instance and hands it to the manager of its saga type.
+fn run_operation(
+    conn: &mut PgConnection,
+    saga_type: &str,
+    saga_id: &str,
+    operation: Operation,
+) -> Result<SagaInstance, Error> {
+    conn.transaction(|conn| {
+        let instance = schema::saga_instances::table
+            .select(SagaInstance::as_select())
+            .find((saga_type, saga_id))
+            .for_update()
+            .get_result::<SagaInstance>(conn)
+            .map_err(|err| match err {
+                diesel::result::Error::NotFound => Error::NotFound,
+                _ => Error::Unexpected,
+            })?;
+
+        if instance.end_state {
+            return Err(Error::UnsupportedOperation("Saga has already ended"));
+        }
+        if let (Operation::Compensate, true) = (&operation, instance.compensating) {
+            return Err(Error::UnsupportedOperation("Saga is already compensating"));
+        }
+
+        let sagas = saga::registry();
+        let saga = sagas
+            .get(&instance.saga_type)
+            .ok_or(Error::UnsupportedSagaType)?;
+        if let (Operation::Compensate, false) = (&operation, saga.can_compensate(&instance)) {
+            return Err(Error::UnsupportedOperation(
+                "Saga is past its point of no return",
+            ));
+        }
+        run_with(saga, conn, instance, operation)
+    })
+}
+
+fn run_with(
+    saga: &dyn RegisteredSaga,
+    conn: &mut PgConnection,
+    instance: SagaInstance,
+    operation: Operation,
+) -> Result<SagaInstance, Error> {
+    let instance = match operation {
+        Operation::RetryStep => saga.retry_step(conn, instance)?,
+        Operation::Compensate => saga.compensate(conn, instance)?,
+    };
+    Ok(instance)
+}
+
+fn serialize_saga_instance(instance: SagaInstance) -> ftgo_proto::order_service::SagaInstance {
+    ftgo_proto::order_service::SagaInstance {
+        saga_type: instance.saga_type,
+        saga_id: instance.saga_id,
+        currently_executing: instance.currently_executing,
+        last_request_id: instance.last_request_id,
+        end_state: instance.end_state,
+        compensating: instance.compensating,
+        failed: instance.failed,
+        saga_data_json: instance.saga_data_json.to_string(),
+        step_deadline: instance.step_deadline.map(|deadline| Timestamp {
+            seconds: deadline.timestamp(),
+            nanos: deadline.timestamp_subsec_nanos() as i32,
+        }),
+        step_attempts: instance.step_attempts,
+    }
+}
+
+fn serialize_saga_step_log(entry: SagaStepLog) -> SagaStepLogEntry {
+    SagaStepLogEntry {
+        id: entry.id,
+        step_index: entry.step_index,
+        compensating: entry.compensating,
+        event: ftgo_proto::order_service::SagaStepEvent::from(entry.event).into(),
+        request_id: entry.request_id,
+        reply_succeed: entry.reply_succeed,
+        reply_body: entry.reply_body,
+        created_at: Some(Timestamp {
+            seconds: entry.created_at.timestamp(),
+            nanos: entry.created_at.timestamp_subsec_nanos() as i32,
+        }),
+    }
+}
@@ -0,0 +1,44 @@
+use chrono::SecondsFormat;
+use dotenvy::dotenv;
+use ftgo_order_service::{establish_connection, models::SagaStepLog, saga::load_history};
+
+fn format_entry(entry: &SagaStepLog) -> String {
+    let mut line = format!(
+        "{}  step={:<2} {:<10} {:<20}",
+        entry
+            .created_at
+            .to_rfc3339_opts(SecondsFormat::Millis, true),
+        entry.step_index,
+        if entry.compensating {
+            "compensate"
+        } else {
+            "forward"
+        },
+        format!("{:?}", entry.event),
+    );
+    if let Some(request_id) = &entry.request_id {
+        line.push_str(&format!(" request={}", request_id));
+    }
+    if let Some(succeed) = entry.reply_succeed {
+        line.push_str(&format!(" succeed={}", succeed));
+    }
+    if let Some(body) = &entry.reply_body {
+        line.push_str(&format!(" body={} bytes", body.len()));
+    }
+    line
+}
+
+/// Prints the step log of a saga instance as a timeline
+pub fn main(saga_type: &str, saga_id: &str) {
+    dotenv().ok();
+
+    let conn = &mut establish_connection();
+    let entries = load_history(conn, saga_type, saga_id).expect("Failed to load saga history");
+    if entries.is_empty() {
+        eprintln!("No history for saga {} (id={})", saga_type, saga_id);
+        return;
+    }
+    for entry in entries.iter() {
+        println!("{}", format_entry(entry));
+    }
+}
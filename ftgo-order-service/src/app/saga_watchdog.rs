@@ -0,0 +1,79 @@
+use std::{thread::sleep, time::Duration};
+
+use chrono::Utc;
+use diesel::{prelude::*, update, Connection, PgConnection};
+use dotenvy::dotenv;
+use ftgo_order_service::{
+    establish_connection,
+    models::SagaInstance,
+    saga::{self, registry::SagaRegistry},
+    schema,
+};
+
+/// Picks the saga instance whose step deadline passed first and hands it to its manager.
+/// Returns false when there is no expired step.
+fn process_next_expired_step(
+    conn: &mut PgConnection,
+    sagas: &SagaRegistry,
+    max_retries: i32,
+) -> Result<bool, diesel::result::Error> {
+    use schema::saga_instances::dsl::*;
+
+    conn.transaction(|conn| {
+        let saga_instance = match saga_instances
+            .select(SagaInstance::as_select())
+            .filter(end_state.eq(false))
+            .filter(step_deadline.lt(Utc::now()))
+            .order(step_deadline.asc())
+            .for_update()
+            .skip_locked()
+            .first::<SagaInstance>(conn)
+            .optional()?
+        {
+            Some(saga_instance) => saga_instance,
+            None => return Ok(false),
+        };
+
+        match sagas.get(&saga_instance.saga_type) {
+            Some(saga) => {
+                saga.handle_timeout(conn, saga_instance, max_retries)?;
+            }
+            None => {
+                eprintln!(
+                    "Unknown saga type {} (id={}), disarming its deadline",
+                    saga_instance.saga_type, saga_instance.saga_id
+                );
+                update(saga_instances)
+                    .set(step_deadline.eq(None::<chrono::DateTime<Utc>>))
+                    .filter(
+                        saga_type
+                            .eq(&saga_instance.saga_type)
+                            .and(saga_id.eq(&saga_instance.saga_id)),
+                    )
+                    .execute(conn)?;
+            }
+        }
+
+        Ok(true)
+    })
+}
+
+pub fn main(max_retries: i32) {
+    dotenv().ok();
+
+    let conn = &mut establish_connection();
+    let sagas = saga::registry();
+
+    loop {
+        match process_next_expired_step(conn, &sagas, max_retries) {
+            Ok(true) => {}
+            Ok(false) => {
+                sleep(Duration::from_secs(1));
+            }
+            Err(err) => {
+                eprintln!("Error processing expired saga step: {:?}", err);
+                sleep(Duration::from_secs(1));
+            }
+        }
+    }
+}
@@ -1,9 +1,19 @@
-use diesel::{prelude::*, update, Connection, PgConnection};
+use bigdecimal::BigDecimal;
+use chrono::DateTime;
+use diesel::{delete, insert_into, prelude::*, update, Connection, PgConnection};
 use ftgo_proto::order_service::{order_command::Command, OrderCommand};
 use thiserror::Error;
 use uuid::Uuid;
 
-use crate::{events::OrderEventPublisher, models, schema};
+use crate::{
+    events::OrderEventPublisher,
+    models,
+    saga::{
+        cancel_order::{CancelOrderSaga, CancelOrderSagaState},
+        SagaManager,
+    },
+    schema,
+};
 
 pub fn handle_command(
     order_command: OrderCommand,
@@ -32,6 +42,8 @@ pub fn handle_command(
             let mut publisher = OrderEventPublisher::new(conn);
             publisher.order_authorized(&order)?;
 
+            cancel_if_ticket_rejected(conn, &oid)?;
+
             Ok(())
         }
 
@@ -55,13 +67,240 @@ pub fn handle_command(
                 .execute(conn)?;
 
             let mut publisher = OrderEventPublisher::new(conn);
-            publisher.order_authorized(&order)?;
+            publisher.order_rejected(&order, command.reason.as_deref())?;
+
+            Ok(())
+        }
+
+        Command::UndoBeginCancel(command) => {
+            let oid = command.id.parse::<Uuid>().expect("Invalid order id");
+            let order = transition(
+                conn,
+                &oid,
+                models::OrderState::CancelPending,
+                models::OrderState::Approved,
+            )?;
+
+            let mut publisher = OrderEventPublisher::new(conn);
+            publisher.order_cancel_rejected(&order)?;
+
+            Ok(())
+        }
+
+        Command::ConfirmCancel(command) => {
+            let oid = command.id.parse::<Uuid>().expect("Invalid order id");
+            let order = transition(
+                conn,
+                &oid,
+                models::OrderState::CancelPending,
+                models::OrderState::Cancelled,
+            )?;
+
+            let mut publisher = OrderEventPublisher::new(conn);
+            publisher.order_cancelled(&order)?;
+
+            Ok(())
+        }
+
+        Command::UndoBeginRevise(command) => {
+            let oid = command.id.parse::<Uuid>().expect("Invalid order id");
+            let order = transition(
+                conn,
+                &oid,
+                models::OrderState::RevisionPending,
+                models::OrderState::Approved,
+            )?;
+
+            let mut publisher = OrderEventPublisher::new(conn);
+            publisher.order_revision_rejected(&order)?;
+
+            cancel_if_ticket_rejected(conn, &oid)?;
+
+            Ok(())
+        }
+
+        Command::ConfirmRevise(command) => {
+            let oid = command.id.parse::<Uuid>().expect("Invalid order id");
+            let order = transition(
+                conn,
+                &oid,
+                models::OrderState::RevisionPending,
+                models::OrderState::Approved,
+            )?;
+
+            let line_items = command
+                .line_items
+                .into_iter()
+                .map(|li| models::OrderLineItem {
+                    id: Uuid::new_v4(),
+                    order_id: oid,
+                    quantity: li.quantity,
+                    menu_item_id: li.menu_item_id,
+                    name: li.name,
+                    price: li
+                        .price
+                        .expect("Line item price is empty")
+                        .amount
+                        .parse()
+                        .expect("Invalid line item price"),
+                })
+                .collect::<Vec<_>>();
+            delete(schema::order_line_items::table)
+                .filter(schema::order_line_items::order_id.eq(&oid))
+                .execute(conn)?;
+            insert_into(schema::order_line_items::table)
+                .values(&line_items)
+                .execute(conn)?;
+
+            let delivery_time = command
+                .delivery_time
+                .and_then(|ts| DateTime::from_timestamp(ts.seconds, ts.nanos as u32))
+                .unwrap_or(order.delivery_time);
+            let order = update(schema::orders::table)
+                .set((
+                    schema::orders::delivery_time.eq(delivery_time),
+                    schema::orders::version.eq(order.version + 1),
+                ))
+                .filter(schema::orders::id.eq(&oid))
+                .returning(models::Order::as_returning())
+                .get_result::<models::Order>(conn)?;
+
+            let restaurant = schema::restaurants::table
+                .select(models::Restaurant::as_select())
+                .find(&order.restaurant_id)
+                .get_result::<models::Restaurant>(conn)?;
+            let mut publisher = OrderEventPublisher::new(conn);
+            publisher.order_revised(&order, &line_items, &restaurant)?;
+
+            cancel_if_ticket_rejected(conn, &oid)?;
 
             Ok(())
         }
     })
 }
 
+/// Records that the restaurant rejected the ticket of the order and cancels the order.
+/// An order that is still being created or revised is cancelled once it gets back to
+/// `APPROVED`, so the running saga is not interrupted.
+pub fn handle_ticket_rejected(
+    conn: &mut PgConnection,
+    oid: &Uuid,
+    reason: &str,
+) -> Result<(), CommandHandlerError> {
+    conn.transaction(|conn| {
+        let order = match schema::orders::table
+            .select(models::Order::as_select())
+            .find(oid)
+            .for_update()
+            .get_result::<models::Order>(conn)
+        {
+            Ok(order) => order,
+            Err(diesel::result::Error::NotFound) => return Ok(()),
+            Err(err) => return Err(err.into()),
+        };
+        match order.state {
+            models::OrderState::ApprovalPending
+            | models::OrderState::Approved
+            | models::OrderState::RevisionPending => {}
+            // Already cancelled, or being cancelled by the consumer
+            models::OrderState::CancelPending
+            | models::OrderState::Cancelled
+            | models::OrderState::Rejected => return Ok(()),
+        }
+
+        update(schema::orders::table)
+            .set(schema::orders::ticket_rejection_reason.eq(reason))
+            .filter(schema::orders::id.eq(oid))
+            .execute(conn)?;
+        if order.state == models::OrderState::Approved {
+            cancel_if_ticket_rejected(conn, oid)?;
+        }
+        Ok(())
+    })
+}
+
+/// Moves the order to `CANCEL_PENDING` and starts the saga that cancels the ticket and
+/// refunds the consumer.
+pub fn begin_cancel(
+    conn: &mut PgConnection,
+    order: models::Order,
+    line_items: &[models::OrderLineItem],
+) -> Result<models::Order, diesel::result::Error> {
+    update(schema::orders::table)
+        .set(schema::orders::state.eq(models::OrderState::CancelPending))
+        .filter(schema::orders::id.eq(&order.id))
+        .execute(conn)?;
+    let order = models::Order {
+        state: models::OrderState::CancelPending,
+        ..order
+    };
+
+    let mut publisher = OrderEventPublisher::new(conn);
+    publisher.order_cancel_requested(&order)?;
+
+    let order_total: BigDecimal = line_items.iter().map(|li| li.total_price()).sum();
+    let saga_data = CancelOrderSagaState::new(
+        &order.id,
+        &order.restaurant_id,
+        &order.consumer_id,
+        &order_total,
+    );
+    let mut saga_manager = SagaManager::new(CancelOrderSaga::new(), conn);
+    saga_manager.create(saga_data)?;
+
+    Ok(order)
+}
+
+/// Starts cancelling an approved order whose ticket was rejected by the restaurant
+fn cancel_if_ticket_rejected(
+    conn: &mut PgConnection,
+    oid: &Uuid,
+) -> Result<(), diesel::result::Error> {
+    let order = schema::orders::table
+        .select(models::Order::as_select())
+        .find(oid)
+        .get_result::<models::Order>(conn)?;
+    if order.state != models::OrderState::Approved || order.ticket_rejection_reason.is_none() {
+        return Ok(());
+    }
+    let line_items = schema::order_line_items::table
+        .select(models::OrderLineItem::as_select())
+        .filter(schema::order_line_items::order_id.eq(oid))
+        .get_results(conn)?;
+    begin_cancel(conn, order, &line_items)?;
+    Ok(())
+}
+
+/// Moves the order from `expect` to `next`, locking the row for the rest of the transaction.
+fn transition(
+    conn: &mut PgConnection,
+    oid: &Uuid,
+    expect: models::OrderState,
+    next: models::OrderState,
+) -> Result<models::Order, CommandHandlerError> {
+    let order = schema::orders::table
+        .select(models::Order::as_select())
+        .find(oid)
+        .for_update()
+        .get_result::<models::Order>(conn)?;
+    if order.state != expect {
+        return Err(CommandHandlerError::InvalidState {
+            current: order.state,
+            expect,
+        });
+    }
+
+    update(schema::orders::table)
+        .set(schema::orders::state.eq(next))
+        .filter(schema::orders::id.eq(oid))
+        .execute(conn)?;
+
+    Ok(models::Order {
+        state: next,
+        ..order
+    })
+}
+
 #[derive(Error, Debug)]
 pub enum CommandHandlerError {
     #[error("Invalid current state {current:?}, expected {expect:?}")]
@@ -1,11 +1,16 @@
 use crate::schema;
 use crate::serializer::serialize_order_details;
 use crate::{models, models::NewOutbox, EVENT_CHANNEL};
+use bigdecimal::BigDecimal;
 use diesel::{prelude::*, PgConnection};
+use ftgo_proto::common::Money;
 use ftgo_proto::order_service::{
-    order_event, OrderAuthorizedEvent, OrderCreatedEvent, OrderEvent, OrderRejectedEvent,
+    order_event, OrderAuthorizedEvent, OrderCancelRejectedEvent, OrderCancelRequestedEvent,
+    OrderCancelledEvent, OrderCreatedEvent, OrderEvent, OrderRejectedEvent, OrderRevisedEvent,
+    OrderRevision, OrderRevisionProposedEvent, OrderRevisionRejectedEvent,
 };
 use prost::Message;
+use prost_types::Timestamp;
 use uuid::Uuid;
 
 pub struct OrderEventPublisher<'a> {
@@ -43,15 +48,113 @@ impl<'a> OrderEventPublisher<'a> {
         self.publish(event, &order.id)
     }
 
-    pub fn order_rejected(&mut self, order: &models::Order) -> Result<(), diesel::result::Error> {
+    pub fn order_rejected(
+        &mut self,
+        order: &models::Order,
+        reason: Option<&str>,
+    ) -> Result<(), diesel::result::Error> {
         let event = OrderEvent {
             event: Some(order_event::Event::OrderRejected(OrderRejectedEvent {
                 id: order.id.to_string(),
+                reason: reason.map(str::to_string),
             })),
         };
         self.publish(event, &order.id)
     }
 
+    pub fn order_revision_proposed(
+        &mut self,
+        order: &models::Order,
+        order_revision: &OrderRevision,
+        current_order_total: &BigDecimal,
+        new_order_total: &BigDecimal,
+    ) -> Result<(), diesel::result::Error> {
+        let event = OrderEvent {
+            event: Some(order_event::Event::OrderRevisionProposed(
+                OrderRevisionProposedEvent {
+                    order_revision: Some(order_revision.clone()),
+                    current_order_total: Some(Money {
+                        amount: current_order_total.to_string(),
+                    }),
+                    new_order_total: Some(Money {
+                        amount: new_order_total.to_string(),
+                    }),
+                },
+            )),
+        };
+        self.publish(event, &order.id)
+    }
+
+    pub fn order_cancel_requested(
+        &mut self,
+        order: &models::Order,
+    ) -> Result<(), diesel::result::Error> {
+        let event = OrderEvent {
+            event: Some(order_event::Event::OrderCancelRequested(
+                OrderCancelRequestedEvent {
+                    id: order.id.to_string(),
+                },
+            )),
+        };
+        self.publish(event, &order.id)
+    }
+
+    pub fn order_cancel_rejected(
+        &mut self,
+        order: &models::Order,
+    ) -> Result<(), diesel::result::Error> {
+        let event = OrderEvent {
+            event: Some(order_event::Event::OrderCancelRejected(
+                OrderCancelRejectedEvent {
+                    id: order.id.to_string(),
+                },
+            )),
+        };
+        self.publish(event, &order.id)
+    }
+
+    pub fn order_cancelled(&mut self, order: &models::Order) -> Result<(), diesel::result::Error> {
+        let event = OrderEvent {
+            event: Some(order_event::Event::OrderCancelled(OrderCancelledEvent {
+                id: order.id.to_string(),
+            })),
+        };
+        self.publish(event, &order.id)
+    }
+
+    pub fn order_revised(
+        &mut self,
+        order: &models::Order,
+        line_items: &Vec<models::OrderLineItem>,
+        restaurant: &models::Restaurant,
+    ) -> Result<(), diesel::result::Error> {
+        let event = OrderEvent {
+            event: Some(order_event::Event::OrderRevised(OrderRevisedEvent {
+                id: order.id.to_string(),
+                order_details: Some(serialize_order_details(order, line_items, restaurant)),
+                delivery_time: Some(Timestamp {
+                    seconds: order.delivery_time.timestamp(),
+                    nanos: order.delivery_time.timestamp_subsec_nanos() as i32,
+                }),
+            })),
+        };
+        self.publish(event, &order.id)
+    }
+
+    pub fn order_revision_rejected(
+        &mut self,
+        order: &models::Order,
+    ) -> Result<(), diesel::result::Error> {
+        let event = OrderEvent {
+            event: Some(order_event::Event::OrderRevisionRejected(
+                OrderRevisionRejectedEvent {
+                    id: order.id.to_string(),
+                },
+            )),
+        };
+        self.publish(event, &order.id)
+    }
+
     fn publish(&mut self, event: OrderEvent, order_id: &Uuid) -> Result<(), diesel::result::Error> {
         let mut buf = Vec::new();
         event.encode(&mut buf).unwrap();
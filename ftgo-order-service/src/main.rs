@@ -14,6 +14,16 @@ enum Commands {
     RPC,
     Consumer,
     Producer,
+    SagaWatchdog {
+        /// Number of times an expired step is re-sent before it is treated as failed
+        #[arg(long, default_value_t = 3)]
+        max_retries: i32,
+    },
+    /// Print the step log of a saga instance
+    SagaHistory {
+        saga_type: String,
+        saga_id: String,
+    },
 }
 
 #[tokio::main]
@@ -29,5 +39,13 @@ pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
             app::producer::main();
             Ok(())
         }
+        Commands::SagaWatchdog { max_retries } => {
+            app::saga_watchdog::main(*max_retries);
+            Ok(())
+        }
+        Commands::SagaHistory { saga_type, saga_id } => {
+            app::saga_history::main(saga_type, saga_id);
+            Ok(())
+        }
     }
 }
@@ -14,7 +14,8 @@ use serde_json::Value;
 use uuid::Uuid;
 
 use crate::schema::{
-    order_line_items, orders, outbox, restaurant_menu_items, restaurants, saga_instances,
+    order_line_items, orders, outbox, restaurant_menu_items, restaurants, saga_dead_letters,
+    saga_instances, saga_processed_replies, saga_step_log,
 };
 
 #[derive(FromSqlRow, AsExpression, PartialEq, Copy, Clone, Debug)]
@@ -94,6 +95,8 @@ pub struct Order {
     pub delivery_address: String,
     pub payment_token: Option<String>,
     pub created_at: DateTime<Utc>,
+    /// Set when the restaurant rejected the ticket. The order is cancelled once it is approved.
+    pub ticket_rejection_reason: Option<String>,
 }
 
 #[derive(
@@ -140,6 +143,7 @@ pub struct RestaurantMenuItem {
     pub id: String,
     pub name: String,
     pub price: BigDecimal,
+    pub available: bool,
 }
 
 #[derive(Queryable, Selectable, Debug, PartialEq)]
@@ -161,6 +165,7 @@ pub struct NewOutbox {
 
 #[derive(Queryable, Selectable, Identifiable, Insertable, AsChangeset, Debug, PartialEq)]
 #[diesel(table_name = saga_instances, primary_key(saga_type, saga_id))]
+#[diesel(treat_none_as_null = true)]
 pub struct SagaInstance {
     pub saga_type: String,
     pub saga_id: String,
@@ -170,4 +175,118 @@ pub struct SagaInstance {
     pub compensating: bool,
     pub failed: bool,
     pub saga_data_json: Value,
+    pub step_deadline: Option<DateTime<Utc>>,
+    pub step_attempts: i32,
+}
+
+#[derive(FromSqlRow, AsExpression, PartialEq, Copy, Clone, Debug)]
+#[diesel(sql_type = crate::schema::sql_types::SagaStepEvent)]
+pub enum SagaStepEvent {
+    /// Command of the step was sent
+    Invoked,
+    /// Reply to the last command was received
+    Replied,
+    /// Deadline of the step passed without a reply
+    TimedOut,
+    /// Saga turned around and started running compensations
+    CompensationStarted,
+    /// Saga reached its end state
+    Ended,
+    /// Compensation failed and the saga was given up
+    Failed,
+    /// Step could not complete past the pivot and waits for an operator
+    Parked,
+}
+
+impl ToSql<crate::schema::sql_types::SagaStepEvent, Pg> for SagaStepEvent {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        match *self {
+            SagaStepEvent::Invoked => out.write_all(b"INVOKED")?,
+            SagaStepEvent::Replied => out.write_all(b"REPLIED")?,
+            SagaStepEvent::TimedOut => out.write_all(b"TIMED_OUT")?,
+            SagaStepEvent::CompensationStarted => out.write_all(b"COMPENSATION_STARTED")?,
+            SagaStepEvent::Ended => out.write_all(b"ENDED")?,
+            SagaStepEvent::Failed => out.write_all(b"FAILED")?,
+            SagaStepEvent::Parked => out.write_all(b"PARKED")?,
+        }
+        Ok(IsNull::No)
+    }
+}
+
+impl FromSql<crate::schema::sql_types::SagaStepEvent, Pg> for SagaStepEvent {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        match bytes.as_bytes() {
+            b"INVOKED" => Ok(SagaStepEvent::Invoked),
+            b"REPLIED" => Ok(SagaStepEvent::Replied),
+            b"TIMED_OUT" => Ok(SagaStepEvent::TimedOut),
+            b"COMPENSATION_STARTED" => Ok(SagaStepEvent::CompensationStarted),
+            b"ENDED" => Ok(SagaStepEvent::Ended),
+            b"FAILED" => Ok(SagaStepEvent::Failed),
+            b"PARKED" => Ok(SagaStepEvent::Parked),
+            _ => Err("Unrecognized enum variant".into()),
+        }
+    }
+}
+
+impl From<SagaStepEvent> for ftgo_proto::order_service::SagaStepEvent {
+    fn from(e: SagaStepEvent) -> Self {
+        match e {
+            SagaStepEvent::Invoked => ftgo_proto::order_service::SagaStepEvent::Invoked,
+            SagaStepEvent::Replied => ftgo_proto::order_service::SagaStepEvent::Replied,
+            SagaStepEvent::TimedOut => ftgo_proto::order_service::SagaStepEvent::TimedOut,
+            SagaStepEvent::CompensationStarted => {
+                ftgo_proto::order_service::SagaStepEvent::CompensationStarted
+            }
+            SagaStepEvent::Ended => ftgo_proto::order_service::SagaStepEvent::Ended,
+            SagaStepEvent::Failed => ftgo_proto::order_service::SagaStepEvent::Failed,
+            SagaStepEvent::Parked => ftgo_proto::order_service::SagaStepEvent::Parked,
+        }
+    }
+}
+
+#[derive(Queryable, Selectable, Identifiable, Debug, PartialEq)]
+#[diesel(table_name = saga_step_log)]
+pub struct SagaStepLog {
+    pub id: i64,
+    pub saga_type: String,
+    pub saga_id: String,
+    pub step_index: i32,
+    pub compensating: bool,
+    pub event: SagaStepEvent,
+    pub request_id: Option<String>,
+    pub reply_succeed: Option<bool>,
+    pub reply_body: Option<Vec<u8>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Insertable, Debug, PartialEq)]
+#[diesel(table_name = saga_step_log)]
+pub struct NewSagaStepLog {
+    pub saga_type: String,
+    pub saga_id: String,
+    pub step_index: i32,
+    pub compensating: bool,
+    pub event: SagaStepEvent,
+    pub request_id: Option<String>,
+    pub reply_succeed: Option<bool>,
+    pub reply_body: Option<Vec<u8>>,
+}
+
+#[derive(Insertable, Debug, PartialEq)]
+#[diesel(table_name = saga_processed_replies)]
+pub struct NewSagaProcessedReply {
+    pub request_id: String,
+    pub saga_type: String,
+    pub saga_id: String,
+}
+
+/// A command reply that could not be routed to any registered saga
+#[derive(Insertable, Debug, PartialEq)]
+#[diesel(table_name = saga_dead_letters)]
+pub struct NewSagaDeadLetter {
+    pub saga_type: Option<String>,
+    pub saga_id: Option<String>,
+    pub request_id: Option<String>,
+    pub reason: String,
+    pub reply: Vec<u8>,
 }
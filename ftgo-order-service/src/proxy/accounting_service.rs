@@ -1,14 +1,15 @@
-use crate::models::NewOutbox;
-use crate::{schema, REPLY_CHANNEL};
 use bigdecimal::BigDecimal;
-use diesel::{prelude::*, PgConnection};
+use diesel::PgConnection;
 use ftgo_proto::accounting_service::{
-    accounting_command, AccountingCommand, DepositCommand, WithdrawCommand,
+    accounting_command, AccountingCommand, AuthorizeFundsCommand, CaptureAuthorizationCommand,
+    DepositCommand, ReleaseAuthorizationCommand, WithdrawCommand,
 };
 use prost::Message;
 use std::collections::HashMap;
 use uuid::Uuid;
 
+use super::CommandSender;
+
 const ACCOUNTING_COMMAND_CHANNEL: &str = "accounting.command";
 
 pub struct AccountingServiceProxy<'a> {
@@ -24,58 +25,111 @@ impl<'a> AccountingServiceProxy<'a> {
         &mut self,
         consumer_id: &Uuid,
         order_id: &Uuid,
+        restaurant_id: &Uuid,
         amount: &BigDecimal,
-        state: &HashMap<String, String>,
-    ) -> Result<(), diesel::result::Error> {
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
         let command = accounting_command::Command::Withdraw(WithdrawCommand {
             id: consumer_id.to_string(),
             amount: Some(ftgo_proto::common::Money {
                 amount: amount.to_string(),
             }),
             description: Some(format!("Order {}", order_id)),
+            restaurant_id: Some(restaurant_id.to_string()),
         });
-        self.publish(command, state, consumer_id)
+        self.send(command, consumer_id, headers)
     }
 
+    /// Refunds the order payment, reversing the payout to the restaurant
     pub fn deposit(
         &mut self,
         consumer_id: &Uuid,
         order_id: &Uuid,
+        restaurant_id: &Uuid,
         amount: &BigDecimal,
-        state: &HashMap<String, String>,
-    ) -> Result<(), diesel::result::Error> {
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
         let command = accounting_command::Command::Deposit(DepositCommand {
             id: consumer_id.to_string(),
             amount: Some(ftgo_proto::common::Money {
                 amount: amount.to_string(),
             }),
             description: Some(format!("Order {}", order_id)),
+            restaurant_id: Some(restaurant_id.to_string()),
         });
-        self.publish(command, state, consumer_id)
+        self.send(command, consumer_id, headers)
     }
 
-    fn publish(
+    /// Holds the amount on the consumer account, using the order id as the authorization id
+    pub fn authorize_funds(
         &mut self,
-        command: accounting_command::Command,
-        state: &HashMap<String, String>,
         consumer_id: &Uuid,
-    ) -> Result<(), diesel::result::Error> {
-        let command = AccountingCommand {
-            state: state.clone(),
-            reply_channel: Some(REPLY_CHANNEL.to_string()),
-            command: Some(command),
-        };
+        order_id: &Uuid,
+        amount: &BigDecimal,
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
+        let command = accounting_command::Command::AuthorizeFunds(AuthorizeFundsCommand {
+            id: consumer_id.to_string(),
+            authorization_id: order_id.to_string(),
+            amount: Some(ftgo_proto::common::Money {
+                amount: amount.to_string(),
+            }),
+            description: Some(format!("Order {}", order_id)),
+        });
+        self.send(command, consumer_id, headers)
+    }
+
+    /// Pays the held amount to the restaurant
+    pub fn capture_authorization(
+        &mut self,
+        consumer_id: &Uuid,
+        order_id: &Uuid,
+        restaurant_id: &Uuid,
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
+        let command =
+            accounting_command::Command::CaptureAuthorization(CaptureAuthorizationCommand {
+                id: consumer_id.to_string(),
+                authorization_id: order_id.to_string(),
+                restaurant_id: Some(restaurant_id.to_string()),
+            });
+        self.send(command, consumer_id, headers)
+    }
 
-        let mut buf = Vec::new();
-        command.encode(&mut buf).unwrap();
+    pub fn release_authorization(
+        &mut self,
+        consumer_id: &Uuid,
+        order_id: &Uuid,
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
+        let command =
+            accounting_command::Command::ReleaseAuthorization(ReleaseAuthorizationCommand {
+                id: consumer_id.to_string(),
+                authorization_id: order_id.to_string(),
+            });
+        self.send(command, consumer_id, headers)
+    }
+}
+
+impl<'a> CommandSender for AccountingServiceProxy<'a> {
+    type Command = accounting_command::Command;
+
+    const COMMAND_CHANNEL: &'static str = ACCOUNTING_COMMAND_CHANNEL;
 
-        diesel::insert_into(schema::outbox::table)
-            .values(NewOutbox {
-                topic: ACCOUNTING_COMMAND_CHANNEL.to_string(),
-                key: consumer_id.to_string(),
-                value: buf,
-            })
-            .execute(self.conn)
-            .map(|_| ())
+    fn connection(&mut self) -> &mut PgConnection {
+        self.conn
+    }
+
+    fn encode(
+        command: Self::Command,
+        state: HashMap<String, String>,
+        reply_channel: String,
+    ) -> Vec<u8> {
+        AccountingCommand {
+            state,
+            reply_channel: Some(reply_channel),
+            command: Some(command),
+        }
+        .encode_to_vec()
     }
 }
@@ -1,7 +1,5 @@
-use crate::models::NewOutbox;
-use crate::{schema, REPLY_CHANNEL};
 use bigdecimal::BigDecimal;
-use diesel::{prelude::*, PgConnection};
+use diesel::PgConnection;
 use ftgo_proto::consumer_service::{
     consumer_command, ConsumerCommand, ValidateOrderByConsumerCommand,
 };
@@ -9,6 +7,8 @@ use prost::Message;
 use std::collections::HashMap;
 use uuid::Uuid;
 
+use super::CommandSender;
+
 const CONSUMER_COMMAND_CHANNEL: &str = "consumer.command";
 
 pub struct ConsumerServiceProxy<'a> {
@@ -25,8 +25,8 @@ impl<'a> ConsumerServiceProxy<'a> {
         consumer_id: &Uuid,
         order_id: &Uuid,
         order_total: &BigDecimal,
-        state: &HashMap<String, String>,
-    ) -> Result<(), diesel::result::Error> {
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
         let command =
             consumer_command::Command::ValidateOrderByConsumer(ValidateOrderByConsumerCommand {
                 id: consumer_id.to_string(),
@@ -35,31 +35,29 @@ impl<'a> ConsumerServiceProxy<'a> {
                     amount: order_total.to_string(),
                 }),
             });
-        self.publish(command, state, consumer_id)
+        self.send(command, consumer_id, headers)
     }
+}
 
-    fn publish(
-        &mut self,
-        command: consumer_command::Command,
-        state: &HashMap<String, String>,
-        consumer_id: &Uuid,
-    ) -> Result<(), diesel::result::Error> {
-        let command = ConsumerCommand {
-            state: state.clone(),
-            reply_channel: Some(REPLY_CHANNEL.to_string()),
-            command: Some(command),
-        };
+impl<'a> CommandSender for ConsumerServiceProxy<'a> {
+    type Command = consumer_command::Command;
+
+    const COMMAND_CHANNEL: &'static str = CONSUMER_COMMAND_CHANNEL;
 
-        let mut buf = Vec::new();
-        command.encode(&mut buf).unwrap();
+    fn connection(&mut self) -> &mut PgConnection {
+        self.conn
+    }
 
-        diesel::insert_into(schema::outbox::table)
-            .values(NewOutbox {
-                topic: CONSUMER_COMMAND_CHANNEL.to_string(),
-                key: consumer_id.to_string(),
-                value: buf,
-            })
-            .execute(self.conn)
-            .map(|_| ())
+    fn encode(
+        command: Self::Command,
+        state: HashMap<String, String>,
+        reply_channel: String,
+    ) -> Vec<u8> {
+        ConsumerCommand {
+            state,
+            reply_channel: Some(reply_channel),
+            command: Some(command),
+        }
+        .encode_to_vec()
     }
 }
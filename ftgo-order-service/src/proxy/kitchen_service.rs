@@ -1,15 +1,17 @@
 use std::collections::HashMap;
 
-use crate::models::NewOutbox;
-use crate::{schema, REPLY_CHANNEL};
-use diesel::{prelude::*, PgConnection};
+use diesel::PgConnection;
 use ftgo_proto::kitchen_service::{
-    kitchen_command, CancelCreateTicketCommand, ConfirmCreateTicketCommand, CreateTicketCommand,
-    KitchenCommand, TicketDetails,
+    kitchen_command, BeginCancelTicketCommand, BeginReviseTicketCommand, CancelCreateTicketCommand,
+    ConfirmCancelTicketCommand, ConfirmCreateTicketCommand, ConfirmReviseTicketCommand,
+    CreateTicketCommand, KitchenCommand, TicketDetails, UndoBeginCancelTicketCommand,
+    UndoBeginReviseTicketCommand,
 };
 use prost::Message;
 use uuid::Uuid;
 
+use super::CommandSender;
+
 const KITCHEN_COMMAND_CHANNEL: &str = "kitchen.command";
 
 pub struct KitchenServiceProxy<'a> {
@@ -26,62 +28,136 @@ impl<'a> KitchenServiceProxy<'a> {
         id: &Uuid,
         details: &TicketDetails,
         restaurant_id: &Uuid,
-        state: &HashMap<String, String>,
-    ) -> Result<(), diesel::result::Error> {
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
         let command = kitchen_command::Command::CreateTicket(CreateTicketCommand {
             id: id.to_string(),
             details: Some(details.clone()),
             restaurant_id: restaurant_id.to_string(),
         });
-        self.publish(command, state, restaurant_id)
+        self.send(command, restaurant_id, headers)
     }
 
     pub fn cancel_create_ticket(
         &mut self,
         id: &Uuid,
         restaurant_id: &Uuid,
-        state: &HashMap<String, String>,
-    ) -> Result<(), diesel::result::Error> {
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
         let command = kitchen_command::Command::CancelCreateTicket(CancelCreateTicketCommand {
             id: id.to_string(),
         });
-        self.publish(command, state, restaurant_id)
+        self.send(command, restaurant_id, headers)
     }
 
     pub fn confirm_create_ticket(
         &mut self,
         id: &Uuid,
         restaurant_id: &Uuid,
-        state: &HashMap<String, String>,
-    ) -> Result<(), diesel::result::Error> {
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
         let command = kitchen_command::Command::ConfirmCreateTicket(ConfirmCreateTicketCommand {
             id: id.to_string(),
         });
-        self.publish(command, state, restaurant_id)
+        self.send(command, restaurant_id, headers)
+    }
+
+    pub fn begin_cancel_ticket(
+        &mut self,
+        id: &Uuid,
+        restaurant_id: &Uuid,
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
+        let command = kitchen_command::Command::BeginCancelTicket(BeginCancelTicketCommand {
+            id: id.to_string(),
+        });
+        self.send(command, restaurant_id, headers)
+    }
+
+    pub fn undo_begin_cancel_ticket(
+        &mut self,
+        id: &Uuid,
+        restaurant_id: &Uuid,
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
+        let command =
+            kitchen_command::Command::UndoBeginCancelTicket(UndoBeginCancelTicketCommand {
+                id: id.to_string(),
+            });
+        self.send(command, restaurant_id, headers)
+    }
+
+    pub fn confirm_cancel_ticket(
+        &mut self,
+        id: &Uuid,
+        restaurant_id: &Uuid,
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
+        let command = kitchen_command::Command::ConfirmCancelTicket(ConfirmCancelTicketCommand {
+            id: id.to_string(),
+        });
+        self.send(command, restaurant_id, headers)
+    }
+
+    pub fn begin_revise_ticket(
+        &mut self,
+        id: &Uuid,
+        restaurant_id: &Uuid,
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
+        let command = kitchen_command::Command::BeginReviseTicket(BeginReviseTicketCommand {
+            id: id.to_string(),
+        });
+        self.send(command, restaurant_id, headers)
     }
 
-    fn publish(
+    pub fn undo_begin_revise_ticket(
         &mut self,
-        command: kitchen_command::Command,
-        state: &HashMap<String, String>,
+        id: &Uuid,
+        restaurant_id: &Uuid,
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
+        let command =
+            kitchen_command::Command::UndoBeginReviseTicket(UndoBeginReviseTicketCommand {
+                id: id.to_string(),
+            });
+        self.send(command, restaurant_id, headers)
+    }
+
+    pub fn confirm_revise_ticket(
+        &mut self,
+        id: &Uuid,
+        details: &TicketDetails,
         restaurant_id: &Uuid,
-    ) -> Result<(), diesel::result::Error> {
-        let command = KitchenCommand {
-            state: state.clone(),
-            reply_channel: Some(REPLY_CHANNEL.to_string()),
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
+        let command = kitchen_command::Command::ConfirmReviseTicket(ConfirmReviseTicketCommand {
+            id: id.to_string(),
+            details: Some(details.clone()),
+        });
+        self.send(command, restaurant_id, headers)
+    }
+}
+
+impl<'a> CommandSender for KitchenServiceProxy<'a> {
+    type Command = kitchen_command::Command;
+
+    const COMMAND_CHANNEL: &'static str = KITCHEN_COMMAND_CHANNEL;
+
+    fn connection(&mut self) -> &mut PgConnection {
+        self.conn
+    }
+
+    fn encode(
+        command: Self::Command,
+        state: HashMap<String, String>,
+        reply_channel: String,
+    ) -> Vec<u8> {
+        KitchenCommand {
+            state,
+            reply_channel: Some(reply_channel),
             command: Some(command),
-        };
-
-        let mut buf = Vec::new();
-        command.encode(&mut buf).unwrap();
-
-        diesel::insert_into(schema::outbox::table)
-            .values(NewOutbox {
-                topic: KITCHEN_COMMAND_CHANNEL.to_string(),
-                key: restaurant_id.to_string(),
-                value: buf,
-            })
-            .execute(self.conn)
-            .map(|_| ())
+        }
+        .encode_to_vec()
     }
 }
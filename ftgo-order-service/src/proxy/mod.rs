@@ -1,4 +1,65 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+
+use crate::models::NewOutbox;
+use crate::saga::REQUEST_ID_HEADER;
+use crate::{schema, REPLY_CHANNEL};
+use diesel::{prelude::*, PgConnection};
+use uuid::Uuid;
+
 pub mod accounting_service;
 pub mod consumer_service;
 pub mod kitchen_service;
 pub mod order_service;
+
+/// Publishes commands of a participant service through the outbox.
+pub trait CommandSender {
+    type Command: Debug;
+
+    const COMMAND_CHANNEL: &'static str;
+
+    fn connection(&mut self) -> &mut PgConnection;
+
+    /// Wraps the command into the envelope message of the participant
+    fn encode(
+        command: Self::Command,
+        state: HashMap<String, String>,
+        reply_channel: String,
+    ) -> Vec<u8>;
+
+    /// Sends the command with a `REQUEST-ID` added to the saga headers and routes its
+    /// reply to the order service. A request id already in the headers is kept, so that a
+    /// re-sent command is deduplicated by the participant. Returns the request id.
+    fn send(
+        &mut self,
+        command: Self::Command,
+        key: &Uuid,
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
+        let request_id = headers
+            .get(REQUEST_ID_HEADER)
+            .cloned()
+            .unwrap_or_else(|| Uuid::new_v4().to_string());
+        let state = {
+            let mut state = headers.clone();
+            state.insert(REQUEST_ID_HEADER.to_string(), request_id.clone());
+            state
+        };
+        println!(
+            "REQUESTED:{}: {} {:?}",
+            request_id,
+            Self::COMMAND_CHANNEL,
+            command
+        );
+
+        let value = Self::encode(command, state, REPLY_CHANNEL.to_string());
+        diesel::insert_into(schema::outbox::table)
+            .values(NewOutbox {
+                topic: Self::COMMAND_CHANNEL.to_string(),
+                key: key.to_string(),
+                value,
+            })
+            .execute(self.connection())?;
+        Ok(request_id)
+    }
+}
@@ -1,13 +1,17 @@
-use crate::models::NewOutbox;
-use crate::{schema, REPLY_CHANNEL};
-use diesel::{prelude::*, PgConnection};
+use crate::models;
+use chrono::{DateTime, Utc};
+use diesel::PgConnection;
 use ftgo_proto::order_service::{
-    order_command, ApproveOrderCommand, OrderCommand, RejectOrderCommand,
+    order_command, ApproveOrderCommand, ConfirmCancelOrderCommand, ConfirmReviseOrderCommand,
+    OrderCommand, RejectOrderCommand, UndoBeginCancelOrderCommand, UndoBeginReviseOrderCommand,
 };
 use prost::Message;
+use prost_types::Timestamp;
 use std::collections::HashMap;
 use uuid::Uuid;
 
+use super::CommandSender;
+
 const ORDER_COMMAND_CHANNEL: &str = "order.command";
 
 pub struct OrderServiceProxy<'a> {
@@ -22,47 +26,98 @@ impl<'a> OrderServiceProxy<'a> {
     pub fn approve_order(
         &mut self,
         order_id: &Uuid,
-        state: &HashMap<String, String>,
-    ) -> Result<(), diesel::result::Error> {
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
         let command = order_command::Command::Approve(ApproveOrderCommand {
             id: order_id.to_string(),
         });
-        self.publish(command, state, order_id)
+        self.send(command, order_id, headers)
     }
 
     pub fn reject_order(
         &mut self,
         order_id: &Uuid,
-        state: &HashMap<String, String>,
-    ) -> Result<(), diesel::result::Error> {
+        reason: &Option<String>,
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
         let command = order_command::Command::Reject(RejectOrderCommand {
             id: order_id.to_string(),
+            reason: reason.clone(),
         });
-        self.publish(command, state, order_id)
+        self.send(command, order_id, headers)
     }
 
-    fn publish(
+    pub fn undo_begin_cancel_order(
         &mut self,
-        command: order_command::Command,
-        state: &HashMap<String, String>,
         order_id: &Uuid,
-    ) -> Result<(), diesel::result::Error> {
-        let command = OrderCommand {
-            state: state.clone(),
-            reply_channel: Some(REPLY_CHANNEL.to_string()),
-            command: Some(command),
-        };
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
+        let command = order_command::Command::UndoBeginCancel(UndoBeginCancelOrderCommand {
+            id: order_id.to_string(),
+        });
+        self.send(command, order_id, headers)
+    }
+
+    pub fn confirm_cancel_order(
+        &mut self,
+        order_id: &Uuid,
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
+        let command = order_command::Command::ConfirmCancel(ConfirmCancelOrderCommand {
+            id: order_id.to_string(),
+        });
+        self.send(command, order_id, headers)
+    }
+
+    pub fn undo_begin_revise_order(
+        &mut self,
+        order_id: &Uuid,
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
+        let command = order_command::Command::UndoBeginRevise(UndoBeginReviseOrderCommand {
+            id: order_id.to_string(),
+        });
+        self.send(command, order_id, headers)
+    }
+
+    pub fn confirm_revise_order(
+        &mut self,
+        order_id: &Uuid,
+        line_items: &[models::OrderLineItem],
+        delivery_time: &DateTime<Utc>,
+        headers: &HashMap<String, String>,
+    ) -> Result<String, diesel::result::Error> {
+        let command = order_command::Command::ConfirmRevise(ConfirmReviseOrderCommand {
+            id: order_id.to_string(),
+            line_items: line_items.iter().map(|li| li.into()).collect(),
+            delivery_time: Some(Timestamp {
+                seconds: delivery_time.timestamp(),
+                nanos: delivery_time.timestamp_subsec_nanos() as i32,
+            }),
+        });
+        self.send(command, order_id, headers)
+    }
+}
+
+impl<'a> CommandSender for OrderServiceProxy<'a> {
+    type Command = order_command::Command;
 
-        let mut buf = Vec::new();
-        command.encode(&mut buf).unwrap();
+    const COMMAND_CHANNEL: &'static str = ORDER_COMMAND_CHANNEL;
 
-        diesel::insert_into(schema::outbox::table)
-            .values(NewOutbox {
-                topic: ORDER_COMMAND_CHANNEL.to_string(),
-                key: order_id.to_string(),
-                value: buf,
-            })
-            .execute(self.conn)
-            .map(|_| ())
+    fn connection(&mut self) -> &mut PgConnection {
+        self.conn
+    }
+
+    fn encode(
+        command: Self::Command,
+        state: HashMap<String, String>,
+        reply_channel: String,
+    ) -> Vec<u8> {
+        OrderCommand {
+            state,
+            reply_channel: Some(reply_channel),
+            command: Some(command),
+        }
+        .encode_to_vec()
     }
 }
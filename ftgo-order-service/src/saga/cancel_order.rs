@@ -0,0 +1,108 @@
+use crate::proxy::{
+    accounting_service::AccountingServiceProxy, kitchen_service::KitchenServiceProxy,
+    order_service::OrderServiceProxy,
+};
+use bigdecimal::BigDecimal;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{step, Saga, SagaDefition};
+
+pub const SAGA_TYPE: &str = "cancel-order";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct CancelOrderSagaState {
+    pub order_id: Uuid,
+    pub restaurant_id: Uuid,
+    pub consumer_id: Uuid,
+    pub order_total: BigDecimal,
+}
+
+impl CancelOrderSagaState {
+    pub fn new(
+        order_id: &Uuid,
+        restaurant_id: &Uuid,
+        consumer_id: &Uuid,
+        order_total: &BigDecimal,
+    ) -> Self {
+        Self {
+            order_id: *order_id,
+            restaurant_id: *restaurant_id,
+            consumer_id: *consumer_id,
+            order_total: order_total.clone(),
+        }
+    }
+}
+
+/// Cancels an approved order. The order is moved to `CANCEL_PENDING` before the
+/// saga is created, so the first step only carries the compensation that restores it.
+pub struct CancelOrderSaga<'a> {
+    pub saga_definition: SagaDefition<'a, CancelOrderSagaState>,
+}
+
+impl<'a> CancelOrderSaga<'a> {
+    pub fn new() -> Self {
+        Self {
+            saga_definition: step()
+                .with_compensation(|saga_state: &CancelOrderSagaState, headers, conn| {
+                    OrderServiceProxy::new(conn)
+                        .undo_begin_cancel_order(&saga_state.order_id, headers)
+                })
+                .step()
+                .invoke_participant(|saga_state: &CancelOrderSagaState, headers, conn| {
+                    KitchenServiceProxy::new(conn).begin_cancel_ticket(
+                        &saga_state.order_id,
+                        &saga_state.restaurant_id,
+                        headers,
+                    )
+                })
+                .with_compensation(|saga_state: &CancelOrderSagaState, headers, conn| {
+                    KitchenServiceProxy::new(conn).undo_begin_cancel_ticket(
+                        &saga_state.order_id,
+                        &saga_state.restaurant_id,
+                        headers,
+                    )
+                })
+                .step()
+                .invoke_participant(|saga_state: &CancelOrderSagaState, headers, conn| {
+                    AccountingServiceProxy::new(conn).deposit(
+                        &saga_state.consumer_id,
+                        &saga_state.order_id,
+                        &saga_state.restaurant_id,
+                        &saga_state.order_total,
+                        headers,
+                    )
+                })
+                .pivot()
+                .step()
+                .invoke_participant(|saga_state: &CancelOrderSagaState, headers, conn| {
+                    KitchenServiceProxy::new(conn).confirm_cancel_ticket(
+                        &saga_state.order_id,
+                        &saga_state.restaurant_id,
+                        headers,
+                    )
+                })
+                .step()
+                .invoke_participant(|saga_state: &CancelOrderSagaState, headers, conn| {
+                    OrderServiceProxy::new(conn).confirm_cancel_order(&saga_state.order_id, headers)
+                })
+                .build(),
+        }
+    }
+}
+
+impl<'a> Default for CancelOrderSaga<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Saga<CancelOrderSagaState> for CancelOrderSaga<'a> {
+    fn r#type(&self) -> &'static str {
+        SAGA_TYPE
+    }
+
+    fn get_definition(&self) -> &SagaDefition<'a, CancelOrderSagaState> {
+        &self.saga_definition
+    }
+}
@@ -1,51 +1,196 @@
 use std::collections::HashMap;
 
 use crate::{
-    models::{self, SagaInstance},
+    models::{
+        self, NewSagaProcessedReply, NewSagaStepLog, SagaInstance, SagaStepEvent, SagaStepLog,
+    },
     schema,
 };
+use chrono::{TimeDelta, Utc};
 use diesel::{insert_into, prelude::*, update, PgConnection};
 use ftgo_proto::common::CommandReply;
 use serde::{de::DeserializeOwned, Serialize};
 use serde_json::Value;
 use uuid::Uuid;
 
+pub mod cancel_order;
 pub mod create_order;
+pub mod registry;
+pub mod revise_order;
 
-const SAGA_HEADER_TYPE: &'static str = "SAGA-TYPE";
-const SAGA_HEADER_ID: &'static str = "SAGA-ID";
+pub(crate) const SAGA_HEADER_TYPE: &str = "SAGA-TYPE";
+pub(crate) const SAGA_HEADER_ID: &str = "SAGA-ID";
+pub(crate) const REQUEST_ID_HEADER: &str = "REQUEST-ID";
+/// Request id of the command a compensation undoes, so that the participant can tell an undo
+/// from a new command and skip it when the command never took effect
+pub(crate) const COMPENSATED_REQUEST_ID_HEADER: &str = "COMPENSATED-REQUEST-ID";
+
+/// Registry of every saga run by the order service
+pub fn registry() -> registry::SagaRegistry {
+    registry::SagaRegistry::default()
+        .register(create_order::CreateOrderSaga::new)
+        .register(cancel_order::CancelOrderSaga::new)
+        .register(revise_order::ReviseOrderSaga::new)
+}
+
+/// How long a step may wait for its reply before the watchdog picks it up
+pub const DEFAULT_STEP_TIMEOUT: TimeDelta = TimeDelta::seconds(60);
+
+/// Sends the command of a step and returns its request id
+pub type Invocation<'a, Data> = Box<
+    dyn Fn(
+            &Data,
+            &HashMap<String, String>,
+            &mut PgConnection,
+        ) -> Result<String, diesel::result::Error>
+        + 'a,
+>;
+
+/// Decides from the saga data whether a step applies
+pub type Predicate<'a, Data> = Box<dyn Fn(&Data) -> bool + 'a>;
 
 pub struct SagaStep<'a, Data> {
-    pub invoke: Option<
-        Box<
-            dyn Fn(
-                    &Data,
-                    &HashMap<String, String>,
-                    &mut PgConnection,
-                ) -> Result<String, diesel::result::Error>
-                + 'a,
-        >,
-    >,
+    /// Step is skipped in both directions when the predicate returns false
+    pub predicate: Option<Predicate<'a, Data>>,
+    pub invoke: Option<Invocation<'a, Data>>,
     pub on_reply: Option<Box<dyn Fn(Data, &CommandReply) -> Data + 'a>>,
-    pub invoke_compensation: Option<
-        Box<
-            dyn Fn(
-                    &Data,
-                    &HashMap<String, String>,
-                    &mut PgConnection,
-                ) -> Result<String, diesel::result::Error>
-                + 'a,
-        >,
-    >,
+    pub invoke_compensation: Option<Invocation<'a, Data>>,
+    pub pivot: bool,
+}
+
+impl<'a, Data> SagaStep<'a, Data> {
+    fn empty() -> Self {
+        Self {
+            predicate: None,
+            invoke: None,
+            on_reply: None,
+            invoke_compensation: None,
+            pivot: false,
+        }
+    }
 }
 
 pub struct SagaDefition<'a, Data> {
     pub steps: Vec<SagaStep<'a, Data>>,
 }
 
+impl<'a, Data> SagaDefition<'a, Data> {
+    /// Whether a failure at the step may still turn the saga around. Steps after the pivot
+    /// have to be completed instead.
+    pub fn can_compensate_from(&self, step_index: i32) -> bool {
+        match self.steps.iter().position(|step| step.pivot) {
+            Some(pivot) => step_index <= pivot as i32,
+            None => true,
+        }
+    }
+
+    /// Whether a step still waiting on its reply may be abandoned. The outcome of the pivot
+    /// is unknown until it replies, so neither it nor the steps after it are compensated.
+    pub fn can_abandon(&self, step_index: i32) -> bool {
+        match self.steps.iter().position(|step| step.pivot) {
+            Some(pivot) => step_index < pivot as i32,
+            None => true,
+        }
+    }
+}
+
+/// Builds a saga definition step by step.
+///
+/// ```ignore
+/// step()
+///     .with_compensation(|data, headers, conn| ...)
+///     .step()
+///     .invoke_participant(|data, headers, conn| ...)
+///     .on_reply(|data, reply| ...)
+///     .build()
+/// ```
+pub fn step<'a, Data>() -> SagaDefinitionBuilder<'a, Data> {
+    SagaDefinitionBuilder {
+        steps: Vec::new(),
+        current: SagaStep::empty(),
+    }
+}
+
+pub struct SagaDefinitionBuilder<'a, Data> {
+    steps: Vec<SagaStep<'a, Data>>,
+    current: SagaStep<'a, Data>,
+}
+
+impl<'a, Data> SagaDefinitionBuilder<'a, Data> {
+    pub fn invoke_participant(
+        mut self,
+        invoke: impl Fn(
+                &Data,
+                &HashMap<String, String>,
+                &mut PgConnection,
+            ) -> Result<String, diesel::result::Error>
+            + 'a,
+    ) -> Self {
+        self.current.invoke = Some(Box::new(invoke));
+        self
+    }
+
+    /// Same as `invoke_participant`, but the whole step is skipped when the predicate
+    /// returns false
+    pub fn invoke_participant_if(
+        mut self,
+        predicate: impl Fn(&Data) -> bool + 'a,
+        invoke: impl Fn(
+                &Data,
+                &HashMap<String, String>,
+                &mut PgConnection,
+            ) -> Result<String, diesel::result::Error>
+            + 'a,
+    ) -> Self {
+        self.current.predicate = Some(Box::new(predicate));
+        self.invoke_participant(invoke)
+    }
+
+    pub fn on_reply(mut self, on_reply: impl Fn(Data, &CommandReply) -> Data + 'a) -> Self {
+        self.current.on_reply = Some(Box::new(on_reply));
+        self
+    }
+
+    pub fn with_compensation(
+        mut self,
+        compensation: impl Fn(
+                &Data,
+                &HashMap<String, String>,
+                &mut PgConnection,
+            ) -> Result<String, diesel::result::Error>
+            + 'a,
+    ) -> Self {
+        self.current.invoke_compensation = Some(Box::new(compensation));
+        self
+    }
+
+    /// Marks the current step as the point of no return. Its failure is still compensated,
+    /// but failures after it and timeouts from it on park the saga for an operator.
+    pub fn pivot(mut self) -> Self {
+        self.current.pivot = true;
+        self
+    }
+
+    /// Finishes the current step and starts a new one
+    pub fn step(mut self) -> Self {
+        let current = std::mem::replace(&mut self.current, SagaStep::empty());
+        self.steps.push(current);
+        self
+    }
+
+    pub fn build(self) -> SagaDefition<'a, Data> {
+        let mut steps = self.steps;
+        steps.push(self.current);
+        SagaDefition { steps }
+    }
+}
+
 pub trait Saga<Data: Serialize + DeserializeOwned> {
     fn r#type(&self) -> &'static str;
     fn get_definition(&self) -> &SagaDefition<Data>;
+    fn step_timeout(&self) -> TimeDelta {
+        DEFAULT_STEP_TIMEOUT
+    }
     fn serialize_data(&self, data: &Data) -> Value {
         serde_json::to_value(data).unwrap()
     }
@@ -77,6 +222,8 @@ impl<'a, Data: Serialize + DeserializeOwned> SagaManager<'a, Data> {
             compensating: false,
             failed: false,
             saga_data_json: serde_json::to_value(&saga_data).unwrap(),
+            step_deadline: None,
+            step_attempts: 0,
         };
         insert_into(schema::saga_instances::table)
             .values(&saga_instance)
@@ -101,7 +248,35 @@ impl<'a, Data: Serialize + DeserializeOwned> SagaManager<'a, Data> {
         let mut saga_instance = schema::saga_instances::table
             .select(models::SagaInstance::as_select())
             .find((saga_type, saga_id))
+            .for_update()
             .get_result::<models::SagaInstance>(self.connection)?;
+
+        // Retries of a step keep its request id, so only a reply to an earlier step is stale
+        let request_id = message.state.get(REQUEST_ID_HEADER);
+        if saga_instance.end_state || request_id != saga_instance.last_request_id.as_ref() {
+            println!(
+                "Ignore stale saga reply: request_id={:?}, last_request_id={:?}",
+                request_id, saga_instance.last_request_id,
+            );
+            return Ok(());
+        }
+        // Remember handled request ids, so a redelivered reply is never applied twice even
+        // when `last_request_id` has not moved on yet
+        if let Some(request_id) = request_id {
+            let inserted = insert_into(schema::saga_processed_replies::table)
+                .values(NewSagaProcessedReply {
+                    request_id: request_id.to_string(),
+                    saga_type: saga_instance.saga_type.clone(),
+                    saga_id: saga_instance.saga_id.clone(),
+                })
+                .on_conflict_do_nothing()
+                .execute(self.connection)?;
+            if inserted == 0 {
+                println!("Ignore duplicated saga reply: request_id={}", request_id);
+                return Ok(());
+            }
+        }
+        self.log_step(&saga_instance, SagaStepEvent::Replied, Some(message))?;
         let saga_data = self.saga.deserialize_data(&saga_instance.saga_data_json);
 
         let current_step = {
@@ -128,6 +303,95 @@ impl<'a, Data: Serialize + DeserializeOwned> SagaManager<'a, Data> {
         Ok(())
     }
 
+    /// Called by the watchdog once the deadline of the current step has passed.
+    ///
+    /// Re-sends the step under the same request id while `step_attempts` is below
+    /// `max_retries`, then gives the step up. Its command may still be applied late, so
+    /// only a step with nothing to undo is treated as failed and starts compensation. Any
+    /// other step, and every step from the pivot on, parks the saga instead, and a
+    /// compensating saga fails.
+    pub fn handle_timeout(
+        &mut self,
+        mut saga_instance: SagaInstance,
+        max_retries: i32,
+    ) -> Result<SagaInstance, diesel::result::Error> {
+        let saga_data = self.saga.deserialize_data(&saga_instance.saga_data_json);
+        self.log_step(&saga_instance, SagaStepEvent::TimedOut, None)?;
+
+        if saga_instance.step_attempts >= max_retries {
+            println!(
+                "Saga step timed out (type={}, id={}, executing={}, compensating={})",
+                saga_instance.saga_type,
+                saga_instance.saga_id,
+                saga_instance.currently_executing,
+                saga_instance.compensating,
+            );
+            return self.give_up(saga_instance, &saga_data);
+        }
+
+        saga_instance.step_attempts += 1;
+        println!(
+            "Retry saga step (type={}, id={}, executing={}, attempt={})",
+            saga_instance.saga_type,
+            saga_instance.saga_id,
+            saga_instance.currently_executing,
+            saga_instance.step_attempts,
+        );
+        if let Err(err) = self.invoke_step(&mut saga_instance, &saga_data, true) {
+            println!(
+                "Failed to retry saga step (instance={:?}): {:?}",
+                saga_instance, err
+            );
+            return self.give_up(saga_instance, &saga_data);
+        }
+        self.save(&saga_instance)?;
+        Ok(saga_instance)
+    }
+
+    /// Re-sends the command of the current step on behalf of an operator.
+    ///
+    /// Unlike a timeout retry this resets `step_attempts`, giving the watchdog a fresh
+    /// retry budget. The request id is kept unless a reply to it was already handled.
+    /// The instance must not be in its end state.
+    pub fn retry_step(
+        &mut self,
+        mut saga_instance: SagaInstance,
+    ) -> Result<SagaInstance, diesel::result::Error> {
+        let saga_data = self.saga.deserialize_data(&saga_instance.saga_data_json);
+
+        println!(
+            "Manually retry saga step (type={}, id={}, executing={}, compensating={})",
+            saga_instance.saga_type,
+            saga_instance.saga_id,
+            saga_instance.currently_executing,
+            saga_instance.compensating,
+        );
+        saga_instance.step_attempts = 0;
+        if !self.invoke_step(&mut saga_instance, &saga_data, true)? {
+            // Nothing to re-send for the current step, so move on as if it had succeeded
+            return self.process(saga_instance, &saga_data, true);
+        }
+        self.save(&saga_instance)?;
+        Ok(saga_instance)
+    }
+
+    /// Abandons the current step on behalf of an operator, who vouches that its command
+    /// did not take effect, so that the saga compensates the steps before it. The instance
+    /// must neither be in its end state nor already compensating, and its current step
+    /// must be before the pivot.
+    pub fn compensate(
+        &mut self,
+        saga_instance: SagaInstance,
+    ) -> Result<SagaInstance, diesel::result::Error> {
+        let saga_data = self.saga.deserialize_data(&saga_instance.saga_data_json);
+
+        println!(
+            "Manually compensate saga (type={}, id={}, executing={})",
+            saga_instance.saga_type, saga_instance.saga_id, saga_instance.currently_executing,
+        );
+        self.process(saga_instance, &saga_data, false)
+    }
+
     fn process(
         &mut self,
         mut saga_instance: SagaInstance,
@@ -135,99 +399,585 @@ impl<'a, Data: Serialize + DeserializeOwned> SagaManager<'a, Data> {
         succeed: bool,
     ) -> Result<SagaInstance, diesel::result::Error> {
         match (saga_instance.compensating, succeed) {
-            (_, true) => {
+            (compensating, true) => {
                 // Resume
-                saga_instance = match self.next(saga_instance, saga_data) {
-                    (saga_instance, Ok(())) => saga_instance,
-                    (saga_instance, Err(err)) => {
-                        println!(
-                            "Failed to process saga (instance={:?}, succeed={}): {:?}",
-                            saga_instance, succeed, err
-                        );
-                        return self.process(saga_instance, saga_data, false);
-                    }
-                }
+                let direction = if compensating { -1 } else { 1 };
+                let from = saga_instance.currently_executing + direction;
+                self.resume(saga_instance, saga_data, from)
             }
             (false, false) => {
-                // Start compensating
-                saga_instance.compensating = true;
-                saga_instance = match self.next(saga_instance, saga_data) {
-                    (saga_instance, Ok(())) => saga_instance,
-                    (saga_instance, Err(err)) => {
-                        println!(
-                            "Failed to process saga (instance={:?}, succeed={}): {:?}",
-                            saga_instance, succeed, err
-                        );
-                        return self.process(saga_instance, saga_data, false);
-                    }
+                let definition = self.saga.get_definition();
+                if !definition.can_compensate_from(saga_instance.currently_executing) {
+                    return self.park(saga_instance);
                 }
+                // Start compensating from the step before the failed one
+                let from = saga_instance.currently_executing - 1;
+                self.start_compensation(saga_instance, saga_data, from)
             }
             (true, false) => {
                 // Fail to compensate
                 saga_instance.end_state = true;
                 saga_instance.failed = true;
+                saga_instance.step_deadline = None;
+                self.log_step(&saga_instance, SagaStepEvent::Failed, None)?;
+                self.save(&saga_instance)?;
+                Ok(saga_instance)
+            }
+        }
+    }
+
+    /// Called once the current step is not waited on any longer
+    fn give_up(
+        &mut self,
+        saga_instance: SagaInstance,
+        saga_data: &Data,
+    ) -> Result<SagaInstance, diesel::result::Error> {
+        if saga_instance.compensating {
+            return self.process(saga_instance, saga_data, false);
+        }
+        let definition = self.saga.get_definition();
+        let idx = saga_instance.currently_executing;
+        // Only steps whose success was recorded are compensated. The outcome of this one is
+        // unknown, so it is left to an operator unless there is nothing to undo for it.
+        if !definition.can_abandon(idx)
+            || definition.steps[idx as usize].invoke_compensation.is_some()
+        {
+            return self.park(saga_instance);
+        }
+        self.process(saga_instance, saga_data, false)
+    }
+
+    fn start_compensation(
+        &mut self,
+        mut saga_instance: SagaInstance,
+        saga_data: &Data,
+        from: i32,
+    ) -> Result<SagaInstance, diesel::result::Error> {
+        saga_instance.compensating = true;
+        self.log_step(&saga_instance, SagaStepEvent::CompensationStarted, None)?;
+        self.resume(saga_instance, saga_data, from)
+    }
+
+    fn resume(
+        &mut self,
+        saga_instance: SagaInstance,
+        saga_data: &Data,
+        from: i32,
+    ) -> Result<SagaInstance, diesel::result::Error> {
+        let saga_instance = match self.next(saga_instance, saga_data, from) {
+            (saga_instance, Ok(())) => saga_instance,
+            (saga_instance, Err(err)) => {
+                println!(
+                    "Failed to process saga (instance={:?}): {:?}",
+                    saga_instance, err
+                );
+                return self.process(saga_instance, saga_data, false);
             }
         };
+        self.save(&saga_instance)?;
+        Ok(saga_instance)
+    }
+
+    /// Leaves the saga waiting on its current step until an operator retries it
+    fn park(
+        &mut self,
+        mut saga_instance: SagaInstance,
+    ) -> Result<SagaInstance, diesel::result::Error> {
+        println!(
+            "Park saga (type={}, id={}, executing={})",
+            saga_instance.saga_type, saga_instance.saga_id, saga_instance.currently_executing,
+        );
+        saga_instance.step_deadline = None;
+        self.log_step(&saga_instance, SagaStepEvent::Parked, None)?;
+        self.save(&saga_instance)?;
+        Ok(saga_instance)
+    }
+
+    fn save(&mut self, saga_instance: &SagaInstance) -> Result<(), diesel::result::Error> {
         update(schema::saga_instances::table)
-            .set(&saga_instance)
+            .set(saga_instance)
             .filter(
                 schema::saga_instances::saga_type
                     .eq(&saga_instance.saga_type)
                     .and(schema::saga_instances::saga_id.eq(&saga_instance.saga_id)),
             )
             .execute(self.connection)?;
-        Ok(saga_instance)
+        Ok(())
     }
 
-    /// Should only called by process()
+    /// Should only called by resume()
     fn next(
         &mut self,
         mut saga_instance: SagaInstance,
         saga_data: &Data,
+        from: i32,
     ) -> (SagaInstance, Result<(), diesel::result::Error>) {
-        let steps = &self.saga.get_definition().steps;
+        let steps_len: i32 = self.saga.get_definition().steps.len().try_into().unwrap();
         let direction = if saga_instance.compensating { -1 } else { 1 };
-        let mut i = saga_instance.currently_executing + direction;
-        while i >= 0 && i < steps.len().try_into().unwrap() {
-            let idx: usize = i.try_into().unwrap();
-            let step = &steps[idx];
+        let mut i = from;
+        while i >= 0 && i < steps_len {
             saga_instance.currently_executing = i;
+            saga_instance.step_attempts = 0;
             println!(
                 "Next executing={}, compensating={}",
                 saga_instance.currently_executing, saga_instance.compensating,
             );
 
-            let func = if saga_instance.compensating {
-                &step.invoke_compensation
-            } else {
-                &step.invoke
-            };
-
-            if let Some(func) = func {
-                let saga_headers = HashMap::from([
-                    (
-                        SAGA_HEADER_TYPE.to_string(),
-                        saga_instance.saga_type.to_string(),
-                    ),
-                    (
-                        SAGA_HEADER_ID.to_string(),
-                        saga_instance.saga_id.to_string(),
-                    ),
-                ]);
-                let request_id = match func(saga_data, &saga_headers, self.connection) {
-                    Ok(request_id) => request_id,
-                    Err(err) => {
-                        return (saga_instance, Err(err));
-                    }
-                };
-                saga_instance.last_request_id = Some(request_id);
-                return (saga_instance, Ok(()));
-            } else {
-                i += direction;
-                continue;
+            match self.invoke_step(&mut saga_instance, saga_data, false) {
+                Ok(true) => return (saga_instance, Ok(())),
+                Ok(false) => {
+                    i += direction;
+                    continue;
+                }
+                Err(err) => return (saga_instance, Err(err)),
             }
         }
         saga_instance.end_state = true;
-        return (saga_instance, Ok(()));
+        saga_instance.step_deadline = None;
+        let result = self.log_step(&saga_instance, SagaStepEvent::Ended, None);
+        (saga_instance, result)
+    }
+
+    /// Sends the command of the current step and arms its deadline. With `resend` the
+    /// command keeps the request id of the previous attempt, unless a reply to it was
+    /// already handled. Returns false when the step has nothing to send in the current
+    /// direction.
+    fn invoke_step(
+        &mut self,
+        saga_instance: &mut SagaInstance,
+        saga_data: &Data,
+        resend: bool,
+    ) -> Result<bool, diesel::result::Error> {
+        let compensated_request_id = if saga_instance.compensating {
+            self.succeeded_request_id(saga_instance)?
+        } else {
+            None
+        };
+        let idx: usize = saga_instance.currently_executing.try_into().unwrap();
+        let step = &self.saga.get_definition().steps[idx];
+
+        let func = if saga_instance.compensating {
+            &step.invoke_compensation
+        } else {
+            &step.invoke
+        };
+        let applicable = step
+            .predicate
+            .as_ref()
+            .is_none_or(|predicate| predicate(saga_data));
+
+        if let (true, Some(func)) = (applicable, func) {
+            let mut saga_headers = HashMap::from([
+                (
+                    SAGA_HEADER_TYPE.to_string(),
+                    saga_instance.saga_type.to_string(),
+                ),
+                (
+                    SAGA_HEADER_ID.to_string(),
+                    saga_instance.saga_id.to_string(),
+                ),
+            ]);
+            if let (true, Some(request_id)) = (resend, &saga_instance.last_request_id) {
+                let replied = schema::saga_processed_replies::table
+                    .find(request_id)
+                    .count()
+                    .get_result::<i64>(self.connection)?
+                    > 0;
+                if !replied {
+                    saga_headers.insert(REQUEST_ID_HEADER.to_string(), request_id.clone());
+                }
+            }
+            if let Some(request_id) = compensated_request_id {
+                saga_headers.insert(COMPENSATED_REQUEST_ID_HEADER.to_string(), request_id);
+            }
+            let request_id = func(saga_data, &saga_headers, self.connection)?;
+            saga_instance.last_request_id = Some(request_id);
+            saga_instance.step_deadline = Some(Utc::now() + self.saga.step_timeout());
+            self.log_step(saga_instance, SagaStepEvent::Invoked, None)?;
+            Ok(true)
+        } else {
+            Ok(false)
+        }
+    }
+
+    /// Request id of the command whose success was replied for the current step
+    fn succeeded_request_id(
+        &mut self,
+        saga_instance: &SagaInstance,
+    ) -> Result<Option<String>, diesel::result::Error> {
+        let request_id = schema::saga_step_log::table
+            .select(schema::saga_step_log::request_id)
+            .filter(
+                schema::saga_step_log::saga_type
+                    .eq(&saga_instance.saga_type)
+                    .and(schema::saga_step_log::saga_id.eq(&saga_instance.saga_id))
+                    .and(schema::saga_step_log::step_index.eq(saga_instance.currently_executing))
+                    .and(schema::saga_step_log::compensating.eq(false))
+                    .and(schema::saga_step_log::event.eq(SagaStepEvent::Replied))
+                    .and(schema::saga_step_log::reply_succeed.eq(true)),
+            )
+            .order(schema::saga_step_log::id.desc())
+            .first::<Option<String>>(self.connection)
+            .optional()?;
+        Ok(request_id.flatten())
+    }
+
+    /// Appends an entry to `saga_step_log` for the current step of the instance
+    fn log_step(
+        &mut self,
+        saga_instance: &SagaInstance,
+        event: SagaStepEvent,
+        reply: Option<&CommandReply>,
+    ) -> Result<(), diesel::result::Error> {
+        insert_into(schema::saga_step_log::table)
+            .values(NewSagaStepLog {
+                saga_type: saga_instance.saga_type.clone(),
+                saga_id: saga_instance.saga_id.clone(),
+                step_index: saga_instance.currently_executing,
+                compensating: saga_instance.compensating,
+                event,
+                request_id: saga_instance.last_request_id.clone(),
+                reply_succeed: reply.map(|reply| reply.succeed),
+                reply_body: reply.and_then(|reply| reply.body.clone()),
+            })
+            .execute(self.connection)?;
+        Ok(())
+    }
+}
+
+/// Returns the step log of a saga instance in the order it was written
+pub fn load_history(
+    conn: &mut PgConnection,
+    saga_type: &str,
+    saga_id: &str,
+) -> Result<Vec<SagaStepLog>, diesel::result::Error> {
+    schema::saga_step_log::table
+        .select(SagaStepLog::as_select())
+        .filter(
+            schema::saga_step_log::saga_type
+                .eq(saga_type)
+                .and(schema::saga_step_log::saga_id.eq(saga_id)),
+        )
+        .order(schema::saga_step_log::id.asc())
+        .get_results(conn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{establish_connection, proxy::order_service::OrderServiceProxy};
+    use serde::Deserialize;
+
+    const TEST_SAGA_TYPE: &str = "test-saga";
+
+    #[derive(Debug, Serialize, Deserialize)]
+    struct TestSagaState {
+        order_id: Uuid,
+    }
+
+    struct TestSaga {
+        saga_definition: SagaDefition<'static, TestSagaState>,
+    }
+
+    impl TestSaga {
+        fn new() -> Self {
+            Self {
+                saga_definition: step()
+                    .invoke_participant(|saga_state: &TestSagaState, headers, conn| {
+                        OrderServiceProxy::new(conn).approve_order(&saga_state.order_id, headers)
+                    })
+                    .with_compensation(|saga_state: &TestSagaState, headers, conn| {
+                        OrderServiceProxy::new(conn).reject_order(
+                            &saga_state.order_id,
+                            &None,
+                            headers,
+                        )
+                    })
+                    .step()
+                    .invoke_participant(|saga_state: &TestSagaState, headers, conn| {
+                        OrderServiceProxy::new(conn).approve_order(&saga_state.order_id, headers)
+                    })
+                    .step()
+                    .invoke_participant(|saga_state: &TestSagaState, headers, conn| {
+                        OrderServiceProxy::new(conn).approve_order(&saga_state.order_id, headers)
+                    })
+                    .pivot()
+                    .step()
+                    .invoke_participant(|saga_state: &TestSagaState, headers, conn| {
+                        OrderServiceProxy::new(conn).approve_order(&saga_state.order_id, headers)
+                    })
+                    .build(),
+            }
+        }
+    }
+
+    impl Saga<TestSagaState> for TestSaga {
+        fn r#type(&self) -> &'static str {
+            TEST_SAGA_TYPE
+        }
+
+        fn get_definition(&self) -> &SagaDefition<'static, TestSagaState> {
+            &self.saga_definition
+        }
+    }
+
+    // Every test runs in a transaction that is never committed
+    fn setup_connection() -> PgConnection {
+        let mut conn = establish_connection();
+        conn.begin_test_transaction().unwrap();
+        conn
+    }
+
+    fn create_saga(conn: &mut PgConnection) -> SagaInstance {
+        SagaManager::new(TestSaga::new(), conn)
+            .create(TestSagaState {
+                order_id: Uuid::new_v4(),
+            })
+            .unwrap()
+    }
+
+    fn reload(conn: &mut PgConnection, saga_instance: &SagaInstance) -> SagaInstance {
+        schema::saga_instances::table
+            .select(SagaInstance::as_select())
+            .find((&saga_instance.saga_type, &saga_instance.saga_id))
+            .get_result(conn)
+            .unwrap()
+    }
+
+    fn reply(saga_instance: &SagaInstance, request_id: &str, succeed: bool) -> CommandReply {
+        CommandReply {
+            state: HashMap::from([
+                (
+                    SAGA_HEADER_TYPE.to_string(),
+                    saga_instance.saga_type.to_string(),
+                ),
+                (
+                    SAGA_HEADER_ID.to_string(),
+                    saga_instance.saga_id.to_string(),
+                ),
+                (REQUEST_ID_HEADER.to_string(), request_id.to_string()),
+            ]),
+            succeed,
+            body: None,
+        }
+    }
+
+    fn handle_reply(conn: &mut PgConnection, message: &CommandReply) {
+        SagaManager::new(TestSaga::new(), conn)
+            .handle_reply(message)
+            .unwrap();
+    }
+
+    fn handle_timeout(conn: &mut PgConnection, saga_instance: SagaInstance) -> SagaInstance {
+        SagaManager::new(TestSaga::new(), conn)
+            .handle_timeout(saga_instance, 0)
+            .unwrap()
+    }
+
+    fn last_event(conn: &mut PgConnection, saga_instance: &SagaInstance) -> SagaStepEvent {
+        load_history(conn, &saga_instance.saga_type, &saga_instance.saga_id)
+            .unwrap()
+            .last()
+            .unwrap()
+            .event
+    }
+
+    fn count_replied(conn: &mut PgConnection, saga_instance: &SagaInstance) -> usize {
+        load_history(conn, &saga_instance.saga_type, &saga_instance.saga_id)
+            .unwrap()
+            .into_iter()
+            .filter(|entry| entry.event == SagaStepEvent::Replied)
+            .count()
+    }
+
+    #[test]
+    fn test_duplicated_reply_is_applied_once() {
+        let conn = &mut setup_connection();
+        let saga_instance = create_saga(conn);
+        let first_request_id = saga_instance.last_request_id.clone().unwrap();
+
+        let message = reply(&saga_instance, &first_request_id, true);
+        handle_reply(conn, &message);
+        let advanced = reload(conn, &saga_instance);
+        assert_eq!(advanced.currently_executing, 1);
+        assert_ne!(advanced.last_request_id, Some(first_request_id));
+
+        handle_reply(conn, &message);
+        assert_eq!(reload(conn, &saga_instance), advanced);
+        assert_eq!(count_replied(conn, &saga_instance), 1);
+    }
+
+    #[test]
+    fn test_processed_request_id_is_not_applied_again() {
+        let conn = &mut setup_connection();
+        let saga_instance = create_saga(conn);
+        let first_request_id = saga_instance.last_request_id.clone().unwrap();
+
+        let message = reply(&saga_instance, &first_request_id, true);
+        handle_reply(conn, &message);
+
+        // Even if the saga were waiting on the same request id again, the reply is not replayed
+        let mut rewound = reload(conn, &saga_instance);
+        rewound.currently_executing = 0;
+        rewound.last_request_id = Some(first_request_id);
+        SagaManager::new(TestSaga::new(), conn)
+            .save(&rewound)
+            .unwrap();
+
+        handle_reply(conn, &message);
+        assert_eq!(reload(conn, &saga_instance), rewound);
+        assert_eq!(count_replied(conn, &saga_instance), 1);
+    }
+
+    #[test]
+    fn test_out_of_order_reply_is_ignored() {
+        let conn = &mut setup_connection();
+        let saga_instance = create_saga(conn);
+        let first_request_id = saga_instance.last_request_id.clone().unwrap();
+
+        // The step timed out and was re-sent under the same request id
+        let retried = SagaManager::new(TestSaga::new(), conn)
+            .handle_timeout(saga_instance, 3)
+            .unwrap();
+        assert_eq!(retried.step_attempts, 1);
+        assert_eq!(retried.last_request_id, Some(first_request_id.clone()));
+
+        handle_reply(conn, &reply(&retried, &first_request_id, true));
+        let advanced = reload(conn, &retried);
+        assert_eq!(advanced.currently_executing, 1);
+        assert!(!advanced.compensating);
+
+        // A late failure for the first step must neither advance nor compensate the saga
+        handle_reply(conn, &reply(&retried, &first_request_id, false));
+        assert_eq!(reload(conn, &retried), advanced);
+        assert_eq!(count_replied(conn, &retried), 1);
+    }
+
+    fn count_compensations(conn: &mut PgConnection, saga_instance: &SagaInstance) -> usize {
+        load_history(conn, &saga_instance.saga_type, &saga_instance.saga_id)
+            .unwrap()
+            .into_iter()
+            .filter(|entry| entry.compensating && entry.event == SagaStepEvent::Invoked)
+            .count()
+    }
+
+    #[test]
+    fn test_timed_out_step_is_parked() {
+        let conn = &mut setup_connection();
+        let saga_instance = create_saga(conn);
+        let first_request_id = saga_instance.last_request_id.clone().unwrap();
+
+        // The step may still be applied late, so it is not compensated
+        let parked = handle_timeout(conn, saga_instance);
+        assert_eq!(parked.currently_executing, 0);
+        assert!(!parked.compensating);
+        assert_eq!(parked.step_deadline, None);
+        assert_eq!(parked.last_request_id, Some(first_request_id));
+        assert_eq!(last_event(conn, &parked), SagaStepEvent::Parked);
+        assert_eq!(count_compensations(conn, &parked), 0);
+    }
+
+    #[test]
+    fn test_late_failure_of_timed_out_step_is_not_compensated() {
+        let conn = &mut setup_connection();
+        let saga_instance = create_saga(conn);
+        let first_request_id = saga_instance.last_request_id.clone().unwrap();
+        let parked = handle_timeout(conn, saga_instance);
+
+        // The command of the step failed, but its reply only arrives after the timeout
+        handle_reply(conn, &reply(&parked, &first_request_id, false));
+        let ended = reload(conn, &parked);
+        assert!(ended.end_state);
+        assert!(ended.compensating);
+        assert!(!ended.failed);
+        assert_eq!(count_compensations(conn, &ended), 0);
+    }
+
+    #[test]
+    fn test_timed_out_step_without_compensation_is_abandoned() {
+        let conn = &mut setup_connection();
+        let saga_instance = create_saga(conn);
+        let first_request_id = saga_instance.last_request_id.clone().unwrap();
+        handle_reply(conn, &reply(&saga_instance, &first_request_id, true));
+
+        // Nothing to undo for the timed-out step, so only the step before it is compensated
+        let waiting = reload(conn, &saga_instance);
+        let compensating = handle_timeout(conn, waiting);
+        assert!(compensating.compensating);
+        assert_eq!(compensating.currently_executing, 0);
+        assert_eq!(count_compensations(conn, &compensating), 1);
+
+        // The compensation refers to the command it undoes
+        let compensated_request_id = SagaManager::new(TestSaga::new(), conn)
+            .succeeded_request_id(&compensating)
+            .unwrap();
+        assert_eq!(compensated_request_id, Some(first_request_id));
+    }
+
+    #[test]
+    fn test_timed_out_pivot_is_parked() {
+        let conn = &mut setup_connection();
+        let saga_instance = create_saga(conn);
+        for _ in 0..2 {
+            let waiting = reload(conn, &saga_instance);
+            let request_id = waiting.last_request_id.clone().unwrap();
+            handle_reply(conn, &reply(&waiting, &request_id, true));
+        }
+
+        let waiting = reload(conn, &saga_instance);
+        let parked = handle_timeout(conn, waiting);
+        assert_eq!(parked.currently_executing, 2);
+        assert!(!parked.compensating);
+        assert!(!parked.end_state);
+        assert_eq!(parked.step_deadline, None);
+        assert_eq!(last_event(conn, &parked), SagaStepEvent::Parked);
+
+        // An operator retry re-sends the pivot under its request id
+        let pivot_request_id = parked.last_request_id.clone();
+        let retried = SagaManager::new(TestSaga::new(), conn)
+            .retry_step(parked)
+            .unwrap();
+        assert_eq!(retried.last_request_id, pivot_request_id);
+        assert!(retried.step_deadline.is_some());
+    }
+
+    #[test]
+    fn test_failure_past_pivot_is_parked() {
+        let conn = &mut setup_connection();
+        let saga_instance = create_saga(conn);
+        for _ in 0..3 {
+            let waiting = reload(conn, &saga_instance);
+            let request_id = waiting.last_request_id.clone().unwrap();
+            handle_reply(conn, &reply(&waiting, &request_id, true));
+        }
+
+        let waiting = reload(conn, &saga_instance);
+        assert_eq!(waiting.currently_executing, 3);
+        let request_id = waiting.last_request_id.clone().unwrap();
+        handle_reply(conn, &reply(&waiting, &request_id, false));
+
+        let parked = reload(conn, &saga_instance);
+        assert_eq!(parked.currently_executing, 3);
+        assert!(!parked.compensating);
+        assert!(!parked.end_state);
+        assert_eq!(last_event(conn, &parked), SagaStepEvent::Parked);
+        assert!(!SagaManager::new(TestSaga::new(), conn)
+            .saga
+            .get_definition()
+            .can_abandon(parked.currently_executing));
+    }
+
+    #[test]
+    fn test_reply_with_unknown_request_id_is_ignored() {
+        let conn = &mut setup_connection();
+        let saga_instance = create_saga(conn);
+        let waiting = reload(conn, &saga_instance);
+
+        handle_reply(
+            conn,
+            &reply(&saga_instance, &Uuid::new_v4().to_string(), true),
+        );
+        assert_eq!(reload(conn, &saga_instance), waiting);
+        assert_eq!(count_replied(conn, &saga_instance), 0);
     }
 }
@@ -0,0 +1,176 @@
+use std::{collections::HashMap, marker::PhantomData};
+
+use diesel::{insert_into, prelude::*, PgConnection};
+use ftgo_proto::common::CommandReply;
+use prost::Message;
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{
+    models::{NewSagaDeadLetter, SagaInstance},
+    schema,
+};
+
+use super::{Saga, SagaManager, REQUEST_ID_HEADER, SAGA_HEADER_ID, SAGA_HEADER_TYPE};
+
+/// Manager operations of a saga type, with its data type erased so that
+/// different sagas can be stored side by side in a [`SagaRegistry`].
+pub trait RegisteredSaga {
+    fn handle_reply(
+        &self,
+        conn: &mut PgConnection,
+        message: &CommandReply,
+    ) -> Result<(), diesel::result::Error>;
+
+    fn handle_timeout(
+        &self,
+        conn: &mut PgConnection,
+        saga_instance: SagaInstance,
+        max_retries: i32,
+    ) -> Result<SagaInstance, diesel::result::Error>;
+
+    fn retry_step(
+        &self,
+        conn: &mut PgConnection,
+        saga_instance: SagaInstance,
+    ) -> Result<SagaInstance, diesel::result::Error>;
+
+    fn compensate(
+        &self,
+        conn: &mut PgConnection,
+        saga_instance: SagaInstance,
+    ) -> Result<SagaInstance, diesel::result::Error>;
+
+    /// Whether the current step of the instance may still be abandoned
+    fn can_compensate(&self, saga_instance: &SagaInstance) -> bool;
+}
+
+struct SagaFactory<S, Data> {
+    new_saga: fn() -> S,
+    _data: PhantomData<fn() -> Data>,
+}
+
+impl<S, Data> RegisteredSaga for SagaFactory<S, Data>
+where
+    S: Saga<Data> + 'static,
+    Data: Serialize + DeserializeOwned,
+{
+    fn handle_reply(
+        &self,
+        conn: &mut PgConnection,
+        message: &CommandReply,
+    ) -> Result<(), diesel::result::Error> {
+        SagaManager::new((self.new_saga)(), conn).handle_reply(message)
+    }
+
+    fn handle_timeout(
+        &self,
+        conn: &mut PgConnection,
+        saga_instance: SagaInstance,
+        max_retries: i32,
+    ) -> Result<SagaInstance, diesel::result::Error> {
+        SagaManager::new((self.new_saga)(), conn).handle_timeout(saga_instance, max_retries)
+    }
+
+    fn retry_step(
+        &self,
+        conn: &mut PgConnection,
+        saga_instance: SagaInstance,
+    ) -> Result<SagaInstance, diesel::result::Error> {
+        SagaManager::new((self.new_saga)(), conn).retry_step(saga_instance)
+    }
+
+    fn compensate(
+        &self,
+        conn: &mut PgConnection,
+        saga_instance: SagaInstance,
+    ) -> Result<SagaInstance, diesel::result::Error> {
+        SagaManager::new((self.new_saga)(), conn).compensate(saga_instance)
+    }
+
+    fn can_compensate(&self, saga_instance: &SagaInstance) -> bool {
+        (self.new_saga)()
+            .get_definition()
+            .can_abandon(saga_instance.currently_executing)
+    }
+}
+
+/// Sagas keyed by `Saga::type()`
+#[derive(Default)]
+pub struct SagaRegistry {
+    sagas: HashMap<&'static str, Box<dyn RegisteredSaga>>,
+}
+
+impl SagaRegistry {
+    pub fn register<S, Data>(mut self, new_saga: fn() -> S) -> Self
+    where
+        S: Saga<Data> + 'static,
+        Data: Serialize + DeserializeOwned + 'static,
+    {
+        let saga_type = new_saga().r#type();
+        let previous = self.sagas.insert(
+            saga_type,
+            Box::new(SagaFactory {
+                new_saga,
+                _data: PhantomData,
+            }),
+        );
+        assert!(
+            previous.is_none(),
+            "Saga type `{}` is registered twice",
+            saga_type
+        );
+        self
+    }
+
+    pub fn get(&self, saga_type: &str) -> Option<&dyn RegisteredSaga> {
+        self.sagas.get(saga_type).map(|saga| saga.as_ref())
+    }
+
+    /// Routes the reply to the saga named by its `SAGA-TYPE` header. Replies that
+    /// no registered saga can take are parked in `saga_dead_letters`.
+    pub fn handle_reply(
+        &self,
+        conn: &mut PgConnection,
+        message: &CommandReply,
+    ) -> Result<(), diesel::result::Error> {
+        let saga_type = message.state.get(SAGA_HEADER_TYPE);
+        let saga = match (saga_type, message.state.get(SAGA_HEADER_ID)) {
+            (Some(t), Some(_)) => self
+                .get(t)
+                .ok_or_else(|| format!("Unknown saga type `{}`", t)),
+            (None, _) => Err(format!("Missing `{}` header", SAGA_HEADER_TYPE)),
+            (_, None) => Err(format!("Missing `{}` header", SAGA_HEADER_ID)),
+        };
+        let saga = match saga {
+            Ok(saga) => saga,
+            Err(reason) => {
+                eprintln!("Park saga reply in dead letters: {}", reason);
+                return Self::park(conn, message, reason);
+            }
+        };
+
+        match saga.handle_reply(conn, message) {
+            Err(diesel::result::Error::NotFound) => {
+                Self::park(conn, message, "Saga instance not found".to_string())
+            }
+            result => result,
+        }
+    }
+
+    fn park(
+        conn: &mut PgConnection,
+        message: &CommandReply,
+        reason: String,
+    ) -> Result<(), diesel::result::Error> {
+        insert_into(schema::saga_dead_letters::table)
+            .values(NewSagaDeadLetter {
+                saga_type: message.state.get(SAGA_HEADER_TYPE).cloned(),
+                saga_id: message.state.get(SAGA_HEADER_ID).cloned(),
+                request_id: message.state.get(REQUEST_ID_HEADER).cloned(),
+                reason,
+                reply: message.encode_to_vec(),
+            })
+            .execute(conn)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,172 @@
+use crate::{
+    models::OrderLineItem,
+    proxy::{
+        accounting_service::AccountingServiceProxy, kitchen_service::KitchenServiceProxy,
+        order_service::OrderServiceProxy,
+    },
+};
+use bigdecimal::BigDecimal;
+use chrono::{DateTime, Utc};
+use ftgo_proto::kitchen_service::TicketDetails;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use super::{step, Saga, SagaDefition};
+
+pub const SAGA_TYPE: &str = "revise-order";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ReviseOrderSagaState {
+    pub order_id: Uuid,
+    pub restaurant_id: Uuid,
+    pub consumer_id: Uuid,
+    pub current_order_total: BigDecimal,
+    pub new_order_total: BigDecimal,
+    /// Line items of the order after the revision is applied
+    pub line_items: Vec<OrderLineItem>,
+    pub delivery_time: DateTime<Utc>,
+}
+
+impl ReviseOrderSagaState {
+    pub fn new(
+        order_id: &Uuid,
+        restaurant_id: &Uuid,
+        consumer_id: &Uuid,
+        current_order_total: &BigDecimal,
+        new_order_total: &BigDecimal,
+        line_items: &[OrderLineItem],
+        delivery_time: &DateTime<Utc>,
+    ) -> Self {
+        Self {
+            order_id: *order_id,
+            restaurant_id: *restaurant_id,
+            consumer_id: *consumer_id,
+            current_order_total: current_order_total.clone(),
+            new_order_total: new_order_total.clone(),
+            line_items: line_items.to_vec(),
+            delivery_time: *delivery_time,
+        }
+    }
+}
+
+/// Revises an approved order whose ticket is not yet accepted. The order is moved to
+/// `REVISION_PENDING` before the saga is created, so the first step only carries the
+/// compensation that restores it.
+pub struct ReviseOrderSaga<'a> {
+    pub saga_definition: SagaDefition<'a, ReviseOrderSagaState>,
+}
+
+impl<'a> ReviseOrderSaga<'a> {
+    pub fn new() -> Self {
+        Self {
+            saga_definition: step()
+                .with_compensation(|saga_state: &ReviseOrderSagaState, headers, conn| {
+                    OrderServiceProxy::new(conn)
+                        .undo_begin_revise_order(&saga_state.order_id, headers)
+                })
+                .step()
+                .invoke_participant(|saga_state: &ReviseOrderSagaState, headers, conn| {
+                    KitchenServiceProxy::new(conn).begin_revise_ticket(
+                        &saga_state.order_id,
+                        &saga_state.restaurant_id,
+                        headers,
+                    )
+                })
+                .with_compensation(|saga_state: &ReviseOrderSagaState, headers, conn| {
+                    KitchenServiceProxy::new(conn).undo_begin_revise_ticket(
+                        &saga_state.order_id,
+                        &saga_state.restaurant_id,
+                        headers,
+                    )
+                })
+                .step()
+                .invoke_participant_if(
+                    |saga_state: &ReviseOrderSagaState| {
+                        saga_state.new_order_total > saga_state.current_order_total
+                    },
+                    |saga_state: &ReviseOrderSagaState, headers, conn| {
+                        AccountingServiceProxy::new(conn).withdraw(
+                            &saga_state.consumer_id,
+                            &saga_state.order_id,
+                            &saga_state.restaurant_id,
+                            &(&saga_state.new_order_total - &saga_state.current_order_total),
+                            headers,
+                        )
+                    },
+                )
+                // Sent with the request id of the withdrawal, so accounting only refunds
+                // what it actually charged
+                .with_compensation(|saga_state: &ReviseOrderSagaState, headers, conn| {
+                    AccountingServiceProxy::new(conn).deposit(
+                        &saga_state.consumer_id,
+                        &saga_state.order_id,
+                        &saga_state.restaurant_id,
+                        &(&saga_state.new_order_total - &saga_state.current_order_total),
+                        headers,
+                    )
+                })
+                .step()
+                .invoke_participant_if(
+                    |saga_state: &ReviseOrderSagaState| {
+                        saga_state.new_order_total < saga_state.current_order_total
+                    },
+                    |saga_state: &ReviseOrderSagaState, headers, conn| {
+                        AccountingServiceProxy::new(conn).deposit(
+                            &saga_state.consumer_id,
+                            &saga_state.order_id,
+                            &saga_state.restaurant_id,
+                            &(&saga_state.current_order_total - &saga_state.new_order_total),
+                            headers,
+                        )
+                    },
+                )
+                .with_compensation(|saga_state: &ReviseOrderSagaState, headers, conn| {
+                    AccountingServiceProxy::new(conn).withdraw(
+                        &saga_state.consumer_id,
+                        &saga_state.order_id,
+                        &saga_state.restaurant_id,
+                        &(&saga_state.current_order_total - &saga_state.new_order_total),
+                        headers,
+                    )
+                })
+                .step()
+                .invoke_participant(|saga_state: &ReviseOrderSagaState, headers, conn| {
+                    KitchenServiceProxy::new(conn).confirm_revise_ticket(
+                        &saga_state.order_id,
+                        &TicketDetails {
+                            line_items: saga_state.line_items.iter().map(|li| li.into()).collect(),
+                        },
+                        &saga_state.restaurant_id,
+                        headers,
+                    )
+                })
+                .pivot()
+                .step()
+                .invoke_participant(|saga_state: &ReviseOrderSagaState, headers, conn| {
+                    OrderServiceProxy::new(conn).confirm_revise_order(
+                        &saga_state.order_id,
+                        &saga_state.line_items,
+                        &saga_state.delivery_time,
+                        headers,
+                    )
+                })
+                .build(),
+        }
+    }
+}
+
+impl<'a> Default for ReviseOrderSaga<'a> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<'a> Saga<ReviseOrderSagaState> for ReviseOrderSaga<'a> {
+    fn r#type(&self) -> &'static str {
+        SAGA_TYPE
+    }
+
+    fn get_definition(&self) -> &SagaDefition<'a, ReviseOrderSagaState> {
+        &self.saga_definition
+    }
+}
@@ -4,6 +4,10 @@ pub mod sql_types {
     #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
     #[diesel(postgres_type(name = "order_state"))]
     pub struct OrderState;
+
+    #[derive(diesel::query_builder::QueryId, Clone, diesel::sql_types::SqlType)]
+    #[diesel(postgres_type(name = "saga_step_event"))]
+    pub struct SagaStepEvent;
 }
 
 diesel::table! {
@@ -31,6 +35,7 @@ diesel::table! {
         delivery_address -> Text,
         payment_token -> Nullable<Text>,
         created_at -> Timestamptz,
+        ticket_rejection_reason -> Nullable<Text>,
     }
 }
 
@@ -50,6 +55,7 @@ diesel::table! {
         id -> Text,
         name -> Text,
         price -> Numeric,
+        available -> Bool,
     }
 }
 
@@ -70,6 +76,47 @@ diesel::table! {
         compensating -> Bool,
         failed -> Bool,
         saga_data_json -> Jsonb,
+        step_deadline -> Nullable<Timestamptz>,
+        step_attempts -> Int4,
+    }
+}
+
+diesel::table! {
+    saga_dead_letters (id) {
+        id -> Int8,
+        saga_type -> Nullable<Text>,
+        saga_id -> Nullable<Text>,
+        request_id -> Nullable<Text>,
+        reason -> Text,
+        reply -> Bytea,
+        created_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    saga_processed_replies (request_id) {
+        request_id -> Text,
+        saga_type -> Text,
+        saga_id -> Text,
+        processed_at -> Timestamptz,
+    }
+}
+
+diesel::table! {
+    use diesel::sql_types::*;
+    use super::sql_types::SagaStepEvent;
+
+    saga_step_log (id) {
+        id -> Int8,
+        saga_type -> Text,
+        saga_id -> Text,
+        step_index -> Int4,
+        compensating -> Bool,
+        event -> SagaStepEvent,
+        request_id -> Nullable<Text>,
+        reply_succeed -> Nullable<Bool>,
+        reply_body -> Nullable<Bytea>,
+        created_at -> Timestamptz,
     }
 }
 
@@ -83,5 +130,8 @@ diesel::allow_tables_to_appear_in_same_query!(
     outbox,
     restaurant_menu_items,
     restaurants,
+    saga_dead_letters,
     saga_instances,
+    saga_processed_replies,
+    saga_step_log,
 );
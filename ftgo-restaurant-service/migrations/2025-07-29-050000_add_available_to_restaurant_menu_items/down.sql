@@ -0,0 +1 @@
+ALTER TABLE restaurant_menu_items DROP COLUMN available;
@@ -12,7 +12,7 @@ use ftgo_proto::restaurant_service::restaurant_service_server::{
 };
 use ftgo_proto::restaurant_service::{
     CreateRestaurantPayload, CreateRestaurantResponse, GetRestaurantPayload, GetRestaurantResponse,
-    ListRestaurantsResponse, MenuItem, Restaurant,
+    ListRestaurantsResponse, MenuItem, Restaurant, SetMenuItemAvailabilityPayload,
 };
 
 use ftgo_restaurant_service::{establish_connection, models, schema};
@@ -51,6 +51,7 @@ impl RestaurantService for RestaurantServiceImpl {
                         .amount
                         .parse::<BigDecimal>()
                         .map_err(|_| Status::invalid_argument("Invalid price"))?,
+                    available: i.available.unwrap_or(true),
                 })
             })
             .collect::<Result<Vec<_>, Status>>()?;
@@ -111,6 +112,7 @@ impl RestaurantService for RestaurantServiceImpl {
                         price: Some(Money {
                             amount: i.price.to_string(),
                         }),
+                        available: Some(i.available),
                     })
                     .collect(),
             }),
@@ -151,12 +153,54 @@ impl RestaurantService for RestaurantServiceImpl {
                             price: Some(Money {
                                 amount: i.price.to_string(),
                             }),
+                            available: Some(i.available),
                         })
                         .collect(),
                 })
                 .collect(),
         }))
     }
+
+    async fn set_menu_item_availability(
+        &self,
+        request: Request<SetMenuItemAvailabilityPayload>,
+    ) -> Result<Response<MenuItem>, Status> {
+        use schema::restaurant_menu_items::dsl::*;
+
+        let payload = request.into_inner();
+        let rid = payload
+            .restaurant_id
+            .parse::<Uuid>()
+            .map_err(|_| Status::invalid_argument("Invalid restaurant id"))?;
+
+        let conn = &mut establish_connection();
+        let menu_item = conn
+            .transaction(|conn| {
+                let menu_item =
+                    diesel::update(restaurant_menu_items.find((rid, &payload.menu_item_id)))
+                        .set(available.eq(payload.available))
+                        .returning(models::RestaurantMenuItem::as_returning())
+                        .get_result(conn)?;
+
+                let mut publisher = RestaurantEventPublisher::new(conn);
+                publisher.menu_item_availability_changed(&menu_item);
+
+                Ok(menu_item)
+            })
+            .map_err(|err| match err {
+                diesel::result::Error::NotFound => Status::not_found("Menu item not found"),
+                _ => Status::internal("Failed to update menu item"),
+            })?;
+
+        Ok(Response::new(MenuItem {
+            id: menu_item.id,
+            name: menu_item.name,
+            price: Some(Money {
+                amount: menu_item.price.to_string(),
+            }),
+            available: Some(menu_item.available),
+        }))
+    }
 }
 
 pub async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -188,7 +232,9 @@ mod tests {
     use super::*;
     use diesel::RunQueryDsl;
     use ftgo_proto::common::Money;
-    use ftgo_proto::restaurant_service::{CreateRestaurantPayload, GetRestaurantPayload, MenuItem};
+    use ftgo_proto::restaurant_service::{
+        CreateRestaurantPayload, GetRestaurantPayload, MenuItem, SetMenuItemAvailabilityPayload,
+    };
     use tonic::Request;
     use uuid::Uuid;
 
@@ -218,6 +264,7 @@ mod tests {
                     price: Some(Money {
                         amount: "10.00".to_string(),
                     }),
+                    available: None,
                 },
                 MenuItem {
                     id: "item2".to_string(),
@@ -225,6 +272,7 @@ mod tests {
                     price: Some(Money {
                         amount: "20.00".to_string(),
                     }),
+                    available: None,
                 },
             ],
         };
@@ -274,6 +322,7 @@ mod tests {
                 price: Some(Money {
                     amount: "invalid".to_string(),
                 }),
+                available: None,
             }],
         };
         let request = Request::new(payload);
@@ -303,12 +352,14 @@ mod tests {
                 id: "item1".to_string(),
                 name: "Item 1".to_string(),
                 price: BigDecimal::parse_bytes(b"10.00", 10).unwrap(),
+                available: true,
             },
             models::RestaurantMenuItem {
                 restaurant_id: restaurant_id,
                 id: "item2".to_string(),
                 name: "Item 2".to_string(),
                 price: BigDecimal::parse_bytes(b"20.00", 10).unwrap(),
+                available: true,
             },
         ];
 
@@ -371,12 +422,14 @@ mod tests {
             id: "item1".to_string(),
             name: "Item 1".to_string(),
             price: BigDecimal::parse_bytes(b"10.00", 10).unwrap(),
+            available: true,
         }];
         let menu_items2 = vec![models::RestaurantMenuItem {
             restaurant_id: restaurant_id2,
             id: "item3".to_string(),
             name: "Item 3".to_string(),
             price: BigDecimal::parse_bytes(b"30.00", 10).unwrap(),
+            available: true,
         }];
 
         let conn = &mut establish_connection();
@@ -406,4 +459,61 @@ mod tests {
             .iter()
             .any(|r| r.menu_items.len() == 1 && r.menu_items[0].name == "Item 3"));
     }
+
+    #[tokio::test]
+    async fn test_set_menu_item_availability() {
+        setup_database();
+        let service = RestaurantServiceImpl::default();
+        let restaurant_id = Uuid::new_v4();
+
+        let conn = &mut establish_connection();
+        diesel::insert_into(schema::restaurants::table)
+            .values(&models::Restaurant {
+                id: restaurant_id,
+                name: "Test Restaurant".to_string(),
+                address: "Test Address".to_string(),
+            })
+            .execute(conn)
+            .unwrap();
+        diesel::insert_into(schema::restaurant_menu_items::table)
+            .values(&models::RestaurantMenuItem {
+                restaurant_id,
+                id: "item1".to_string(),
+                name: "Item 1".to_string(),
+                price: BigDecimal::parse_bytes(b"10.00", 10).unwrap(),
+                available: true,
+            })
+            .execute(conn)
+            .unwrap();
+
+        let request = Request::new(SetMenuItemAvailabilityPayload {
+            restaurant_id: restaurant_id.to_string(),
+            menu_item_id: "item1".to_string(),
+            available: false,
+        });
+        let response = service.set_menu_item_availability(request).await.unwrap();
+        assert_eq!(response.into_inner().available, Some(false));
+
+        let menu_item = schema::restaurant_menu_items::table
+            .find((restaurant_id, "item1"))
+            .first::<models::RestaurantMenuItem>(conn)
+            .unwrap();
+        assert!(!menu_item.available);
+    }
+
+    #[tokio::test]
+    async fn test_set_menu_item_availability_not_found() {
+        setup_database();
+        let service = RestaurantServiceImpl::default();
+
+        let request = Request::new(SetMenuItemAvailabilityPayload {
+            restaurant_id: Uuid::new_v4().to_string(),
+            menu_item_id: "item1".to_string(),
+            available: false,
+        });
+        let response = service.set_menu_item_availability(request).await;
+
+        assert!(response.is_err());
+        assert_eq!(response.unwrap_err().code(), tonic::Code::NotFound);
+    }
 }
@@ -3,7 +3,8 @@ use crate::{models, models::NewOutbox, EVENT_CHANNEL};
 use diesel::{prelude::*, PgConnection};
 use ftgo_proto::common::Money;
 use ftgo_proto::restaurant_service::{
-    restaurant_event, MenuItem, RestaurantCreatedEvent, RestaurantEvent,
+    restaurant_event, MenuItem, MenuItemAvailabilityChangedEvent, RestaurantCreatedEvent,
+    RestaurantEvent,
 };
 use prost::Message;
 
@@ -35,18 +36,36 @@ impl<'a> RestaurantEventPublisher<'a> {
                             price: Some(Money {
                                 amount: i.price.to_string(),
                             }),
+                            available: Some(i.available),
                         })
                         .collect(),
                 },
             )),
         };
+        self.publish(event, &restaurant.id.to_string());
+    }
+
+    pub fn menu_item_availability_changed(&mut self, menu_item: &models::RestaurantMenuItem) {
+        let event = RestaurantEvent {
+            event: Some(restaurant_event::Event::MenuItemAvailabilityChanged(
+                MenuItemAvailabilityChangedEvent {
+                    id: menu_item.restaurant_id.to_string(),
+                    menu_item_id: menu_item.id.clone(),
+                    available: menu_item.available,
+                },
+            )),
+        };
+        self.publish(event, &menu_item.restaurant_id.to_string());
+    }
+
+    fn publish(&mut self, event: RestaurantEvent, key: &str) {
         let mut buf = Vec::new();
         event.encode(&mut buf).unwrap();
 
         let _ = diesel::insert_into(schema::outbox::table)
             .values(NewOutbox {
                 topic: EVENT_CHANNEL.to_string(),
-                key: restaurant.id.to_string(),
+                key: key.to_string(),
                 value: buf,
             })
             .execute(self.conn);
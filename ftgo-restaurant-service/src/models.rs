@@ -20,6 +20,7 @@ pub struct RestaurantMenuItem {
     pub id: String,
     pub name: String,
     pub price: BigDecimal,
+    pub available: bool,
 }
 
 #[derive(Queryable, Selectable, Debug, PartialEq)]
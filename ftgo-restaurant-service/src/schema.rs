@@ -16,6 +16,7 @@ diesel::table! {
         id -> Text,
         name -> Text,
         price -> Numeric,
+        available -> Bool,
     }
 }
 